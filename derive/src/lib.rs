@@ -0,0 +1,145 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Expr, Fields, Index, Member};
+
+/// implement `tbo2::Device` for a struct from the offsets of its fields.
+///
+/// fields marked `#[device(offset = ..)]` are mapped at that offset and must implement
+/// `tbo2::devices::Field`. `read_only` and `write_only` restrict access to a field.
+/// reset clears every mapped field, unmarked fields are left alone.
+#[proc_macro_derive(Device, attributes(device))]
+pub fn derive_device(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct Mapped {
+    member: Member,
+    ty: syn::Type,
+    offset: Expr,
+    readable: bool,
+    writable: bool,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(
+            input.span(),
+            "Device can only be derived for structs",
+        ));
+    };
+
+    let mut mapped = vec![];
+    let fields = match &data.fields {
+        Fields::Named(fields) => fields.named.iter().collect(),
+        Fields::Unnamed(fields) => fields.unnamed.iter().collect(),
+        Fields::Unit => vec![],
+    };
+    for (i, field) in fields.into_iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        };
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("device")) {
+            let mut offset = None;
+            let mut readable = true;
+            let mut writable = true;
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("offset") {
+                    offset = Some(meta.value()?.parse::<Expr>()?);
+                } else if meta.path.is_ident("read_only") {
+                    writable = false;
+                } else if meta.path.is_ident("write_only") {
+                    readable = false;
+                } else {
+                    return Err(meta.error("expected `offset`, `read_only` or `write_only`"));
+                }
+                Ok(())
+            })?;
+            let Some(offset) = offset else {
+                return Err(syn::Error::new(attr.span(), "missing `offset = ..`"));
+            };
+            if !readable && !writable {
+                return Err(syn::Error::new(
+                    attr.span(),
+                    "a field cannot be both read_only and write_only",
+                ));
+            }
+            mapped.push(Mapped {
+                member: member.clone(),
+                ty: field.ty.clone(),
+                offset,
+                readable,
+                writable,
+            });
+        }
+    }
+
+    let reads: Vec<_> = mapped
+        .iter()
+        .filter(|m| m.readable)
+        .map(|m| {
+            let Mapped {
+                member, ty, offset, ..
+            } = m;
+            quote! {
+                if let Some(i) = addr.checked_sub(#offset) {
+                    if i < <#ty as ::tbo2::devices::Field>::SIZE {
+                        return Some(::tbo2::devices::Field::read_byte(&self.#member, i));
+                    }
+                }
+            }
+        })
+        .collect();
+    let writes = mapped.iter().filter(|m| m.writable).map(|m| {
+        let Mapped {
+            member, ty, offset, ..
+        } = m;
+        quote! {
+            if let Some(i) = addr.checked_sub(#offset) {
+                if i < <#ty as ::tbo2::devices::Field>::SIZE {
+                    ::tbo2::devices::Field::write_byte(&mut self.#member, i, data);
+                    return Some(());
+                }
+            }
+        }
+    });
+    let writes: Vec<_> = writes.collect();
+    let resets = mapped.iter().map(|m| {
+        let member = &m.member;
+        quote! { ::tbo2::devices::Field::clear(&mut self.#member); }
+    });
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::tbo2::Device for #name #ty_generics #where_clause {
+            fn reset(&mut self) {
+                #(#resets)*
+            }
+
+            fn read(&mut self, addr: usize) -> Option<u8> {
+                #(#reads)*
+                None
+            }
+
+            fn peek(&self, addr: usize) -> Option<u8> {
+                #(#reads)*
+                None
+            }
+
+            fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+                #(#writes)*
+                None
+            }
+
+            fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+                #(#writes)*
+                None
+            }
+        }
+    })
+}
@@ -0,0 +1,410 @@
+//! a small graphical debugger on top of the public TbO2 APIs.
+//!
+//! cargo run --example debugger --features gui -- [program.bin] [origin] [program.dbg]
+//!
+//! the program is loaded into 64K of RAM at _origin_ (default $0400) and the reset vector points at it.
+//! with a cc65 debug file, the trace shows source lines and breakpoints accept `file:line` and symbols.
+
+use std::{
+    collections::VecDeque,
+    env, fs,
+    time::{Duration, Instant},
+};
+
+use eframe::egui;
+use tbo2::{devices::Interrupt, disasm, Cpu, DebugInfo, ExecutionError, Machine, CPU};
+
+/// instructions executed per frame while running.
+const STEPS_PER_FRAME: usize = 10_000;
+/// longest a frame runs the machine for, so a throttled clock keeps the window responsive.
+const FRAME_TIME: Duration = Duration::from_millis(15);
+/// executed instructions kept in the trace view.
+const TRACE_LEN: usize = 200;
+/// lines in the disassembly view, a few of them before PC.
+const DISASM_LINES: usize = 16;
+
+/// counts down from 10 into $10 and loops, used when no program is given.
+const DEMO: &[u8] = &[
+    0xA2, 0x0A, // LDX #$0A
+    0x86, 0x10, // STX $10
+    0xCA, // DEX
+    0xD0, 0xFB, // BNE $0402
+    0x4C, 0x00, 0x04, // JMP $0400
+];
+
+fn main() -> eframe::Result {
+    let mut args = env::args().skip(1);
+    let program = match args.next() {
+        Some(path) => fs::read(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path, e)),
+        None => DEMO.to_vec(),
+    };
+    let origin = match args.next() {
+        Some(v) => u16::from_str_radix(v.trim_start_matches("0x").trim_start_matches('$'), 16)
+            .expect("origin must be a hexadecimal address"),
+        None => 0x0400,
+    };
+    let debug_info = args.next().map(|path| {
+        DebugInfo::load(&path).unwrap_or_else(|e| panic!("cannot load {}: {:?}", path, e))
+    });
+
+    eframe::run_native(
+        "TbO2 debugger",
+        eframe::NativeOptions::default(),
+        Box::new(move |_| Ok(Box::new(Debugger::new(origin, program, debug_info)))),
+    )
+}
+
+struct Debugger {
+    origin: u16,
+    program: Vec<u8>,
+    debug_info: Option<DebugInfo>,
+    machine: Machine,
+    running: bool,
+    error: Option<String>,
+    trace: VecDeque<String>,
+    breakpoint_input: String,
+    memory_base: u16,
+    memory_input: String,
+    poke_addr: String,
+    poke_value: String,
+    registers_addr: u16,
+    registers_input: String,
+    /// the clock in kHz, empty to run unthrottled.
+    clock_input: String,
+}
+impl Debugger {
+    fn new(origin: u16, program: Vec<u8>, debug_info: Option<DebugInfo>) -> Self {
+        Self {
+            machine: new_machine(origin, &program),
+            origin,
+            program,
+            debug_info,
+            running: false,
+            error: None,
+            trace: VecDeque::new(),
+            breakpoint_input: String::new(),
+            memory_base: origin & 0xFFF0,
+            memory_input: String::new(),
+            poke_addr: String::new(),
+            poke_value: String::new(),
+            registers_addr: 0,
+            registers_input: String::new(),
+            clock_input: String::new(),
+        }
+    }
+
+    fn step(&mut self) -> bool {
+        let pc = self.machine.cpu().get_pc();
+        match self.machine.step() {
+            Ok(_) => {
+                if self.trace.len() == TRACE_LEN {
+                    self.trace.pop_front();
+                }
+                let mut line = self.machine.cpu().trace_exec().trim_end().to_owned();
+                if let Some(desc) = self.describe(pc) {
+                    line = format!("{} ; {}", line, desc);
+                }
+                self.trace.push_back(line);
+                true
+            }
+            Err(e) => {
+                self.stop(e);
+                false
+            }
+        }
+    }
+
+    /// run a step over or out, leaving the trace to the instructions stepped one by one.
+    /// the breakpoints live in the CPU, so these stop at them as well.
+    fn step_with(&mut self, step: impl FnOnce(&mut CPU) -> Result<u64, ExecutionError>) {
+        if let Err(e) = step(self.machine.cpu_mut()) {
+            self.stop(e);
+        }
+    }
+
+    /// stop running on _e_, which is only an error if it is no breakpoint.
+    fn stop(&mut self, e: ExecutionError) {
+        self.running = false;
+        if !matches!(e, ExecutionError::Breakpoint(_)) {
+            self.error = Some(e.to_string());
+        }
+    }
+
+    fn describe(&self, addr: u16) -> Option<String> {
+        self.debug_info.as_ref()?.describe(addr)
+    }
+
+    fn resolve(&self, spec: &str) -> Option<u16> {
+        match &self.debug_info {
+            Some(info) => info.resolve(spec),
+            None => parse_hex(spec),
+        }
+    }
+
+    fn run_frame(&mut self) {
+        let start = Instant::now();
+        for _ in 0..STEPS_PER_FRAME {
+            if !self.step() || start.elapsed() > FRAME_TIME {
+                return;
+            }
+        }
+    }
+
+    fn registers_ui(&mut self, ui: &mut egui::Ui) {
+        let regs = self.machine.cpu().registers();
+        ui.heading("Registers");
+        egui::Grid::new("registers").show(ui, |ui| {
+            for (name, value) in [
+                ("PC", format!("${:04X}", regs.pc)),
+                ("A", format!("${:02X}", regs.a)),
+                ("X", format!("${:02X}", regs.x)),
+                ("Y", format!("${:02X}", regs.y)),
+                ("SP", format!("${:02X}", regs.sp)),
+                ("P", format!("{:08b}", regs.status)),
+            ] {
+                ui.label(name);
+                ui.monospace(value);
+                ui.end_row();
+            }
+        });
+        ui.monospace("         NV-BDIZC");
+        ui.label(format!("cycles: {}", self.machine.cpu().cycles()));
+        if let Some(desc) = self.describe(regs.pc) {
+            ui.label(format!("at {}", desc));
+        }
+        ui.label(format!(
+            "IRQ line: {}",
+            if self.machine.is_irq_asserted() {
+                "asserted"
+            } else {
+                "released"
+            }
+        ));
+        ui.horizontal(|ui| {
+            ui.label("pending");
+            for (name, interrupt) in [("IRQ", Interrupt::Irq), ("NMI", Interrupt::Nmi)] {
+                let mut pending = self.machine.cpu().is_pending(interrupt);
+                if ui.checkbox(&mut pending, name).changed() {
+                    self.machine.cpu_mut().set_pending(interrupt, pending);
+                }
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Step").clicked() {
+                self.error = None;
+                self.step();
+            }
+            if ui.button("Step over").clicked() {
+                self.step_with(CPU::step_over);
+            }
+            if ui.button("Step out").clicked() {
+                self.step_with(CPU::step_out);
+            }
+            let label = if self.running { "Pause" } else { "Run" };
+            if ui.button(label).clicked() {
+                self.error = None;
+                self.running = !self.running;
+            }
+            if ui.button("Reset").clicked() {
+                let breakpoints: Vec<_> = self.machine.cpu().breakpoints().collect();
+                let (hz, turbo) = (self.machine.get_clock_hz(), self.machine.is_turbo());
+                self.machine = new_machine(self.origin, &self.program);
+                for addr in breakpoints {
+                    self.machine.cpu_mut().add_breakpoint(addr);
+                }
+                self.machine.set_clock_hz(hz);
+                self.machine.set_turbo(turbo);
+                self.trace.clear();
+                self.error = None;
+                self.running = false;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("clock kHz");
+            ui.add(egui::TextEdit::singleline(&mut self.clock_input).desired_width(60.0));
+            if ui.button("Set").clicked() {
+                let khz = self.clock_input.trim().parse::<u64>().ok();
+                self.machine
+                    .set_clock_hz(khz.filter(|v| *v > 0).map(|v| v * 1000));
+            }
+            let mut turbo = self.machine.is_turbo();
+            let throttled = self.machine.get_clock_hz().is_some();
+            let checkbox = egui::Checkbox::new(&mut turbo, "Turbo");
+            if ui.add_enabled(throttled, checkbox).changed() {
+                self.machine.set_turbo(turbo);
+            }
+        });
+        if let Some(e) = &self.error {
+            ui.colored_label(egui::Color32::RED, e);
+        }
+    }
+
+    fn breakpoints_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Breakpoints");
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.breakpoint_input).desired_width(60.0));
+            if ui.button("Add").clicked() {
+                if let Some(addr) = self.resolve(&self.breakpoint_input) {
+                    self.machine.cpu_mut().add_breakpoint(addr);
+                    self.breakpoint_input.clear();
+                }
+            }
+        });
+        let mut removed = None;
+        let breakpoints: Vec<_> = self.machine.cpu().breakpoints().collect();
+        for addr in breakpoints {
+            ui.horizontal(|ui| {
+                match self.describe(addr) {
+                    Some(desc) => ui.monospace(format!("${:04X} {}", addr, desc)),
+                    None => ui.monospace(format!("${:04X}", addr)),
+                };
+                if ui.small_button("x").clicked() {
+                    removed = Some(addr);
+                }
+            });
+        }
+        if let Some(addr) = removed {
+            self.machine.cpu_mut().remove_breakpoint(addr);
+        }
+    }
+
+    fn memory_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Memory");
+        ui.horizontal(|ui| {
+            ui.label("go to");
+            ui.add(egui::TextEdit::singleline(&mut self.memory_input).desired_width(60.0));
+            if ui.button("Show").clicked() {
+                if let Some(addr) = parse_hex(&self.memory_input) {
+                    self.memory_base = addr & 0xFFF0;
+                }
+            }
+            ui.separator();
+            ui.label("poke");
+            ui.add(egui::TextEdit::singleline(&mut self.poke_addr).desired_width(60.0));
+            ui.add(egui::TextEdit::singleline(&mut self.poke_value).desired_width(30.0));
+            if ui.button("Write").clicked() {
+                if let (Some(addr), Some(value)) =
+                    (parse_hex(&self.poke_addr), parse_hex(&self.poke_value))
+                {
+                    let _ = self.machine.cpu_mut().poke_byte(addr, value as u8);
+                }
+            }
+        });
+
+        let pc = self.machine.cpu().get_pc();
+        for row in 0..16u16 {
+            let row_addr = self.memory_base.wrapping_add(row * 16);
+            let mut line = format!("{:04X}:", row_addr);
+            for col in 0..16u16 {
+                let addr = row_addr.wrapping_add(col);
+                let byte = self.machine.cpu().peek_byte(addr);
+                let mark = if addr == pc { '>' } else { ' ' };
+                match byte {
+                    Some(byte) => line.push_str(&format!("{}{:02X}", mark, byte)),
+                    None => line.push_str(&format!("{}??", mark)),
+                }
+            }
+            ui.monospace(line);
+        }
+    }
+
+    fn disasm_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Disassembly");
+        let cpu = self.machine.cpu();
+        let (layout, model, symbols) = (cpu.layout(), cpu.get_model(), cpu.symbols());
+        let pc = cpu.get_pc();
+        // start a few bytes before PC, at the earliest address whose instructions run into PC
+        let start = (pc.saturating_sub(8)..pc)
+            .find(|v| {
+                let lines = disasm::disassemble(layout, *v as usize..pc as usize, model, symbols);
+                lines
+                    .last()
+                    .is_some_and(|v| v.addr as usize + v.bytes.len().max(1) == pc as usize)
+            })
+            .unwrap_or(pc);
+        let breakpoints: Vec<_> = cpu.breakpoints().collect();
+        let mut addr = start;
+        for _ in 0..DISASM_LINES {
+            let line = disasm::disassemble_one(layout, addr, model, symbols);
+            let mark = match (addr == pc, breakpoints.contains(&addr)) {
+                (true, _) => '>',
+                (false, true) => '*',
+                _ => ' ',
+            };
+            ui.monospace(format!("{} {}", mark, line));
+            addr = addr.wrapping_add(line.bytes.len().max(1) as u16);
+        }
+    }
+
+    fn devices_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Devices");
+        let layout = self.machine.cpu().layout();
+        for line in layout.describe().lines() {
+            ui.monospace(line);
+        }
+        ui.horizontal(|ui| {
+            ui.label("registers at");
+            ui.add(egui::TextEdit::singleline(&mut self.registers_input).desired_width(60.0));
+            if ui.button("Show").clicked() {
+                if let Some(addr) = parse_hex(&self.registers_input) {
+                    self.registers_addr = addr;
+                }
+            }
+        });
+        // peeked, so reading status registers does not clear their flags
+        let start = self.registers_addr as usize;
+        ui.monospace(layout.hexdump(start..start + 16).trim_end());
+    }
+
+    fn trace_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Trace");
+        egui::ScrollArea::vertical()
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in &self.trace {
+                    ui.monospace(line);
+                }
+            });
+    }
+}
+impl eframe::App for Debugger {
+    fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        if self.running {
+            self.run_frame();
+            ui.ctx().request_repaint();
+        }
+
+        egui::Panel::left("cpu").show(ui, |ui| self.registers_ui(ui));
+        egui::Panel::right("breakpoints").show(ui, |ui| {
+            self.breakpoints_ui(ui);
+            ui.separator();
+            self.devices_ui(ui);
+        });
+        egui::CentralPanel::default().show(ui, |ui| {
+            self.disasm_ui(ui);
+            ui.separator();
+            self.memory_ui(ui);
+            ui.separator();
+            self.trace_ui(ui);
+        });
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u16> {
+    u16::from_str_radix(
+        s.trim().trim_start_matches("0x").trim_start_matches('$'),
+        16,
+    )
+    .ok()
+}
+
+/// a machine running _program_ that tracks its call stack for stepping out.
+/// idle loops are not slept through, which would freeze the window for a whole frame.
+fn new_machine(origin: u16, program: &[u8]) -> Machine {
+    let mut machine = Machine::with_program(origin, program);
+    machine.set_idle_sleep(false);
+    machine.cpu_mut().set_call_stack_tracking(true);
+    machine
+}
@@ -0,0 +1,89 @@
+//! EhBASIC on the host terminal.
+//!
+//! cargo run --release --example ehbasic -- ehbasic.bin [STATE]
+//!
+//! the ROM image must be EhBASIC built for a 6551 ACIA at $8000, see [`tbo2::Machine::ehbasic`].
+//! the terminal is in raw mode, so Ctrl-C reaches BASIC to stop a running program.
+//! Ctrl-] breaks out of the emulator, saving the machine to `ehbasic.state`,
+//! which is resumed from when given as _STATE_. Ctrl-T toggles turbo, running BASIC
+//! as fast as the host allows instead of at 1 MHz.
+
+use std::{env, fs, process};
+
+use tbo2::{Console, Machine, SaveState, SaveStateError};
+
+/// the clock of a typical 6502 single board computer.
+const CPU_HZ: u64 = 1_000_000;
+
+/// Ctrl-], as telnet uses.
+const BREAK_KEY: u8 = 0x1D;
+/// Ctrl-T.
+const TURBO_KEY: u8 = 0x14;
+
+const STATE_PATH: &str = "ehbasic.state";
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("usage: ehbasic ROM [STATE]");
+        process::exit(2);
+    };
+    let rom = fs::read(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path, e));
+    let state = args.next().map(|path| {
+        let state = fs::File::open(&path)
+            .map_err(SaveStateError::from)
+            .and_then(SaveState::read_from);
+        state.unwrap_or_else(|e| panic!("cannot read {}: {}", path, e))
+    });
+
+    // input that is no terminal, e.g. a piped program, is taken as it is
+    #[cfg(unix)]
+    let mut console = Console::raw().unwrap_or_else(|_| Console::new());
+    #[cfg(not(unix))]
+    let mut console = Console::new();
+    let brk = console.set_break_key(BREAK_KEY);
+    let turbo = console.set_break_key(TURBO_KEY);
+
+    let mut machine = Machine::ehbasic(&rom, console, CPU_HZ);
+    if let Some(state) = state {
+        if let Err(e) = state.restore(machine.cpu_mut()) {
+            drop(machine);
+            eprintln!("cannot restore the machine: {}", e);
+            process::exit(1);
+        }
+    }
+
+    let result = loop {
+        if brk.take() {
+            break Ok(SaveState::capture(machine.cpu()));
+        }
+        if turbo.take() {
+            machine.set_turbo(!machine.is_turbo());
+        }
+        if let Err(e) = machine.step() {
+            break Err(format!("{}\n{}", e, machine.cpu()));
+        }
+    };
+    // the console gives the terminal back when the machine is dropped
+    drop(machine);
+
+    match result {
+        Ok(state) => {
+            let saved = fs::File::create(STATE_PATH).and_then(|f| state.write_to(f, true));
+            match saved {
+                Ok(()) => eprintln!(
+                    "\nsaved to {}, resume with: ehbasic {} {}",
+                    STATE_PATH, path, STATE_PATH
+                ),
+                Err(e) => {
+                    eprintln!("\ncannot write {}: {}", STATE_PATH, e);
+                    process::exit(1);
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
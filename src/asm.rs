@@ -0,0 +1,377 @@
+use core::fmt;
+use std::{cell::Cell, collections::HashMap};
+
+use crate::{
+    inst::{decode_inst, Inst},
+    AddressingMode, CpuModel,
+};
+
+/// what went wrong assembling, _line_ being the 1-based source line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmError {
+    /// _mnemonic_ is no 65C02 instruction or directive.
+    UnknownMnemonic {
+        line: usize,
+        mnemonic: String,
+    },
+    /// _operand_ cannot be parsed, or the instruction has no such addressing mode.
+    InvalidOperand {
+        line: usize,
+        operand: String,
+    },
+    UndefinedLabel {
+        line: usize,
+        label: String,
+    },
+    DuplicateLabel {
+        line: usize,
+        label: String,
+    },
+    /// a branch cannot reach _target_.
+    BranchOutOfRange {
+        line: usize,
+        target: u16,
+    },
+    /// _value_ does not fit the single byte it goes in.
+    ValueOutOfRange {
+        line: usize,
+        value: u16,
+    },
+}
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmError::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {}: unknown mnemonic {}", line, mnemonic)
+            }
+            AsmError::InvalidOperand { line, operand } => {
+                write!(f, "line {}: invalid operand {}", line, operand)
+            }
+            AsmError::UndefinedLabel { line, label } => {
+                write!(f, "line {}: undefined label {}", line, label)
+            }
+            AsmError::DuplicateLabel { line, label } => {
+                write!(f, "line {}: label {} is already defined", line, label)
+            }
+            AsmError::BranchOutOfRange { line, target } => {
+                write!(
+                    f,
+                    "line {}: branch target ${:04X} is out of range",
+                    line, target
+                )
+            }
+            AsmError::ValueOutOfRange { line, value } => {
+                write!(f, "line {}: ${:04X} does not fit in a byte", line, value)
+            }
+        }
+    }
+}
+
+/// assemble 65C02 _source_ into machine code to be loaded at _origin_, see [`asm!`](crate::asm!).
+///
+/// statements are separated by new lines or `;`, each an optional `label:` followed by an
+/// instruction or a `.byte` or `.word` directive with comma-separated values. values are
+/// decimal, `$` hex or `%` binary numbers or labels, optionally with `+` and `-` offsets and a
+/// `<` or `>` prefix taking the low or high byte. zero page addressing is used when an operand
+/// fits, unless it refers to a label defined further down.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AsmError> {
+    let mut statements = vec![];
+    for (i, text) in source.lines().enumerate() {
+        for statement in text.split(';') {
+            let (label, rest) = match statement.split_once(':') {
+                Some((label, rest)) => (Some(label.trim()), rest),
+                None => (None, statement),
+            };
+            let rest = rest.trim();
+            let (mnemonic, operand) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let operand: String = operand.split_whitespace().collect();
+            if label.is_some() || !mnemonic.is_empty() {
+                statements.push(Statement {
+                    line: i + 1,
+                    label,
+                    mnemonic: mnemonic.to_ascii_uppercase(),
+                    operand,
+                });
+            }
+        }
+    }
+
+    let mut asm = Assembler {
+        opcodes: opcodes(),
+        labels: HashMap::new(),
+        final_pass: false,
+        line: 0,
+    };
+    // the first pass finds the addresses of labels, operands referring to labels it has not
+    // seen yet are assumed to be absolute and stay so
+    let mut forward = vec![false; statements.len()];
+    let mut pc = origin;
+    for (statement, forward) in statements.iter().zip(&mut forward) {
+        asm.line = statement.line;
+        if let Some(label) = statement.label {
+            if asm.labels.insert(label.to_owned(), pc).is_some() {
+                return Err(AsmError::DuplicateLabel {
+                    line: statement.line,
+                    label: label.to_owned(),
+                });
+            }
+        }
+        let (bytes, unresolved) = asm.encode(statement, pc, false)?;
+        *forward = unresolved;
+        pc = pc.wrapping_add(bytes.len() as u16);
+    }
+
+    asm.final_pass = true;
+    let mut code = vec![];
+    let mut pc = origin;
+    for (statement, forward) in statements.iter().zip(forward) {
+        asm.line = statement.line;
+        let (bytes, _) = asm.encode(statement, pc, forward)?;
+        pc = pc.wrapping_add(bytes.len() as u16);
+        code.extend(bytes);
+    }
+    Ok(code)
+}
+
+/// assemble 65C02 source into a `Vec<u8>`, panicking if it has errors, see [`asm::assemble`].
+/// the origin defaults to 0.
+///
+/// ```
+/// let code = tbo2::asm!("LDA #$01; STA $5000");
+/// assert_eq!(code, [0xA9, 0x01, 0x8D, 0x00, 0x50]);
+/// let code = tbo2::asm!(0x0400, "loop: DEX; BNE loop; JMP loop");
+/// assert_eq!(code, [0xCA, 0xD0, 0xFD, 0x4C, 0x00, 0x04]);
+/// ```
+#[macro_export]
+macro_rules! asm {
+    ($origin:expr, $source:expr) => {
+        $crate::asm::assemble($source, $origin).unwrap_or_else(|e| panic!("{}", e))
+    };
+    ($source:expr) => {
+        $crate::asm!(0, $source)
+    };
+}
+
+struct Statement<'a> {
+    line: usize,
+    label: Option<&'a str>,
+    mnemonic: String,
+    /// without any whitespace.
+    operand: String,
+}
+
+struct Assembler {
+    opcodes: HashMap<(String, AddressingMode), u8>,
+    labels: HashMap<String, u16>,
+    /// whether labels must all be known.
+    final_pass: bool,
+    line: usize,
+}
+impl Assembler {
+    /// the bytes of _statement_ at _pc_ and whether its operand refers to a label not known yet.
+    /// with _forward_ set, absolute addressing is used even if the operand fits the zero page.
+    fn encode(
+        &self,
+        statement: &Statement,
+        pc: u16,
+        forward: bool,
+    ) -> Result<(Vec<u8>, bool), AsmError> {
+        let mnemonic = statement.mnemonic.as_str();
+        let operand = statement.operand.as_str();
+        let unresolved = Cell::new(false);
+        let eval = |expr: &str| -> Result<u16, AsmError> {
+            let value = self.eval(expr)?;
+            unresolved.set(unresolved.get() || value.is_none());
+            Ok(value.unwrap_or_default())
+        };
+
+        match mnemonic {
+            "" => return Ok((vec![], false)),
+            ".BYTE" | ".WORD" => {
+                let mut bytes = vec![];
+                for expr in operand.split(',') {
+                    let value = eval(expr)?;
+                    if mnemonic == ".WORD" {
+                        bytes.extend(value.to_le_bytes());
+                    } else {
+                        bytes.push(self.byte(value)?);
+                    }
+                }
+                return Ok((bytes, unresolved.get()));
+            }
+            _ => {}
+        }
+
+        let opcode = |addr_mode| self.opcodes.get(&(mnemonic.to_owned(), addr_mode)).copied();
+        if !self.opcodes.keys().any(|(v, _)| v == mnemonic) {
+            return Err(AsmError::UnknownMnemonic {
+                line: self.line,
+                mnemonic: mnemonic.to_owned(),
+            });
+        }
+        let invalid = || AsmError::InvalidOperand {
+            line: self.line,
+            operand: operand.to_owned(),
+        };
+
+        if let Some(opcode) = opcode(AddressingMode::Relative) {
+            let target = eval(operand)?;
+            let offset = self.branch_offset(pc.wrapping_add(2), target, unresolved.get())?;
+            return Ok((vec![opcode, offset], unresolved.get()));
+        }
+        if let Some(opcode) = opcode(AddressingMode::ZeroPageRelative) {
+            let (zp_addr, target) = operand.split_once(',').ok_or_else(invalid)?;
+            let zp_addr = self.byte(eval(zp_addr)?)?;
+            let target = eval(target)?;
+            let offset = self.branch_offset(pc.wrapping_add(3), target, unresolved.get())?;
+            return Ok((vec![opcode, zp_addr, offset], unresolved.get()));
+        }
+
+        let upper = operand.to_ascii_uppercase();
+        let (addr_mode, value) = if operand.is_empty() || upper == "A" {
+            (AddressingMode::Implied, 0)
+        } else if let Some(expr) = operand.strip_prefix('#') {
+            (AddressingMode::Immediate, eval(expr)?)
+        } else if upper.starts_with('(') && upper.ends_with(",X)") {
+            let value = eval(&operand[1..operand.len() - 3])?;
+            match opcode(AddressingMode::AbsoluteXIndirect) {
+                Some(_) => (AddressingMode::AbsoluteXIndirect, value),
+                None => (AddressingMode::XIndirect, value),
+            }
+        } else if upper.starts_with('(') && upper.ends_with("),Y") {
+            (
+                AddressingMode::IndirectY,
+                eval(&operand[1..operand.len() - 3])?,
+            )
+        } else if upper.starts_with('(') && upper.ends_with(')') {
+            let value = eval(&operand[1..operand.len() - 1])?;
+            match opcode(AddressingMode::Indirect) {
+                Some(_) => (AddressingMode::Indirect, value),
+                None => (AddressingMode::ZeroPageIndirect, value),
+            }
+        } else {
+            let (expr, zp, abs) = if upper.ends_with(",X") {
+                let expr = &operand[..operand.len() - 2];
+                (expr, AddressingMode::ZeroPageX, AddressingMode::AbsoluteX)
+            } else if upper.ends_with(",Y") {
+                let expr = &operand[..operand.len() - 2];
+                (expr, AddressingMode::ZeroPageY, AddressingMode::AbsoluteY)
+            } else {
+                (operand, AddressingMode::ZeroPage, AddressingMode::Absolute)
+            };
+            let value = eval(expr)?;
+            let fits = !forward && !unresolved.get() && value <= 0xFF;
+            match opcode(zp) {
+                Some(_) if fits || opcode(abs).is_none() => (zp, value),
+                _ => (abs, value),
+            }
+        };
+
+        let opcode = opcode(addr_mode).ok_or_else(invalid)?;
+        let mut bytes = vec![opcode];
+        match addr_mode.operand_len() {
+            0 => {}
+            1 => bytes.push(self.byte(value)?),
+            _ => bytes.extend(value.to_le_bytes()),
+        }
+        Ok((bytes, unresolved.get()))
+    }
+
+    /// the value of _expr_, None for labels not known yet before the final pass.
+    fn eval(&self, expr: &str) -> Result<Option<u16>, AsmError> {
+        let (expr, part) = match expr.as_bytes().first() {
+            Some(b'<') => (&expr[1..], Some(false)),
+            Some(b'>') => (&expr[1..], Some(true)),
+            _ => (expr, None),
+        };
+
+        let mut value = Some(0u16);
+        let mut rest = expr;
+        let mut negate = false;
+        loop {
+            let end = rest[1.min(rest.len())..]
+                .find(['+', '-'])
+                .map_or(rest.len(), |v| v + 1);
+            let term = self.term(&rest[..end])?;
+            value = match (value, term) {
+                (Some(value), Some(term)) if negate => Some(value.wrapping_sub(term)),
+                (Some(value), Some(term)) => Some(value.wrapping_add(term)),
+                _ => None,
+            };
+            if end == rest.len() {
+                break;
+            }
+            negate = rest.as_bytes()[end] == b'-';
+            rest = &rest[end + 1..];
+        }
+
+        Ok(value.map(|v| match part {
+            Some(true) => v >> 8,
+            Some(false) => v & 0xFF,
+            None => v,
+        }))
+    }
+
+    fn term(&self, term: &str) -> Result<Option<u16>, AsmError> {
+        let invalid = || AsmError::InvalidOperand {
+            line: self.line,
+            operand: term.to_owned(),
+        };
+        let number = if let Some(hex) = term.strip_prefix('$') {
+            u16::from_str_radix(hex, 16)
+        } else if let Some(bin) = term.strip_prefix('%') {
+            u16::from_str_radix(bin, 2)
+        } else if term.starts_with(|c: char| c.is_ascii_digit()) {
+            term.parse()
+        } else {
+            if term.is_empty() || !term.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(invalid());
+            }
+            return match self.labels.get(term) {
+                Some(addr) => Ok(Some(*addr)),
+                None if !self.final_pass => Ok(None),
+                None => Err(AsmError::UndefinedLabel {
+                    line: self.line,
+                    label: term.to_owned(),
+                }),
+            };
+        };
+        number.map(Some).map_err(|_| invalid())
+    }
+
+    fn byte(&self, value: u16) -> Result<u8, AsmError> {
+        u8::try_from(value).map_err(|_| AsmError::ValueOutOfRange {
+            line: self.line,
+            value,
+        })
+    }
+
+    fn branch_offset(&self, next: u16, target: u16, unresolved: bool) -> Result<u8, AsmError> {
+        if unresolved {
+            return Ok(0);
+        }
+        let offset = target.wrapping_sub(next) as i16;
+        i8::try_from(offset)
+            .map(|v| v as u8)
+            .map_err(|_| AsmError::BranchOutOfRange {
+                line: self.line,
+                target,
+            })
+    }
+}
+
+/// the opcode of every documented 65C02 instruction and addressing mode.
+fn opcodes() -> HashMap<(String, AddressingMode), u8> {
+    let mut opcodes = HashMap::new();
+    for byte in 0..=0xFF {
+        let Ok((inst, addr_mode)) = decode_inst(byte, CpuModel::WDC65C02, false) else {
+            continue;
+        };
+        // the unused opcodes decode as NOPs too
+        if matches!(inst, Inst::NOP) && byte != 0xEA {
+            continue;
+        }
+        opcodes.entry((inst.to_string(), addr_mode)).or_insert(byte);
+    }
+    opcodes
+}
@@ -0,0 +1,245 @@
+//! runs a 6502 program without a frontend until it signals completion, e.g. test suites in CI.
+//!
+//! tbo2-run machine.manifest
+//!
+//! the manifest holds one `key = value` per line, `#` starts a comment.
+//! numbers are decimal, or hexadecimal with a `$` or `0x` prefix. paths are relative to the manifest.
+//!
+//! - `model`: `nmos6502`, `wdc65c02` (default) or `rockwell65c02`.
+//! - `fill`: contents of RAM not loaded from a file, `zero` (default), `ones` or `random SEED`,
+//!   which also powers the registers on random.
+//! - `load`: `FILE ADDR`, load _FILE_ into RAM at _ADDR_. may be repeated.
+//! - `start`: address to start at, the reset vector is used when missing.
+//! - `done`: address the program writes a result code to when it finishes, $00 passes.
+//! - `success`: address the program traps at (e.g. `JMP *`) when it passes.
+//!   a trap anywhere else fails.
+//! - `max_cycles`: cycle budget, unlimited when missing.
+//! - `dump`: `START..END FILE`, write memory from _START_ up to _END_ to _FILE_ when the run ends.
+//!   may be repeated.
+//!
+//! exit status: 0 passed, 1 failed, 2 the manifest or an instruction could not be handled,
+//! 3 the cycle budget ran out.
+
+use std::{
+    cell::RefCell,
+    env, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    rc::Rc,
+};
+
+use tbo2::{
+    devices::Snoop, testing::PASS, CpuModel, Device, FillPattern, LayoutBuilder, RunState, CPU, RAM,
+};
+
+#[derive(Debug, Default)]
+struct Manifest {
+    model: CpuModel,
+    fill: FillPattern,
+    loads: Vec<(PathBuf, u16)>,
+    start: Option<u16>,
+    done: Option<u16>,
+    success: Option<u16>,
+    max_cycles: Option<u64>,
+    dumps: Vec<(Range<usize>, PathBuf)>,
+}
+impl Manifest {
+    fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut manifest = Manifest::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: &str| format!("{}:{}: {}", path.display(), i + 1, msg);
+            let (key, value) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| err("expected `key = value`"))?;
+            let addr = |v: &str| {
+                parse_num(v)
+                    .and_then(|v| u16::try_from(v).ok())
+                    .ok_or_else(|| err("expected an address"))
+            };
+
+            match key {
+                "model" => {
+                    manifest.model = match value.to_ascii_lowercase().as_str() {
+                        "nmos6502" => CpuModel::NMOS6502,
+                        "wdc65c02" => CpuModel::WDC65C02,
+                        "rockwell65c02" => CpuModel::Rockwell65C02,
+                        _ => return Err(err("unknown model")),
+                    }
+                }
+                "fill" => {
+                    manifest.fill = match value.split_once(' ') {
+                        None if value == "zero" => FillPattern::Zero,
+                        None if value == "ones" => FillPattern::Ones,
+                        Some(("random", seed)) => FillPattern::Random(
+                            parse_num(seed.trim()).ok_or_else(|| err("expected a seed"))?,
+                        ),
+                        _ => return Err(err("unknown fill pattern")),
+                    }
+                }
+                "load" => {
+                    let (file, at) = value
+                        .rsplit_once(' ')
+                        .ok_or_else(|| err("expected `FILE ADDR`"))?;
+                    manifest.loads.push((dir.join(file.trim()), addr(at)?));
+                }
+                "start" => manifest.start = Some(addr(value)?),
+                "done" => manifest.done = Some(addr(value)?),
+                "success" => manifest.success = Some(addr(value)?),
+                "max_cycles" => {
+                    manifest.max_cycles =
+                        Some(parse_num(value).ok_or_else(|| err("expected a cycle count"))?)
+                }
+                "dump" => {
+                    let (range, file) = value
+                        .split_once(' ')
+                        .ok_or_else(|| err("expected `START..END FILE`"))?;
+                    let (start, end) = range
+                        .split_once("..")
+                        .ok_or_else(|| err("expected `START..END FILE`"))?;
+                    let end = parse_num(end)
+                        .filter(|v| *v <= 0x10000)
+                        .ok_or_else(|| err("expected an address"))?;
+                    let range = addr(start)? as usize..end as usize;
+                    manifest.dumps.push((range, dir.join(file.trim())));
+                }
+                _ => return Err(err("unknown key")),
+            }
+        }
+
+        if manifest.done.is_none() && manifest.success.is_none() && manifest.max_cycles.is_none() {
+            return Err(format!(
+                "{}: no stop condition, set `done`, `success` or `max_cycles`",
+                path.display()
+            ));
+        }
+        Ok(manifest)
+    }
+}
+
+fn parse_num(v: &str) -> Option<u64> {
+    match v.strip_prefix('$').or_else(|| v.strip_prefix("0x")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => v.parse().ok(),
+    }
+}
+
+/// the last value written to the done address.
+#[derive(Default)]
+struct DoneWatch(Option<u8>);
+impl Device for DoneWatch {
+    fn write(&mut self, _: usize, data: u8) -> Option<()> {
+        self.0 = Some(data);
+        Some(())
+    }
+}
+
+enum Outcome {
+    Passed,
+    Failed(String),
+    OutOfCycles,
+}
+
+fn run(manifest: &Manifest) -> Result<Outcome, String> {
+    let mut ram = RAM::<0x10000>::filled(manifest.fill);
+    for (path, addr) in &manifest.loads {
+        let data = fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        if *addr as usize + data.len() > 0x10000 {
+            return Err(format!("{} does not fit at ${:04X}", path.display(), addr));
+        }
+        ram.load_bytes(*addr as usize, &data);
+    }
+
+    let done = Rc::new(RefCell::new(DoneWatch::default()));
+    let done_range = manifest.done.map_or(0..0, |v| v as usize..v as usize + 1);
+
+    let mut builder = LayoutBuilder::new(0x10000);
+    let ram_id = builder.add_device(Snoop::new(ram, done.clone(), done_range));
+    builder.assign_range(0x0000, 0x10000, ram_id);
+    let layout = builder.build().map_err(|e| format!("{:?}", e))?;
+    let mut cpu = CPU::with_model(layout, manifest.model).expect("64K layout");
+
+    if let FillPattern::Random(seed) = manifest.fill {
+        cpu.randomize_registers(seed);
+    }
+    cpu.reset();
+    if let Some(start) = manifest.start {
+        cpu.set_pc(start);
+    }
+
+    let outcome = loop {
+        if manifest.max_cycles.is_some_and(|v| cpu.cycles() >= v) {
+            break Outcome::OutOfCycles;
+        }
+        let pc = cpu.get_pc();
+        if let Err(e) = cpu.step() {
+            eprintln!("{}", cpu);
+            let pcs: Vec<_> = cpu.pc_history().map(|v| format!("${:04X}", v)).collect();
+            let recent = &pcs[pcs.len().saturating_sub(16)..];
+            eprintln!("recent PCs: {}", recent.join(" "));
+            return Err(format!("at ${:04X}: {}", pc, e));
+        }
+        if let Some(code) = done.borrow_mut().0.take() {
+            break match code {
+                PASS => Outcome::Passed,
+                code => Outcome::Failed(format!("result code ${:02X}", code)),
+            };
+        }
+        if cpu.run_state() == RunState::Jammed {
+            break Outcome::Failed(format!("jammed at ${:04X}", pc));
+        }
+        if cpu.get_pc() == pc {
+            break match manifest.success {
+                Some(addr) if addr == pc => Outcome::Passed,
+                _ => Outcome::Failed(format!("trapped at ${:04X}", pc)),
+            };
+        }
+    };
+    eprintln!("{} after {} cycles", cpu, cpu.cycles());
+
+    for (range, path) in &manifest.dumps {
+        let data: Vec<u8> = range
+            .clone()
+            .map(|addr| cpu.layout().peek(addr).unwrap_or(0xFF))
+            .collect();
+        fs::write(path, data).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    }
+    Ok(outcome)
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: tbo2-run MANIFEST");
+        return ExitCode::from(2);
+    };
+
+    let result = Manifest::load(Path::new(&path)).and_then(|v| run(&v));
+    match result {
+        Ok(Outcome::Passed) => {
+            eprintln!("passed");
+            ExitCode::SUCCESS
+        }
+        Ok(Outcome::Failed(reason)) => {
+            eprintln!("failed: {}", reason);
+            ExitCode::from(1)
+        }
+        Ok(Outcome::OutOfCycles) => {
+            eprintln!("cycle budget ran out");
+            ExitCode::from(3)
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// the smallest lead over real time worth sleeping for.
+const MIN_SLEEP: Duration = Duration::from_millis(1);
+/// falling further behind than this is not caught up on, e.g. after the host was suspended.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// paces emulated clock cycles against host time.
+/// all timing is expressed in emulated cycles, so changing the frequency
+/// or entering turbo mode keeps everything counted in cycles consistent.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    hz: u64,
+    turbo: bool,
+    epoch: Instant,
+    cycles: u64,
+}
+impl Clock {
+    pub fn new(hz: u64) -> Self {
+        assert!(hz > 0, "clock frequency must be non-zero");
+        Self {
+            hz,
+            turbo: false,
+            epoch: Instant::now(),
+            cycles: 0,
+        }
+    }
+
+    pub fn get_hz(&self) -> u64 {
+        self.hz
+    }
+
+    pub fn set_hz(&mut self, hz: u64) {
+        assert!(hz > 0, "clock frequency must be non-zero");
+        self.hz = hz;
+        self.rebase();
+    }
+
+    pub fn is_turbo(&self) -> bool {
+        self.turbo
+    }
+
+    /// turbo mode runs as fast as the host allows.
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo = turbo;
+        self.rebase();
+    }
+
+    /// account for _cycles_ emulated cycles, sleeping whenever emulation runs ahead of real time.
+    pub fn advance(&mut self, cycles: u64) {
+        if self.turbo {
+            return;
+        }
+
+        self.cycles += cycles;
+        let target = Duration::from_nanos(
+            (self.cycles as u128 * 1_000_000_000 / self.hz as u128).min(u64::MAX as u128) as u64,
+        );
+        let elapsed = self.epoch.elapsed();
+
+        if target > elapsed + MIN_SLEEP {
+            thread::sleep(target - elapsed);
+        } else if elapsed > target + MAX_LAG {
+            self.rebase();
+        }
+    }
+
+    fn rebase(&mut self) {
+        self.epoch = Instant::now();
+        self.cycles = 0;
+    }
+}
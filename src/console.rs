@@ -0,0 +1,129 @@
+use std::{
+    io::{self, Read, Write},
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::devices::BreakSignal;
+
+/// the host's standard input and output as a serial line, e.g. for an [`Acia`](crate::devices::Acia).
+/// reads never block, they fail with `WouldBlock` while no input is waiting.
+/// input arrives a line at a time as the host terminal delivers it, with line feeds
+/// turned into carriage returns like a terminal's Return key sends.
+pub struct Console {
+    input: Receiver<u8>,
+    break_keys: Arc<Mutex<Vec<(u8, BreakSignal)>>>,
+    /// the terminal settings to restore when dropped, if it was put in raw mode.
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+impl Console {
+    pub fn new() -> Self {
+        let (tx, input) = mpsc::channel();
+        let break_keys = Arc::new(Mutex::new(Vec::<(u8, BreakSignal)>::new()));
+        let keys = break_keys.clone();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                let Ok(byte) = byte else {
+                    break;
+                };
+                // checked here rather than when the program reads, which it may never do
+                if let Some((_, signal)) = keys.lock().unwrap().iter().find(|v| v.0 == byte) {
+                    signal.raise();
+                    continue;
+                }
+                let byte = if byte == b'\n' { b'\r' } else { byte };
+                if tx.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            input,
+            break_keys,
+            #[cfg(unix)]
+            saved: None,
+        }
+    }
+
+    /// like [`Console::new`], but with the host terminal in raw mode until the console is
+    /// dropped, so input arrives as it is typed and keys like Ctrl-C reach the program
+    /// instead of signalling the host process. fails if standard input is no terminal.
+    #[cfg(unix)]
+    pub fn raw() -> io::Result<Self> {
+        // SAFETY: plain libc calls on standard input, termios is plain data
+        let saved = unsafe {
+            let mut termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let saved = termios;
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            saved
+        };
+        let mut console = Self::new();
+        console.saved = Some(saved);
+        Ok(console)
+    }
+
+    /// swallow _key_ when it is typed and raise the returned signal instead,
+    /// e.g. for a frontend to break into its monitor. several keys may be set,
+    /// each with its own signal, setting a key again replaces its signal.
+    pub fn set_break_key(&mut self, key: u8) -> BreakSignal {
+        let signal = BreakSignal::default();
+        let mut keys = self.break_keys.lock().unwrap();
+        keys.retain(|v| v.0 != key);
+        keys.push((key, signal.clone()));
+        signal
+    }
+
+    pub fn clear_break_key(&mut self, key: u8) {
+        self.break_keys.lock().unwrap().retain(|v| v.0 != key);
+    }
+}
+#[cfg(unix)]
+impl Drop for Console {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.saved {
+            // SAFETY: restoring settings read from the same descriptor
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+        }
+    }
+}
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Read for Console {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            match self.input.try_recv() {
+                Ok(byte) => {
+                    buf[n] = byte;
+                    n += 1;
+                }
+                Err(TryRecvError::Empty) if n == 0 => return Err(io::ErrorKind::WouldBlock.into()),
+                Err(TryRecvError::Disconnected) if n == 0 => return Ok(0),
+                Err(_) => break,
+            }
+        }
+        Ok(n)
+    }
+}
+impl Write for Console {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stdout().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
@@ -1,12 +1,92 @@
 use core::fmt;
+use std::collections::{BTreeMap, VecDeque};
 
-use log::{log_enabled, trace, Level};
+use log::{log_enabled, trace, warn, Level};
 
 use crate::{
-    inst::{decode_inst, AddressingMode, Inst},
-    Device, Layout,
+    devices::{Interrupt, IrqSource, RdyLine},
+    inst::{base_cycles, decode_inst, has_page_penalty, nop_cycles, AddressingMode, Inst},
+    mem::XorShift,
+    BusAccess, CallFrame, CallKind, CpuModel, Device, IoPort, Layout, Profile, SymbolTable,
+    TraceEvent, TraceRecord, TraceSink,
 };
 
+/// a processor core that can be driven by a [`crate::Machine`] and debugging tools
+/// without knowing which chip it emulates.
+pub trait Cpu {
+    /// a plain-data copy of the core's programmer-visible registers.
+    type Registers: Copy + fmt::Debug;
+
+    fn reset(&mut self);
+
+    /// execute one instruction and return the clock cycles it took.
+    fn step(&mut self) -> Result<u64, ExecutionError>;
+
+    fn irq(&mut self);
+
+    /// drive the level-triggered IRQ input. while it is held an interrupt is taken before every
+    /// instruction interrupts are enabled for. cores without one take a single interrupt instead.
+    fn set_irq_line(&mut self, asserted: bool) {
+        if asserted {
+            self.irq();
+        }
+    }
+
+    fn nmi(&mut self);
+
+    /// drive the edge-triggered NMI input. asserting it latches one interrupt, and it has to be
+    /// released before it can trigger again.
+    fn set_nmi_line(&mut self, asserted: bool);
+
+    fn get_pc(&self) -> u16;
+
+    fn set_pc(&mut self, addr: u16);
+
+    /// clock cycles elapsed since the core was created or the counter was last reset.
+    /// only ever increases otherwise, also across resets of the core.
+    fn cycles(&self) -> u64;
+
+    /// start counting [`Cpu::cycles`] from zero again.
+    fn reset_cycles(&mut self);
+
+    /// if the last instruction left the core spinning in an idle loop, account for repeating it
+    /// for up to _max_cycles_ cycles without executing it and return the cycles skipped.
+    /// devices keep time one repetition at a time, and skipping stops as soon as an interrupt
+    /// would end the loop, counting the IRQ line as asserted while _irq_line_ says so,
+    /// e.g. for sources wired up outside the core.
+    fn fast_forward_idle(&mut self, max_cycles: u64, irq_line: &mut dyn FnMut() -> bool) -> u64 {
+        let _ = (max_cycles, irq_line);
+        0
+    }
+
+    fn registers(&self) -> Self::Registers;
+
+    fn set_registers(&mut self, regs: Self::Registers);
+
+    /// the memory and devices the core is wired to, e.g. for memory views in front-ends.
+    fn layout(&self) -> &Layout;
+
+    fn layout_mut(&mut self) -> &mut Layout;
+}
+
+/// whether the CPU executes instructions, see [`CPU::run_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    /// waiting for an interrupt after WAI.
+    Waiting,
+    /// stopped by STP until reset.
+    Stopped,
+    /// locked up by one of the JAM opcodes of NMOS parts until reset.
+    Jammed,
+}
+
+/// instructions remembered by [`CPU::pc_history`].
+pub const PC_HISTORY_LEN: usize = 256;
+
+/// frames kept by [`CPU::call_stack`] at most.
+const MAX_CALL_DEPTH: usize = 256;
+
 pub struct CPU {
     pc: u16,
     sp: u8,
@@ -15,11 +95,71 @@ pub struct CPU {
     y: Register,
     status: Status,
     layout: Layout,
+    model: CpuModel,
+    io_port: Option<IoPort>,
+    cycles: u64,
+    idle_loop_cycles: u8,
+    /// cycles of the current instruction not yet clocked out by [`CPU::tick_cycle`].
+    pending_cycles: u64,
+    cycle_ticking: bool,
+    /// whether the branch of the current instruction was taken to another page.
+    branch_taken: Option<bool>,
+    /// interrupts that came up after the last instruction polled for them, see [`CPU::step`].
+    nmi_deferred: bool,
+    irq_deferred: bool,
+    /// an IRQ the last instruction polled before it disabled interrupts, taken regardless.
+    irq_forced: bool,
+    irq_pending: bool,
+    nmi_pending: bool,
+    irq_line: bool,
+    nmi_line: bool,
+    /// one bit per source asserting the IRQ line, see [`CPU::assert_irq`].
+    irq_sources: u32,
+    irq_inputs: Vec<Box<dyn IrqSource>>,
+    rdy: RdyLine,
+    /// after WAI until an interrupt is requested.
+    waiting: bool,
+    /// after STP until reset.
+    stopped: bool,
+    jammed: bool,
+    undocumented: bool,
+    /// whether the indexed operand of the current instruction is in another page than its base.
+    page_crossed: bool,
 
     debug_inst: Inst,
     debug_pc: u16,
     debug_operand: DebugOp,
     debug_desc: DebugDesc,
+    /// address and data of the memory operand the last instruction read or wrote.
+    debug_access: Option<(u16, u8)>,
+    debug_addr_mode: AddressingMode,
+    debug_status: Status,
+    debug_cycles: u64,
+    trace_sink: Option<Box<dyn TraceSink>>,
+    brk_handler: Option<BrkHandler>,
+    last_brk: Option<(u8, u16)>,
+
+    write_watch: Option<u16>,
+    write_watch_hit: Option<u8>,
+    fault_checking: bool,
+    /// the first fault of the current step, see [`CPU::set_fault_checking`].
+    fault: Option<ExecutionError>,
+    /// the instruction or interrupt entry faults are blamed on.
+    fault_pc: u16,
+
+    history: VecDeque<TraceRecord>,
+    history_len: usize,
+    pc_history: Box<[u16; PC_HISTORY_LEN]>,
+    /// where the next PC goes in _pc_history_, and how many it holds.
+    pc_history_pos: usize,
+    pc_history_cnt: usize,
+    symbols: Option<SymbolTable>,
+    profile: Option<Box<Profile>>,
+    call_stack: Option<Vec<CallFrame>>,
+
+    breakpoints: BTreeMap<u16, Option<BreakCondition>>,
+    /// the breakpoint the last step stopped at, passed over by the next step.
+    resumed_breakpoint: Option<u16>,
 }
 impl fmt::Debug for CPU {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -38,15 +178,48 @@ impl fmt::Debug for CPU {
             .finish()
     }
 }
+/// a one-line register dump, `{:#}` gives a two-line monitor style dump instead.
+impl fmt::Display for CPU {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let p: u8 = self.status.into();
+        if f.alternate() {
+            writeln!(f, " PC  AC XR YR SP NV-BDIZC")?;
+            write!(
+                f,
+                "{:04X} {:02X} {:02X} {:02X} {:02X} {:08b}",
+                self.pc, self.a.data, self.x.data, self.y.data, self.sp, p
+            )
+        } else {
+            write!(
+                f,
+                "PC={:04X} A={:02X} X={:02X} Y={:02X} SP={:02X} P={:02X} {}",
+                self.pc,
+                self.a.data,
+                self.x.data,
+                self.y.data,
+                self.sp,
+                p,
+                self.status.letters()
+            )
+        }
+    }
+}
 impl Drop for CPU {
     fn drop(&mut self) {
         self.layout.detach();
     }
 }
 impl CPU {
-    /// create a 6502 microprocessor emulator.
+    /// create a 6502 microprocessor emulator of the default [`CpuModel`].
     /// _layout_ must have at least 65536 possible addresses ranging from 0x0000 to 0xFFFF.
-    pub fn new(mut layout: Layout) -> Option<Self> {
+    pub fn new(layout: Layout) -> Option<Self> {
+        Self::with_model(layout, CpuModel::default())
+    }
+
+    /// create an emulator of the given _model_, see [`CPU::new`]. the model decides which opcodes
+    /// exist, how decimal mode sets the flags, whether interrupts clear D and the quirks and
+    /// timings that differ between NMOS and CMOS parts.
+    pub fn with_model(mut layout: Layout, model: CpuModel) -> Option<Self> {
         if layout.get_byte_count() < u16::MAX as usize {
             return None;
         }
@@ -60,66 +233,393 @@ impl CPU {
             y: Default::default(),
             status: Status::default(),
             layout,
+            model,
+            io_port: None,
+            cycles: 0,
+            idle_loop_cycles: 0,
+            pending_cycles: 0,
+            cycle_ticking: false,
+            branch_taken: None,
+            nmi_deferred: false,
+            irq_deferred: false,
+            irq_forced: false,
+            irq_pending: false,
+            nmi_pending: false,
+            irq_line: false,
+            nmi_line: false,
+            irq_sources: 0,
+            irq_inputs: vec![],
+            rdy: RdyLine::new(),
+            waiting: false,
+            stopped: false,
+            jammed: false,
+            undocumented: false,
+            page_crossed: false,
             debug_inst: Inst::LDA,
             debug_pc: 0,
             debug_operand: DebugOp::Implied,
             debug_desc: DebugDesc::ChangeVal(0),
+            debug_access: None,
+            debug_addr_mode: AddressingMode::Implied,
+            debug_status: Status::default(),
+            debug_cycles: 0,
+            trace_sink: None,
+            brk_handler: None,
+            last_brk: None,
+            write_watch: None,
+            write_watch_hit: None,
+            fault_checking: false,
+            fault: None,
+            fault_pc: 0,
+            history: VecDeque::new(),
+            history_len: 0,
+            pc_history: Box::new([0; PC_HISTORY_LEN]),
+            pc_history_pos: 0,
+            pc_history_cnt: 0,
+            symbols: None,
+            profile: None,
+            call_stack: None,
+            breakpoints: BTreeMap::new(),
+            resumed_breakpoint: None,
         })
     }
 
+    /// create a 6510 microprocessor emulator, a 6502 with an I/O port at $0000 and $0001
+    /// that overrides whatever _layout_ maps there.
+    pub fn new_6510(layout: Layout) -> Option<Self> {
+        let mut cpu = Self::with_model(layout, CpuModel::NMOS6502)?;
+        cpu.io_port = Some(IoPort::new());
+        Some(cpu)
+    }
+
+    pub fn get_model(&self) -> CpuModel {
+        self.model
+    }
+
+    pub fn is_undocumented_enabled(&self) -> bool {
+        self.undocumented
+    }
+
+    /// execute the stable undocumented opcodes of NMOS parts, e.g. LAX and DCP, instead of
+    /// failing on them. CMOS parts define every opcode, so this has no effect on them.
+    pub fn set_undocumented_enabled(&mut self, enabled: bool) {
+        self.undocumented = enabled;
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    pub fn layout_mut(&mut self) -> &mut Layout {
+        &mut self.layout
+    }
+
+    pub fn io_port(&self) -> Option<&IoPort> {
+        self.io_port.as_ref()
+    }
+
+    pub fn io_port_mut(&mut self) -> Option<&mut IoPort> {
+        self.io_port.as_mut()
+    }
+
+    /// clock cycles the reset sequence takes.
+    pub const RESET_CYCLES: u64 = 7;
+
+    /// run the reset sequence, like pulling the RES line and letting it go, and reset the devices.
+    /// it runs through an interrupt entry with the stack writes suppressed, so SP drops by 3
+    /// without touching memory, interrupts get disabled and PC is loaded from the reset vector.
+    /// A, X, Y and the other flags keep their values, except D which CMOS parts clear.
     pub fn reset(&mut self) {
         self.layout.reset();
+        if let Some(port) = &mut self.io_port {
+            port.reset();
+        }
 
-        self.status = Status::default();
-        self.a = Default::default();
-        self.x = Default::default();
-        self.y = Default::default();
+        self.irq_pending = false;
+        self.nmi_pending = false;
+        self.waiting = false;
+        self.stopped = false;
+        self.jammed = false;
+        if let Some(frames) = &mut self.call_stack {
+            frames.clear();
+        }
 
-        self.sp = 0xFF;
-        self.push_byte((self.pc >> 8) as u8);
-        self.push_byte((self.pc & 0xFF) as u8);
-        self.push_byte(self.status.into());
+        self.sp = self.sp.wrapping_sub(3);
+        self.status.int_disable = true;
+        self.clear_decimal_on_interrupt();
         self.pc = self.read_word(0xFFFC);
+        self.spend_cycles(Self::RESET_CYCLES);
+    }
+
+    /// give A, X, Y, SP and the flags the random values they power on with, drawn from _seed_
+    /// so that runs repeat. call it before [`CPU::reset`], which still sets I and loads PC.
+    pub fn randomize_registers(&mut self, seed: u64) {
+        let mut rng = XorShift::new(seed ^ 0x5245_4753);
+        self.a.data = rng.next_u8();
+        self.x.data = rng.next_u8();
+        self.y.data = rng.next_u8();
+        self.sp = rng.next_u8();
+        self.status = Status::from(rng.next_u8());
+    }
+
+    pub fn run_state(&self) -> RunState {
+        match (self.jammed, self.stopped, self.waiting) {
+            (true, ..) => RunState::Jammed,
+            (_, true, _) => RunState::Stopped,
+            (_, _, true) => RunState::Waiting,
+            _ => RunState::Running,
+        }
     }
 
     pub fn is_irq_enabled(&self) -> bool {
         !self.status.int_disable
     }
 
+    /// whether the IRQ input is held, through [`CPU::set_irq_line`] or any source.
+    pub fn is_irq_line_asserted(&self) -> bool {
+        self.irq_line || self.irq_sources != 0 || self.irq_inputs.iter().any(|v| v.is_asserted())
+    }
+
+    /// wire _source_ to the IRQ input directly, so that it is sampled when instructions poll for
+    /// interrupts as their devices tick, see [`CPU::step`]. sources connected to a
+    /// [`crate::Machine`] are only sampled between steps.
+    pub fn connect_irq(&mut self, source: impl IrqSource + 'static) {
+        self.irq_inputs.push(Box::new(source));
+    }
+
+    /// hold or release the IRQ input. it is sampled between instructions and an interrupt is taken
+    /// each time interrupts are enabled while it is held, like the 6502's level-triggered input.
+    pub fn set_irq_line(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    /// hold the IRQ input on behalf of _source_ (0 to 31) until [`CPU::release_irq`],
+    /// the line stays asserted as long as any source holds it.
+    pub fn assert_irq(&mut self, source: u32) {
+        assert!(source < u32::BITS, "IRQ source out of range");
+        self.irq_sources |= 1 << source;
+    }
+
+    pub fn release_irq(&mut self, source: u32) {
+        assert!(source < u32::BITS, "IRQ source out of range");
+        self.irq_sources &= !(1 << source);
+    }
+
+    /// take an interrupt request right away, or latch it until interrupts get enabled
+    /// if they are disabled, see [`CPU::set_pending`].
     pub fn irq(&mut self) {
+        if self.stopped {
+            return;
+        }
+        // WAI resumes on a request even when interrupts are disabled
+        self.waiting = false;
         if self.status.int_disable {
             if log_enabled!(Level::Trace) {
-                trace!("IRQ IGNORED\r");
+                trace!("IRQ latched while disabled\r");
             }
+            self.irq_pending = true;
             return;
         }
+        self.enter_irq();
+    }
+
+    fn enter_irq(&mut self) {
         self.push_byte((self.pc >> 8) as u8);
         self.push_byte((self.pc & 0xFF) as u8);
         let mut status = self.status;
         status.break_ = false;
         self.push_byte(status.into());
         self.status.int_disable = true;
-        self.pc = self.read_word(0xFFFE);
+        self.clear_decimal_on_interrupt();
+        let from = self.pc;
+        self.pc = self.interrupt_vector(Interrupt::Irq);
+        self.enter_call(CallKind::Interrupt(Interrupt::Irq), from, from);
+        self.spend_cycles(7);
+    }
+
+    /// the RDY input of this CPU, to be handed to devices that stall it.
+    pub fn rdy_line(&self) -> RdyLine {
+        self.rdy.clone()
+    }
+
+    pub fn is_nmi_line_asserted(&self) -> bool {
+        self.nmi_line
+    }
+
+    /// hold or release the edge-triggered NMI input. asserting it latches one interrupt, taken
+    /// before the next instruction, and it has to be released before it can trigger again.
+    pub fn set_nmi_line(&mut self, asserted: bool) {
+        if asserted && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = asserted;
     }
 
+    /// take a non-maskable interrupt right away, whatever the state of the NMI input.
     pub fn nmi(&mut self) {
+        if self.stopped {
+            return;
+        }
+        self.waiting = false;
         self.push_byte((self.pc >> 8) as u8);
         self.push_byte((self.pc & 0xFF) as u8);
         let mut status = self.status;
         status.break_ = false;
         self.push_byte(status.into());
-        self.pc = self.read_word(0xFFFA);
+        self.status.int_disable = true;
+        self.clear_decimal_on_interrupt();
+        let from = self.pc;
+        self.pc = self.interrupt_vector(Interrupt::Nmi);
+        self.enter_call(CallKind::Interrupt(Interrupt::Nmi), from, from);
+        self.spend_cycles(7);
+    }
+
+    /// clock cycles elapsed since the CPU was created or [`CPU::reset_cycles`] was last called,
+    /// the emulated time for devices and schedulers. a reset of the CPU does not clear it.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// start counting [`CPU::cycles`] from zero again.
+    pub fn reset_cycles(&mut self) {
+        self.cycles = 0;
+    }
+
+    pub(crate) fn set_cycles(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+
+    /// whether _interrupt_ is latched to be taken before the next instruction.
+    pub fn is_pending(&self, interrupt: Interrupt) -> bool {
+        match interrupt {
+            Interrupt::Irq => self.irq_pending,
+            Interrupt::Nmi => self.nmi_pending,
+        }
+    }
+
+    /// latch _interrupt_ to be taken before the next instruction, or clear it, e.g. from a debugger.
+    /// a pending IRQ waits while interrupts are disabled.
+    pub fn set_pending(&mut self, interrupt: Interrupt, pending: bool) {
+        match interrupt {
+            Interrupt::Irq => self.irq_pending = pending,
+            Interrupt::Nmi => self.nmi_pending = pending,
+        }
     }
 
-    pub fn step(&mut self) -> Result<(), ExecutionError> {
+    /// whether an NMI and an IRQ would be taken before the next instruction with
+    /// _int_disable_ as the I flag.
+    fn poll_interrupts(&self, int_disable: bool) -> (bool, bool) {
+        let irq = self.irq_pending || self.is_irq_line_asserted();
+        (self.nmi_pending, irq && !int_disable)
+    }
+
+    /// whether an interrupt would end the idle loop, with the IRQ line also held if _irq_line_.
+    /// any interrupt wakes the CPU from WAI, a loop is only left for one that is taken.
+    fn is_idle_interrupted(&self, irq_line: bool) -> bool {
+        if self.stopped {
+            return false;
+        }
+        let irq = irq_line || self.irq_pending || self.is_irq_line_asserted();
+        self.nmi_pending || (irq && (self.waiting || !self.status.int_disable))
+    }
+
+    /// advance the cycle counter and let devices keep time with it.
+    fn spend_cycles(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        if !self.cycle_ticking {
+            self.layout.tick(cycles);
+        }
+    }
+
+    /// fetch the vector of _interrupt_, letting devices acknowledge it and override the vector.
+    fn interrupt_vector(&mut self, interrupt: Interrupt) -> u16 {
+        match self.layout.acknowledge(interrupt) {
+            Some(vector) => vector,
+            None => self.read_word(interrupt.vector_addr()),
+        }
+    }
+
+    /// execute one instruction, after taking any pending interrupt and wait states, and return
+    /// the clock cycles all of them took. while the CPU is halted by WAI, STP or RDY held low,
+    /// a single cycle passes instead. like the hardware, an instruction polls for interrupts
+    /// before its last cycle, or before the last two for a branch taken within the page, and
+    /// interrupts devices raise after that are only taken after the next instruction. CLI, SEI
+    /// and PLP change I after polling, so an IRQ is taken one instruction after CLI, and still
+    /// right after SEI.
+    pub fn step(&mut self) -> Result<u64, ExecutionError> {
+        if self.pending_cycles > 0 {
+            // finish the instruction a tick_cycle left halfway
+            self.layout.tick(self.pending_cycles);
+            self.pending_cycles = 0;
+        }
+        let start = self.cycles;
+        if self.irq_pending || self.nmi_pending || self.is_irq_line_asserted() {
+            self.waiting = false;
+        }
+        if self.stopped && self.fault_checking {
+            return Err(ExecutionError::Halted { pc: self.pc });
+        }
+        if self.waiting || self.stopped {
+            // nothing runs until an interrupt or reset, which the idle fast-forward can skip to
+            self.spend_cycles(1);
+            self.idle_loop_cycles = 1;
+            return Ok(self.cycles - start);
+        }
+        if !self.rdy.is_ready() {
+            self.spend_cycles(1);
+            self.idle_loop_cycles = 0;
+            return Ok(self.cycles - start);
+        }
+        let wait_states = self.rdy.take_wait_states();
+        if wait_states > 0 {
+            self.spend_cycles(wait_states);
+        }
+        self.fault = None;
+        self.fault_pc = self.pc;
+        let nmi_deferred = std::mem::take(&mut self.nmi_deferred);
+        let irq_deferred = std::mem::take(&mut self.irq_deferred);
+        let irq_forced = std::mem::take(&mut self.irq_forced);
+        // at most one interrupt is taken per step, an NMI wins and leaves a pending IRQ
+        // to be taken once its handler clears I
+        let irq = (self.irq_pending || self.is_irq_line_asserted()) && !self.status.int_disable;
+        if self.nmi_pending && !nmi_deferred {
+            self.nmi_pending = false;
+            self.nmi();
+        } else if irq_forced || (irq && !irq_deferred) {
+            self.irq_pending = false;
+            self.enter_irq();
+        }
+        if let Some(fault) = self.fault.take() {
+            return Err(fault);
+        }
+
+        let hit = match self.breakpoints.get(&self.pc) {
+            Some(Some(condition)) => condition(self),
+            Some(None) => true,
+            None => false,
+        };
+        if hit && self.resumed_breakpoint != Some(self.pc) {
+            self.resumed_breakpoint = Some(self.pc);
+            return Err(ExecutionError::Breakpoint(self.pc));
+        }
+        self.resumed_breakpoint = None;
+
         self.debug_pc = self.pc;
+        self.fault_pc = self.pc;
+        self.pc_history[self.pc_history_pos] = self.pc;
+        self.pc_history_pos = (self.pc_history_pos + 1) % PC_HISTORY_LEN;
+        self.pc_history_cnt = (self.pc_history_cnt + 1).min(PC_HISTORY_LEN);
         self.debug_desc = DebugDesc::Unset;
+        self.debug_access = None;
+        self.debug_status = self.status;
+        self.last_brk = None;
+        self.page_crossed = false;
+        self.branch_taken = None;
         let inst_byte = self.next_byte();
 
-        let Some((inst, addr_mode)) = decode_inst(inst_byte) else {
-            return Err(ExecutionError::UnknownInst(inst_byte));
-        };
+        let (inst, addr_mode) = decode_inst(inst_byte, self.model, self.undocumented)?;
         self.debug_inst = inst;
+        self.debug_addr_mode = addr_mode;
 
         match inst {
             Inst::LDA => {
@@ -141,6 +641,7 @@ impl CPU {
             Inst::STA => self.write_byte_addressed(self.a.data, addr_mode),
             Inst::STX => self.write_byte_addressed(self.x.data, addr_mode),
             Inst::STY => self.write_byte_addressed(self.y.data, addr_mode),
+            Inst::STZ => self.write_byte_addressed(0, addr_mode),
 
             Inst::TAX => {
                 self.x = self.a;
@@ -231,7 +732,7 @@ impl CPU {
                     self.debug_operand = DebugOp::Implied;
                     self.debug_desc = DebugDesc::ChangeVal(self.a.data);
                 } else {
-                    let (addr, mut data) = self.read_byte_addressed(addr_mode);
+                    let (addr, mut data) = self.read_modify_addressed(addr_mode);
                     data = data.wrapping_sub(1);
                     self.write_byte(addr, data);
                     self.check_nz(Register { data });
@@ -257,7 +758,7 @@ impl CPU {
                     self.debug_operand = DebugOp::Implied;
                     self.debug_desc = DebugDesc::ChangeVal(self.a.data);
                 } else {
-                    let (addr, mut data) = self.read_byte_addressed(addr_mode);
+                    let (addr, mut data) = self.read_modify_addressed(addr_mode);
                     data = data.wrapping_add(1);
                     self.write_byte(addr, data);
                     self.check_nz(Register { data });
@@ -278,29 +779,13 @@ impl CPU {
             }
 
             Inst::ADC => {
-                let operand = self.read_byte_addressed(addr_mode).1 as u16;
-                let result = (self.a.data as u16)
-                    .wrapping_add(operand)
-                    .wrapping_add(self.status.carry as u16);
-
-                self.status.carry = result > 0xFF;
-                self.status.overflow =
-                    ((result ^ self.a.data as u16) & (result ^ operand) & 0x80) > 0;
-                self.a.data = result as u8;
-                self.check_nz(self.a);
+                let operand = self.read_byte_addressed(addr_mode).1;
+                self.adc(operand);
                 self.debug_desc = DebugDesc::ChangeVal(self.a.data);
             }
             Inst::SBC => {
-                let operand = self.read_byte_addressed(addr_mode).1 ^ 0xFF;
-                let result = (self.a.data as u16)
-                    .wrapping_add(operand as u16) // invert operand to get -operand - 1
-                    .wrapping_add(self.status.carry as u16);
-
-                self.status.carry = result > 0xFF;
-                self.status.overflow =
-                    ((result ^ self.a.data as u16) & (result ^ (operand as u16)) & 0x80) > 0;
-                self.a.data = result as u8;
-                self.check_nz(self.a);
+                let operand = self.read_byte_addressed(addr_mode).1;
+                self.sbc(operand);
                 self.debug_desc = DebugDesc::ChangeVal(self.a.data);
             }
 
@@ -333,7 +818,7 @@ impl CPU {
                     self.a.data = data;
                     self.debug_operand = DebugOp::Implied;
                 } else {
-                    let read = self.read_byte_addressed(addr_mode);
+                    let read = self.read_modify_addressed(addr_mode);
                     data = read.1;
                     send_carry = (data & 0b10000000) > 0;
                     data <<= 1;
@@ -353,7 +838,7 @@ impl CPU {
                     self.a.data = data;
                     self.debug_operand = DebugOp::Implied;
                 } else {
-                    let read = self.read_byte_addressed(addr_mode);
+                    let read = self.read_modify_addressed(addr_mode);
                     data = read.1;
                     send_carry = (data & 0b1) > 0;
                     data >>= 1;
@@ -374,7 +859,7 @@ impl CPU {
                     self.a.data = data;
                     self.debug_operand = DebugOp::Implied;
                 } else {
-                    let read = self.read_byte_addressed(addr_mode);
+                    let read = self.read_modify_addressed(addr_mode);
                     data = read.1;
                     send_carry = (data & 0b10000000) > 0;
                     data <<= 1;
@@ -396,7 +881,7 @@ impl CPU {
                     self.a.data = data;
                     self.debug_operand = DebugOp::Implied;
                 } else {
-                    let read = self.read_byte_addressed(addr_mode);
+                    let read = self.read_modify_addressed(addr_mode);
                     data = read.1;
                     send_carry = (data & 0b1) > 0;
                     data >>= 1;
@@ -459,75 +944,51 @@ impl CPU {
                 self.debug_desc = DebugDesc::Compare(self.y.data, operand);
             }
 
-            Inst::BRA => {
-                let offset = self.read_byte_relative();
-                self.pc = (self.pc as i32 + offset as i32) as u16;
-            }
+            Inst::BRA => self.branch(true),
 
             Inst::BCC => {
-                let offset = self.read_byte_relative();
-                if !self.status.carry {
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                }
+                self.branch(!self.status.carry);
                 self.debug_desc = DebugDesc::Cond(self.status.carry as u8);
             }
             Inst::BCS => {
-                let offset = self.read_byte_relative();
-                if self.status.carry {
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                }
+                self.branch(self.status.carry);
                 self.debug_desc = DebugDesc::Cond(self.status.carry as u8);
             }
 
             Inst::BNE => {
-                let offset = self.read_byte_relative();
-                if !self.status.zero {
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                }
+                self.branch(!self.status.zero);
                 self.debug_desc = DebugDesc::Cond(self.status.zero as u8);
             }
             Inst::BEQ => {
-                let offset = self.read_byte_relative();
-                if self.status.zero {
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                }
+                self.branch(self.status.zero);
                 self.debug_desc = DebugDesc::Cond(self.status.zero as u8);
             }
 
             Inst::BPL => {
-                let offset = self.read_byte_relative();
-                if !self.status.negative {
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                }
+                self.branch(!self.status.negative);
                 self.debug_desc = DebugDesc::Cond(self.status.negative as u8);
             }
             Inst::BMI => {
-                let offset = self.read_byte_relative();
-                if self.status.negative {
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                }
+                self.branch(self.status.negative);
                 self.debug_desc = DebugDesc::Cond(self.status.negative as u8);
             }
 
             Inst::BVC => {
-                let offset = self.read_byte_relative();
-                if !self.status.overflow {
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                }
+                self.branch(!self.status.overflow);
                 self.debug_desc = DebugDesc::Cond(self.status.overflow as u8);
             }
             Inst::BVS => {
-                let offset = self.read_byte_relative();
-                if self.status.overflow {
-                    self.pc = (self.pc as i32 + offset as i32) as u16;
-                }
+                self.branch(self.status.overflow);
                 self.debug_desc = DebugDesc::Cond(self.status.overflow as u8);
             }
 
             Inst::JMP => match addr_mode {
                 AddressingMode::Indirect => {
                     let indirect_addr = self.next_word();
-                    let addr = self.read_word(indirect_addr);
+                    let addr = u16::from_le_bytes([
+                        self.read_byte(indirect_addr),
+                        self.read_byte(self.indirect_hi_addr(indirect_addr)),
+                    ]);
                     self.pc = addr;
                     self.debug_operand = DebugOp::Indirect(indirect_addr);
                     self.debug_desc = DebugDesc::Jmp(self.pc);
@@ -538,6 +999,13 @@ impl CPU {
                     self.debug_operand = DebugOp::Absolute(addr);
                     self.debug_desc = DebugDesc::Jmp(self.pc);
                 }
+                AddressingMode::AbsoluteXIndirect => {
+                    let abs_addr = self.next_word();
+                    let addr = self.read_word(abs_addr.wrapping_add(self.x.data as u16));
+                    self.pc = addr;
+                    self.debug_operand = DebugOp::AbsoluteXIndirect(abs_addr, self.x.data);
+                    self.debug_desc = DebugDesc::Jmp(self.pc);
+                }
                 _ => unimplemented!("JMP {:?}", addr_mode),
             },
             Inst::JSR => {
@@ -546,6 +1014,7 @@ impl CPU {
                 self.push_byte((ret_addr >> 8) as u8);
                 self.push_byte((ret_addr & 0xFF) as u8);
                 self.pc = to_addr;
+                self.enter_call(CallKind::Jsr, self.debug_pc, ret_addr.wrapping_add(1));
                 self.debug_operand = DebugOp::Absolute(self.pc);
                 self.debug_desc = DebugDesc::Jmp(self.pc);
             }
@@ -559,16 +1028,43 @@ impl CPU {
             }
 
             Inst::BRK => {
-                let pc_next = self.pc + 1;
-                self.push_byte((pc_next >> 8) as u8);
-                self.push_byte((pc_next & 0xFF) as u8);
-                let mut status = self.status;
-                status.break_ = true;
-                self.push_byte(status.into());
-                self.status.int_disable = true;
-                self.pc = self.read_word(0xFFFE);
+                let signature = self.read_byte(self.pc);
+                let pc_next = self.pc.wrapping_add(1);
+                self.last_brk = Some((signature, self.debug_pc));
                 self.debug_operand = DebugOp::Implied;
-                self.debug_desc = DebugDesc::Jmp(self.pc);
+
+                let action = match self.brk_handler.take() {
+                    Some(mut handler) => {
+                        self.pc = pc_next;
+                        let action = handler(self, signature, self.debug_pc);
+                        // the handler may have installed a replacement for itself
+                        self.brk_handler.get_or_insert(handler);
+                        action
+                    }
+                    None => BrkAction::Interrupt,
+                };
+                match action {
+                    BrkAction::Interrupt => {
+                        self.push_byte((pc_next >> 8) as u8);
+                        self.push_byte((pc_next & 0xFF) as u8);
+                        let mut status = self.status;
+                        status.break_ = true;
+                        self.push_byte(status.into());
+                        self.status.int_disable = true;
+                        self.clear_decimal_on_interrupt();
+                        // on the NMOS 6502 an NMI arriving during the BRK sequence, e.g. raised by
+                        // the handler, hijacks the vector fetch and the BRK is never seen
+                        self.pc = if self.nmi_pending && !self.model.is_cmos() {
+                            self.nmi_pending = false;
+                            self.interrupt_vector(Interrupt::Nmi)
+                        } else {
+                            self.read_word(0xFFFE)
+                        };
+                        self.enter_call(CallKind::Brk, self.debug_pc, pc_next);
+                        self.debug_desc = DebugDesc::Jmp(self.pc);
+                    }
+                    BrkAction::Handled => self.debug_desc = DebugDesc::Jmp(self.pc),
+                }
             }
             Inst::RTI => {
                 self.status = Status::from(self.pull_byte());
@@ -582,88 +1078,866 @@ impl CPU {
             Inst::BIT => {
                 let data = self.read_byte_addressed(addr_mode).1;
                 self.status.zero = (self.a.data & data) == 0;
-                self.status.negative = (data & 0b10000000) > 0;
-                self.status.overflow = (data & 0b1000000) > 0;
+                // the immediate form has no memory operand to take N and V from
+                if addr_mode != AddressingMode::Immediate {
+                    self.status.negative = (data & 0b10000000) > 0;
+                    self.status.overflow = (data & 0b1000000) > 0;
+                }
+            }
+            Inst::TRB => {
+                let (addr, data) = self.read_modify_addressed(addr_mode);
+                self.status.zero = (self.a.data & data) == 0;
+                let data = data & !self.a.data;
+                self.write_byte(addr, data);
+                self.debug_desc = DebugDesc::ChangeVal(data);
+            }
+            Inst::TSB => {
+                let (addr, data) = self.read_modify_addressed(addr_mode);
+                self.status.zero = (self.a.data & data) == 0;
+                let data = data | self.a.data;
+                self.write_byte(addr, data);
+                self.debug_desc = DebugDesc::ChangeVal(data);
+            }
+
+            Inst::RMB(bit) => {
+                let (addr, data) = self.read_modify_addressed(addr_mode);
+                let data = data & !(1 << bit);
+                self.write_byte(addr, data);
+                self.debug_desc = DebugDesc::ChangeVal(data);
+            }
+            Inst::SMB(bit) => {
+                let (addr, data) = self.read_modify_addressed(addr_mode);
+                let data = data | (1 << bit);
+                self.write_byte(addr, data);
+                self.debug_desc = DebugDesc::ChangeVal(data);
+            }
+            Inst::BBR(bit) | Inst::BBS(bit) => {
+                let zp_addr = self.next_byte();
+                let data = self.read_byte(zp_addr as u16);
+                let offset = self.next_byte() as i8;
+                let target = (self.pc as i32 + offset as i32) as u16;
+                self.debug_operand = DebugOp::ZeroPageRelative(zp_addr, target);
+
+                let is_set = data & (1 << bit) != 0;
+                if is_set == matches!(inst, Inst::BBS(_)) {
+                    self.branch_taken = Some(target & 0xFF00 != self.pc & 0xFF00);
+                    self.pc = target;
+                }
+                self.debug_desc = DebugDesc::Cond(is_set as u8);
+            }
+
+            Inst::SLO => {
+                let (addr, data) = self.read_modify_addressed(addr_mode);
+                self.status.carry = (data & 0b10000000) > 0;
+                let data = data << 1;
+                self.write_byte(addr, data);
+                self.a.data |= data;
+                self.check_nz(self.a);
+                self.debug_desc = DebugDesc::ChangeVal(self.a.data);
+            }
+            Inst::RLA => {
+                let (addr, data) = self.read_modify_addressed(addr_mode);
+                let carry = self.status.carry as u8;
+                self.status.carry = (data & 0b10000000) > 0;
+                let data = (data << 1) | carry;
+                self.write_byte(addr, data);
+                self.a.data &= data;
+                self.check_nz(self.a);
+                self.debug_desc = DebugDesc::ChangeVal(self.a.data);
+            }
+            Inst::SRE => {
+                let (addr, data) = self.read_modify_addressed(addr_mode);
+                self.status.carry = (data & 0b1) > 0;
+                let data = data >> 1;
+                self.write_byte(addr, data);
+                self.a.data ^= data;
+                self.check_nz(self.a);
+                self.debug_desc = DebugDesc::ChangeVal(self.a.data);
+            }
+            Inst::RRA => {
+                let (addr, data) = self.read_modify_addressed(addr_mode);
+                let carry = self.status.carry as u8;
+                self.status.carry = (data & 0b1) > 0;
+                let data = (data >> 1) | (carry << 7);
+                self.write_byte(addr, data);
+                self.adc(data);
+                self.debug_desc = DebugDesc::ChangeVal(self.a.data);
+            }
+            Inst::SAX => self.write_byte_addressed(self.a.data & self.x.data, addr_mode),
+            Inst::LAX => {
+                let data = self.read_byte_addressed(addr_mode).1;
+                self.a.data = data;
+                self.x.data = data;
+                self.check_nz(self.a);
+                self.debug_desc = DebugDesc::ChangeVal(data);
+            }
+            Inst::DCP => {
+                let (addr, data) = self.read_modify_addressed(addr_mode);
+                let data = data.wrapping_sub(1);
+                self.write_byte(addr, data);
+                self.check_nz(Register {
+                    data: self.a.data.wrapping_sub(data),
+                });
+                self.status.carry = self.a.data >= data;
+                self.debug_desc = DebugDesc::Compare(self.a.data, data);
+            }
+            Inst::ISC => {
+                let (addr, data) = self.read_modify_addressed(addr_mode);
+                let data = data.wrapping_add(1);
+                self.write_byte(addr, data);
+                self.sbc(data);
+                self.debug_desc = DebugDesc::ChangeVal(self.a.data);
+            }
+            Inst::ANC => {
+                self.a.data &= self.read_byte_addressed(addr_mode).1;
+                self.check_nz(self.a);
+                self.status.carry = self.status.negative;
+                self.debug_desc = DebugDesc::ChangeVal(self.a.data);
+            }
+            Inst::ALR => {
+                let data = self.a.data & self.read_byte_addressed(addr_mode).1;
+                self.status.carry = (data & 0b1) > 0;
+                self.a.data = data >> 1;
+                self.check_nz(self.a);
+                self.debug_desc = DebugDesc::ChangeVal(self.a.data);
+            }
+            Inst::ARR => {
+                let data = self.a.data & self.read_byte_addressed(addr_mode).1;
+                self.arr(data);
+                self.debug_desc = DebugDesc::ChangeVal(self.a.data);
+            }
+            Inst::SBX => {
+                let operand = self.read_byte_addressed(addr_mode).1;
+                let data = self.a.data & self.x.data;
+                self.status.carry = data >= operand;
+                self.x.data = data.wrapping_sub(operand);
+                self.check_nz(self.x);
+                self.debug_desc = DebugDesc::Compare(data, operand);
+            }
+            Inst::LAS => {
+                let data = self.read_byte_addressed(addr_mode).1 & self.sp;
+                self.a.data = data;
+                self.x.data = data;
+                self.sp = data;
+                self.check_nz(self.a);
+                self.debug_desc = DebugDesc::ChangeVal(data);
             }
 
             Inst::NOP => {
+                if addr_mode == AddressingMode::Implied {
+                    self.debug_operand = DebugOp::Implied;
+                } else {
+                    self.read_byte_addressed(addr_mode);
+                }
+            }
+            Inst::WAI => {
+                self.waiting = true;
+                self.debug_operand = DebugOp::Implied;
+            }
+            Inst::STP => {
+                self.stopped = true;
+                self.debug_operand = DebugOp::Implied;
+            }
+            Inst::JAM => {
+                self.stopped = true;
+                self.jammed = true;
                 self.debug_operand = DebugOp::Implied;
             }
         };
+        let mut cycles = match inst {
+            Inst::NOP if self.model.is_cmos() => nop_cycles(inst_byte),
+            _ => base_cycles(inst, addr_mode, self.model),
+        };
+        if self.page_crossed && has_page_penalty(inst, self.model) {
+            // the carry into the high byte of the address takes another cycle
+            cycles += 1;
+        }
+        if let Some(crossed) = self.branch_taken {
+            // a taken branch spends a cycle adding the offset and another fixing up a page crossing
+            cycles += 1 + crossed as u8;
+        }
+        if self.status.decimal && self.model.is_cmos() && matches!(inst, Inst::ADC | Inst::SBC) {
+            // the 65C02 spends a cycle fixing up the flags of decimal results
+            cycles += 1;
+        }
+        // interrupts are polled before the last cycle, or before the last two of a branch taken
+        // within the page. whatever comes up after that waits for the next instruction
+        let tail = match self.branch_taken {
+            Some(false) => 2,
+            _ => 1,
+        };
+        // CLI, SEI and PLP change I on their last cycle, after polling with the old one
+        let polled_int_disable = match inst {
+            Inst::CLI | Inst::SEI | Inst::PLP => self.debug_status.int_disable,
+            _ => self.status.int_disable,
+        };
+        self.spend_cycles(cycles as u64 - tail);
+        let (nmi_polled, irq_polled) = self.poll_interrupts(polled_int_disable);
+        self.spend_cycles(tail);
+        let (nmi, irq) = self.poll_interrupts(self.status.int_disable);
+        self.nmi_deferred = nmi && !nmi_polled;
+        self.irq_deferred = irq && !irq_polled;
+        self.irq_forced = irq_polled && !irq;
+
+        // jumping or branching onto itself has no effect besides burning cycles
+        let self_loop = self.pc == self.debug_pc
+            && matches!(
+                addr_mode,
+                AddressingMode::Relative | AddressingMode::Absolute
+            )
+            && matches!(
+                inst,
+                Inst::JMP
+                    | Inst::BRA
+                    | Inst::BCC
+                    | Inst::BCS
+                    | Inst::BEQ
+                    | Inst::BNE
+                    | Inst::BMI
+                    | Inst::BPL
+                    | Inst::BVC
+                    | Inst::BVS
+            );
+        self.idle_loop_cycles = if self_loop { cycles } else { 0 };
+        self.debug_cycles = cycles as u64;
 
         if log_enabled!(log::Level::Trace) {
             trace!("{}", self.trace_exec());
         }
+        if let Some(mut sink) = self.trace_sink.take() {
+            match sink.record(self) {
+                Ok(()) => self.trace_sink = Some(sink),
+                Err(e) => warn!("trace sink failed, tracing stopped: {}", e),
+            }
+        }
+        if self.history_len > 0 {
+            self.record_history(inst_byte, addr_mode.operand_len());
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.record(self.debug_pc, cycles as u64);
+        }
+        if let Some(fault) = self.fault.take() {
+            return Err(fault);
+        }
+        if let Some(frames) = &mut self.call_stack {
+            // a frame has returned once its return address is off the stack, whether by RTS, RTI
+            // or pulling it. an RTS to an address the program pushed itself, as in RTS dispatch
+            // tables, pulls no frame's return address and counts as a jump within the frame
+            while frames.last().is_some_and(|v| v.sp < self.sp) {
+                frames.pop();
+            }
+        }
+
+        Ok(self.cycles - start)
+    }
 
+    /// advance a single clock cycle, ticking the devices once, e.g. for peripherals that count
+    /// cycles. this is not bus accurate: an instruction executes, with all its bus accesses,
+    /// on its first cycle and the remaining cycles only tick the devices, so [`CPU::cycles`]
+    /// already counts them. [`CPU::step`] finishes a partly clocked instruction first.
+    pub fn tick_cycle(&mut self) -> Result<(), ExecutionError> {
+        if self.pending_cycles == 0 {
+            let start = self.cycles;
+            self.cycle_ticking = true;
+            let result = self.step();
+            self.cycle_ticking = false;
+            self.pending_cycles = self.cycles - start;
+            result?;
+        }
+        if self.pending_cycles > 0 {
+            self.pending_cycles -= 1;
+            self.layout.tick(1);
+        }
         Ok(())
     }
 
-    pub fn trace_exec(&self) -> String {
-        format!(
-            "{:#06x} {} {:?} {: <15} ; {}\r",
-            self.debug_pc,
-            self.status,
-            self.debug_inst,
-            match self.debug_operand {
-                DebugOp::Implied => String::new(),
-                DebugOp::Immediate(v) => format!("#${:02x}", v),
-                DebugOp::ZeroPage(v) => format!("${:02x}", v),
-                DebugOp::ZeroPageX(v, x) => format!("${:02x}, X({:#04x})", v, x),
-                DebugOp::ZeroPageY(v, y) => format!("${:02x}, Y({:#04x})", v, y),
-                DebugOp::Absolute(v) => format!("${:04x}", v),
-                DebugOp::AbsoluteX(v, x) => format!("${:04x}, X({:#04x})", v, x),
-                DebugOp::AbsoluteY(v, y) => format!("${:04x}, Y({:#04x})", v, y),
-                DebugOp::Relative(v) => format!("${:04x}", (self.pc as i32 + v as i32) as u16),
-                DebugOp::Indirect(v) => format!("(${:04x})", v),
-                DebugOp::XIndirect(v, x) => format!("(${:02x}, X({:#04x}))", v, x),
-                DebugOp::IndirectY(v, y) => format!("(${:02x}), Y({:#04x})", v, y),
-            },
-            match self.debug_desc {
-                DebugDesc::Unset => String::new(),
-                DebugDesc::ChangeVal(v) => format!("result = {:#04x}", v),
-                DebugDesc::ChangeStack(v, sp) => format!("value = {:#04x}, sp = {:#04x}", v, sp),
-                DebugDesc::Compare(reg, operand) =>
-                    format!("reg = {:#04x}, operand = {:#04x}", reg, operand),
-                DebugDesc::Cond(v) => format!(
-                    "flag is {}",
-                    match v {
-                        0 => "cleared",
-                        1 => "set",
-                        _ => unimplemented!("DebugDesc::Cond {}", v),
-                    }
-                ),
-                DebugDesc::Jmp(v) => format!("addr = {:#06x}", v),
-                DebugDesc::Restore(pc) => format!("pc = {:#06x}", pc),
+    /// whether an instruction started by [`CPU::tick_cycle`] still has cycles to clock out.
+    pub fn is_mid_instruction(&self) -> bool {
+        self.pending_cycles > 0
+    }
+
+    /// execute instructions until at least _cycles_ clock cycles have passed and return how many
+    /// cycles the last instruction ran past the budget, to take off the next one.
+    pub fn run_for_cycles(&mut self, cycles: u64) -> Result<u64, ExecutionError> {
+        let end = self.cycles + cycles;
+        while self.cycles < end {
+            self.step()?;
+        }
+        Ok(self.cycles - end)
+    }
+
+    /// execute instructions until _done_ returns true, e.g. when PC reaches an address,
+    /// and return the clock cycles they took. _done_ is checked before every instruction.
+    pub fn run_until(&mut self, mut done: impl FnMut(&CPU) -> bool) -> Result<u64, ExecutionError> {
+        let start = self.cycles;
+        while !done(self) {
+            self.step()?;
+        }
+        Ok(self.cycles - start)
+    }
+
+    /// execute the instruction at PC like [`CPU::step`], running a subroutine it calls with JSR
+    /// until it returns, and return the clock cycles it all took. errors stop it early,
+    /// e.g. a breakpoint within the subroutine.
+    pub fn step_over(&mut self) -> Result<u64, ExecutionError> {
+        let start = self.cycles;
+        let (pc, sp) = (self.pc, self.sp);
+        let is_jsr = self.peek_byte(pc) == Some(0x20);
+        self.step()?;
+        if is_jsr {
+            let return_addr = pc.wrapping_add(3);
+            // SP tells the return from recursive calls of the same subroutine apart
+            self.run_until(|cpu| cpu.pc == return_addr && cpu.sp >= sp)?;
+        }
+        Ok(self.cycles - start)
+    }
+
+    /// run until the innermost frame of [`CPU::call_stack`] returns and return the clock cycles
+    /// it took. with no frame to return from, e.g. while call stack tracking is off, it executes
+    /// a single instruction like [`CPU::step`]. errors stop it early.
+    pub fn step_out(&mut self) -> Result<u64, ExecutionError> {
+        let depth = self.call_stack().len();
+        if depth == 0 {
+            return self.step();
+        }
+        self.run_until(|cpu| cpu.call_stack().len() < depth)
+    }
+
+    /// stop before executing the instruction at _addr_, [`CPU::step`] returns
+    /// [`ExecutionError::Breakpoint`] instead. stepping again executes the instruction.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr, None);
+    }
+
+    /// like [`CPU::add_breakpoint`], but only stop when _condition_ holds as PC reaches _addr_,
+    /// e.g. `|cpu| cpu.get_a() == 0xFF`. replaces any breakpoint at _addr_.
+    pub fn add_conditional_breakpoint(
+        &mut self,
+        addr: u16,
+        condition: impl Fn(&CPU) -> bool + 'static,
+    ) {
+        self.breakpoints.insert(addr, Some(Box::new(condition)));
+    }
+
+    /// false if there was no breakpoint at _addr_.
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr).is_some()
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    /// keep the last _len_ executed instructions to look back at, e.g. after an error.
+    /// 0, the default, keeps none.
+    pub fn set_history_len(&mut self, len: usize) {
+        self.history_len = len;
+        while self.history.len() > len {
+            self.history.pop_front();
+        }
+    }
+
+    /// the kept instructions, oldest first, see [`CPU::set_history_len`].
+    pub fn history(&self) -> impl Iterator<Item = &TraceRecord> + '_ {
+        self.history.iter()
+    }
+
+    /// the addresses of the last [`PC_HISTORY_LEN`] instructions, oldest first, always kept
+    /// unlike [`CPU::history`]. after [`CPU::step`] failed, the last one is where it failed,
+    /// e.g. an undefined opcode, and the rest show how execution got there.
+    pub fn pc_history(&self) -> impl Iterator<Item = u16> + '_ {
+        let start = (self.pc_history_pos + PC_HISTORY_LEN - self.pc_history_cnt) % PC_HISTORY_LEN;
+        (0..self.pc_history_cnt).map(move |i| self.pc_history[(start + i) % PC_HISTORY_LEN])
+    }
+
+    fn record_history(&mut self, opcode: u8, operand_len: u16) {
+        let pc = self.debug_pc;
+        let operand = [1, 2].map(|i| self.peek_byte(pc.wrapping_add(i)).unwrap_or(0));
+        if self.history.len() == self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back(TraceRecord {
+            pc,
+            opcode,
+            operand,
+            operand_len: operand_len as u8,
+            regs: self.registers(),
+            cycles: self.cycles,
+        });
+    }
+
+    /// count the executions and cycles of every instruction address, e.g. to find hot loops,
+    /// see [`Profile::hot_spots`]. turning it off drops the counts.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        if !enabled {
+            self.profile = None;
+        } else if self.profile.is_none() {
+            self.profile = Some(Box::default());
+        }
+    }
+
+    /// the counts since profiling was turned on or cleared, None while it is off.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    pub fn profile_mut(&mut self) -> Option<&mut Profile> {
+        self.profile.as_deref_mut()
+    }
+
+    /// keep a shadow call stack of the subroutines and interrupt handlers entered, e.g. for
+    /// showing a backtrace in a debugger. frames are matched to returns by the stack pointer,
+    /// so programs that move SP themselves may leave stale frames. turning it off drops them.
+    pub fn set_call_stack_tracking(&mut self, enabled: bool) {
+        if !enabled {
+            self.call_stack = None;
+        } else if self.call_stack.is_none() {
+            self.call_stack = Some(vec![]);
+        }
+    }
+
+    /// the frames being run, outermost first. empty while tracking is off,
+    /// see [`CPU::set_call_stack_tracking`].
+    pub fn call_stack(&self) -> &[CallFrame] {
+        self.call_stack.as_deref().unwrap_or_default()
+    }
+
+    fn enter_call(&mut self, kind: CallKind, caller: u16, return_addr: u16) {
+        let Some(frames) = &mut self.call_stack else {
+            return;
+        };
+        if frames.len() == MAX_CALL_DEPTH {
+            // runaway recursion wraps the stack, forget the outermost frames
+            frames.remove(0);
+        }
+        frames.push(CallFrame {
+            kind,
+            caller,
+            target: self.pc,
+            return_addr,
+            sp: self.sp,
+        });
+    }
+
+    /// call _handler_ with the signature byte following each BRK and the address of the BRK,
+    /// e.g. for implementing system calls on the host. the handler runs with PC past the
+    /// signature byte and decides whether the CPU still takes the interrupt.
+    pub fn set_brk_handler(&mut self, handler: Option<BrkHandler>) {
+        self.brk_handler = handler;
+    }
+
+    /// the signature byte and address of the BRK the last step executed, if it was one.
+    pub fn last_brk(&self) -> Option<(u8, u16)> {
+        self.last_brk
+    }
+
+    /// record every executed instruction to _sink_, replacing any previous one.
+    /// a sink that fails is dropped.
+    pub fn set_trace_sink(&mut self, sink: Option<Box<dyn TraceSink>>) {
+        self.trace_sink = sink;
+    }
+
+    /// show the operands of traced instructions by their symbol where they have one.
+    pub fn set_symbols(&mut self, symbols: Option<SymbolTable>) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> Option<&SymbolTable> {
+        self.symbols.as_ref()
+    }
+
+    pub fn trace_exec(&self) -> String {
+        format!(
+            "{:#06x} {} {} {: <15} ; {}\r",
+            self.debug_pc,
+            self.status,
+            self.debug_inst,
+            self.trace_operand(),
+            match self.debug_desc {
+                DebugDesc::Unset => String::new(),
+                DebugDesc::ChangeVal(v) => format!("result = {:#04x}", v),
+                DebugDesc::ChangeStack(v, sp) => format!("value = {:#04x}, sp = {:#04x}", v, sp),
+                DebugDesc::Compare(reg, operand) =>
+                    format!("reg = {:#04x}, operand = {:#04x}", reg, operand),
+                DebugDesc::Cond(v) => format!(
+                    "flag is {}",
+                    match v {
+                        0 => "cleared",
+                        1 => "set",
+                        _ => unimplemented!("DebugDesc::Cond {}", v),
+                    }
+                ),
+                DebugDesc::Jmp(v) => format!("addr = {:#06x}", v),
+                DebugDesc::Restore(pc) => format!("pc = {:#06x}", pc),
             }
         )
     }
 
+    /// step through instructions as an iterator of their [`CPU::trace_event`], e.g. to drive
+    /// a test with `take_while` or `find`. errors are yielded and stepping goes on after them.
+    /// it ends once the CPU stops executing instructions by itself after STP, WAI or a JAM.
+    pub fn iter_steps(&mut self) -> Steps<'_> {
+        Steps { cpu: self }
+    }
+
+    /// the last executed instruction as data, e.g. for debugger front-ends.
+    pub fn trace_event(&self) -> TraceEvent {
+        TraceEvent {
+            pc: self.debug_pc,
+            mnemonic: self.debug_inst.to_string(),
+            addr_mode: self.debug_addr_mode,
+            effective_addr: self.debug_access.map(|v| v.0),
+            value: self.debug_access.map(|v| v.1),
+            cycles: self.debug_cycles,
+            status_before: self.debug_status.into(),
+            status_after: self.status.into(),
+        }
+    }
+
+    /// address and mnemonic of the last executed instruction.
+    pub(crate) fn trace_inst(&self) -> (u16, Inst) {
+        (self.debug_pc, self.debug_inst)
+    }
+
+    /// operand of the last executed instruction as shown in traces.
+    /// addresses with a symbol show as the symbol, see [`CPU::set_symbols`].
+    pub(crate) fn trace_operand(&self) -> String {
+        let name = |addr: u16| self.symbols.as_ref()?.name(addr).map(|v| v.to_owned());
+        let zp = |v: u8| name(v as u16).unwrap_or_else(|| format!("${:02x}", v));
+        let abs = |v: u16| name(v).unwrap_or_else(|| format!("${:04x}", v));
+        match self.debug_operand {
+            DebugOp::Implied => String::new(),
+            DebugOp::Immediate(v) => format!("#${:02x}", v),
+            DebugOp::ZeroPage(v) => zp(v),
+            DebugOp::ZeroPageX(v, x) => format!("{}, X({:#04x})", zp(v), x),
+            DebugOp::ZeroPageY(v, y) => format!("{}, Y({:#04x})", zp(v), y),
+            DebugOp::Absolute(v) => abs(v),
+            DebugOp::AbsoluteX(v, x) => format!("{}, X({:#04x})", abs(v), x),
+            DebugOp::AbsoluteY(v, y) => format!("{}, Y({:#04x})", abs(v), y),
+            DebugOp::Relative(v) => abs((self.pc as i32 + v as i32) as u16),
+            DebugOp::Indirect(v) => format!("({})", abs(v)),
+            DebugOp::XIndirect(v, x) => format!("({}, X({:#04x}))", zp(v), x),
+            DebugOp::IndirectY(v, y) => format!("({}), Y({:#04x})", zp(v), y),
+            DebugOp::ZeroPageIndirect(v) => format!("({})", zp(v)),
+            DebugOp::AbsoluteXIndirect(v, x) => format!("({}, X({:#04x}))", abs(v), x),
+            DebugOp::ZeroPageRelative(v, target) => format!("{}, {}", zp(v), abs(target)),
+        }
+    }
+
+    /// what the instruction at _pc_ would access if it executed now, worked out through
+    /// [`Device::peek`] so that no device observes it. branches give their target
+    /// whether or not they would be taken.
+    pub fn effective_operand(&self, pc: u16) -> Result<EffectiveOperand, ExecutionError> {
+        let Some(opcode) = self.peek_byte(pc) else {
+            return Ok(EffectiveOperand::default());
+        };
+        let (inst, addr_mode) = decode_inst(opcode, self.model, self.undocumented)?;
+        let arg = pc.wrapping_add(1);
+
+        let is_jump = matches!(
+            inst,
+            Inst::JMP | Inst::JSR | Inst::RTS | Inst::RTI | Inst::BRK
+        ) || addr_mode == AddressingMode::Relative;
+        let addr = self.operand_addr(inst, addr_mode, arg);
+        let value = match addr_mode {
+            AddressingMode::Immediate => self.peek_byte(arg),
+            _ if is_jump => None,
+            _ => addr.and_then(|addr| self.peek_byte(addr)),
+        };
+        Ok(EffectiveOperand { addr, value })
+    }
+
+    fn operand_addr(&self, inst: Inst, addr_mode: AddressingMode, arg: u16) -> Option<u16> {
+        let stack = |n: u8| self.peek_byte(0x100 + self.sp.wrapping_add(n) as u16);
+        Some(match (inst, addr_mode) {
+            (Inst::RTS, _) => u16::from_le_bytes([stack(1)?, stack(2)?]).wrapping_add(1),
+            (Inst::RTI, _) => u16::from_le_bytes([stack(2)?, stack(3)?]),
+            (Inst::BRK, _) => self.peek_word(0xFFFE)?,
+            (_, AddressingMode::Implied | AddressingMode::Immediate) => return None,
+            (_, AddressingMode::Absolute) => self.peek_word(arg)?,
+            (_, AddressingMode::AbsoluteX) => self.peek_word(arg)?.wrapping_add(self.x.data as u16),
+            (_, AddressingMode::AbsoluteY) => self.peek_word(arg)?.wrapping_add(self.y.data as u16),
+            (_, AddressingMode::Indirect) => {
+                let indirect_addr = self.peek_word(arg)?;
+                u16::from_le_bytes([
+                    self.peek_byte(indirect_addr)?,
+                    self.peek_byte(self.indirect_hi_addr(indirect_addr))?,
+                ])
+            }
+            (_, AddressingMode::XIndirect) => {
+                self.peek_zp_word(self.peek_byte(arg)?.wrapping_add(self.x.data))?
+            }
+            (_, AddressingMode::IndirectY) => self
+                .peek_zp_word(self.peek_byte(arg)?)?
+                .wrapping_add(self.y.data as u16),
+            (_, AddressingMode::Relative) => {
+                let offset = self.peek_byte(arg)? as i8;
+                arg.wrapping_add(1).wrapping_add(offset as u16)
+            }
+            (_, AddressingMode::ZeroPage | AddressingMode::ZeroPageRelative) => {
+                self.peek_byte(arg)? as u16
+            }
+            (_, AddressingMode::ZeroPageX) => self.peek_byte(arg)?.wrapping_add(self.x.data) as u16,
+            (_, AddressingMode::ZeroPageY) => self.peek_byte(arg)?.wrapping_add(self.y.data) as u16,
+            (_, AddressingMode::ZeroPageIndirect) => self.peek_zp_word(self.peek_byte(arg)?)?,
+            (_, AddressingMode::AbsoluteXIndirect) => {
+                self.peek_word(self.peek_word(arg)?.wrapping_add(self.x.data as u16))?
+            }
+        })
+    }
+
+    /// where JMP (_indirect_addr_) fetches the high byte of its target from. NMOS parts
+    /// do not carry into the high byte of the pointer, so a pointer at $xxFF wraps around its page.
+    fn indirect_hi_addr(&self, indirect_addr: u16) -> u16 {
+        if self.model.is_cmos() {
+            indirect_addr.wrapping_add(1)
+        } else {
+            (indirect_addr & 0xFF00) | (indirect_addr.wrapping_add(1) & 0x00FF)
+        }
+    }
+
+    /// what a read of _addr_ would return, without the side effects of reading devices,
+    /// e.g. for debuggers. None where the device has no side-effect free view, see [`Device::peek`].
+    pub fn peek_byte(&self, addr: u16) -> Option<u8> {
+        if let (Some(port), 0x0000..=0x0001) = (&self.io_port, addr) {
+            return Some(port.read(addr));
+        }
+        self.layout.peek(addr as usize)
+    }
+
+    /// change _addr_ without the side effects of writing devices, also in ROM,
+    /// e.g. for debuggers patching memory. None where the device refuses, see [`Device::poke`].
+    pub fn poke_byte(&mut self, addr: u16, data: u8) -> Option<()> {
+        if let (Some(port), 0x0000..=0x0001) = (&mut self.io_port, addr) {
+            port.write(addr, data);
+            return Some(());
+        }
+        self.layout.poke(addr as usize, data)
+    }
+
+    fn peek_word(&self, addr: u16) -> Option<u16> {
+        Some(u16::from_le_bytes([
+            self.peek_byte(addr)?,
+            self.peek_byte(addr.wrapping_add(1))?,
+        ]))
+    }
+
+    /// like [`CPU::read_zp_word`], the high byte of a pointer at $FF comes from $00.
+    fn peek_zp_word(&self, zp_addr: u8) -> Option<u16> {
+        Some(u16::from_le_bytes([
+            self.peek_byte(zp_addr as u16)?,
+            self.peek_byte(zp_addr.wrapping_add(1) as u16)?,
+        ]))
+    }
+
+    fn adc(&mut self, operand: u8) {
+        if self.status.decimal {
+            self.adc_decimal(operand);
+            return;
+        }
+        let operand = operand as u16;
+        let result = (self.a.data as u16)
+            .wrapping_add(operand)
+            .wrapping_add(self.status.carry as u16);
+
+        self.status.carry = result > 0xFF;
+        self.status.overflow = ((result ^ self.a.data as u16) & (result ^ operand) & 0x80) > 0;
+        self.a.data = result as u8;
+        self.check_nz(self.a);
+    }
+
+    fn sbc(&mut self, operand: u8) {
+        if self.status.decimal {
+            self.sbc_decimal(operand);
+            return;
+        }
+        let operand = operand ^ 0xFF;
+        let result = (self.a.data as u16)
+            .wrapping_add(operand as u16) // invert operand to get -operand - 1
+            .wrapping_add(self.status.carry as u16);
+
+        self.status.carry = result > 0xFF;
+        self.status.overflow =
+            ((result ^ self.a.data as u16) & (result ^ (operand as u16)) & 0x80) > 0;
+        self.a.data = result as u8;
+        self.check_nz(self.a);
+    }
+
+    /// the undocumented ARR on _data_, A AND the operand. it runs through the adder, so decimal
+    /// mode fixes up the rotated result nibble by nibble like ADC would.
+    fn arr(&mut self, data: u8) {
+        let result = (data >> 1) | ((self.status.carry as u8) << 7);
+        self.check_nz(Register { data: result });
+        if !self.status.decimal {
+            self.a.data = result;
+            self.status.carry = (result & 0b1000000) > 0;
+            self.status.overflow = ((result >> 6) ^ (result >> 5)) & 1 > 0;
+            return;
+        }
+
+        self.status.overflow = ((data ^ result) & 0b1000000) > 0;
+        let (hi, lo) = (data >> 4, data & 0x0F);
+        let mut result = result;
+        if lo + (lo & 1) > 5 {
+            result = (result & 0xF0) | (result.wrapping_add(6) & 0x0F);
+        }
+        self.status.carry = hi + (hi & 1) > 5;
+        if self.status.carry {
+            result = result.wrapping_add(0x60);
+        }
+        self.a.data = result;
+    }
+
+    /// decimal mode addition. CMOS parts set N and Z from the decimal result, NMOS parts derive
+    /// them from intermediate results which is modelled here too. V comes from the sum before
+    /// the high nibble gets adjusted on both.
+    fn adc_decimal(&mut self, operand: u8) {
+        let (a, b, c) = (self.a.data as i16, operand as i16, self.status.carry as i16);
+
+        let mut lo = (a & 0x0F) + (b & 0x0F) + c;
+        if lo >= 0x0A {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+        let mut result = (a & 0xF0) + (b & 0xF0) + lo;
+        // N and V come from the sum before the high nibble gets adjusted
+        let signed = (a as u8 as i8 as i16 & !0x0F) + (b as u8 as i8 as i16 & !0x0F) + lo;
+        if result >= 0xA0 {
+            result += 0x60;
+        }
+
+        self.status.carry = result >= 0x100;
+        self.status.overflow = !(-128..=127).contains(&signed);
+        self.a.data = result as u8;
+        if self.model.is_cmos() {
+            self.check_nz(self.a);
+        } else {
+            self.status.negative = (signed & 0x80) > 0;
+            self.status.zero = (a + b + c) & 0xFF == 0;
+        }
+    }
+
+    /// decimal mode subtraction. flags follow the binary subtraction on NMOS parts,
+    /// CMOS parts give valid N and Z for the decimal result.
+    fn sbc_decimal(&mut self, operand: u8) {
+        let (a, b, borrow) = (
+            self.a.data as i16,
+            operand as i16,
+            1 - self.status.carry as i16,
+        );
+
+        let binary = a - b - borrow;
+        self.status.carry = binary >= 0;
+        self.status.overflow = ((a ^ binary) & (a ^ b) & 0x80) > 0;
+
+        let lo = (a & 0x0F) - (b & 0x0F) - borrow;
+        let result = if self.model.is_cmos() {
+            let mut result = binary;
+            if result < 0 {
+                result -= 0x60;
+            }
+            if lo < 0 {
+                result -= 0x06;
+            }
+            result
+        } else {
+            let lo = if lo < 0 {
+                ((lo - 0x06) & 0x0F) - 0x10
+            } else {
+                lo
+            };
+            let mut result = (a & 0xF0) - (b & 0xF0) + lo;
+            if result < 0 {
+                result -= 0x60;
+            }
+            result
+        };
+
+        self.a.data = result as u8;
+        if self.model.is_cmos() {
+            self.check_nz(self.a);
+        } else {
+            self.check_nz(Register { data: binary as u8 });
+        }
+    }
+
+    /// CMOS parts enter interrupt handlers in binary mode, NMOS parts keep the D flag as is.
+    fn clear_decimal_on_interrupt(&mut self) {
+        if self.model.is_cmos() {
+            self.status.decimal = false;
+        }
+    }
+
     fn check_nz(&mut self, reg: Register) {
         self.status.negative = reg.is_negative();
         self.status.zero = reg.is_zero();
     }
 
     fn push_byte(&mut self, data: u8) {
-        self.write_byte(self.get_sp(), data);
+        if self.sp == 0x00 {
+            self.fault(ExecutionError::StackFault {
+                addr: self.stack_addr(),
+                pc: self.fault_pc,
+                access: BusAccess::Write,
+            });
+        }
+        self.write_byte(self.stack_addr(), data);
         self.sp = self.sp.wrapping_sub(1);
     }
 
     fn pull_byte(&mut self) -> u8 {
         self.sp = self.sp.wrapping_add(1);
-        self.read_byte(self.get_sp())
+        if self.sp == 0x00 {
+            self.fault(ExecutionError::StackFault {
+                addr: self.stack_addr(),
+                pc: self.fault_pc,
+                access: BusAccess::Read,
+            });
+        }
+        self.read_byte(self.stack_addr())
     }
 
-    fn get_sp(&self) -> u16 {
+    fn stack_addr(&self) -> u16 {
         self.sp as u16 + 0x100
     }
 
+    /// read the offset of a relative branch and take it if _taken_.
+    fn branch(&mut self, taken: bool) {
+        let offset = self.read_byte_relative();
+        if taken {
+            let target = (self.pc as i32 + offset as i32) as u16;
+            self.branch_taken = Some(target & 0xFF00 != self.pc & 0xFF00);
+            self.pc = target;
+        }
+    }
+
     fn read_byte_relative(&mut self) -> i8 {
         let rel_addr = self.next_byte() as i8;
         self.debug_operand = DebugOp::Relative(rel_addr);
         rel_addr
     }
 
+    /// read the operand of a read-modify-write instruction along with the dummy access the
+    /// hardware makes before writing the result: the NMOS 6502 writes the unmodified value back,
+    /// the 65C02 reads it again.
+    fn read_modify_addressed(&mut self, addr_mode: AddressingMode) -> (u16, u8) {
+        let (addr, data) = self.read_byte_addressed(addr_mode);
+        if self.model.is_cmos() {
+            self.read_byte(addr);
+        } else {
+            self.write_byte(addr, data);
+        }
+        (addr, data)
+    }
+
     fn read_byte_addressed(&mut self, addr_mode: AddressingMode) -> (u16, u8) {
+        let (addr, data) = self.fetch_operand(addr_mode);
+        if addr_mode != AddressingMode::Immediate {
+            self.debug_access = Some((addr, data));
+        }
+        (addr, data)
+    }
+
+    fn fetch_operand(&mut self, addr_mode: AddressingMode) -> (u16, u8) {
         match addr_mode {
             AddressingMode::Implied => unimplemented!("Implied addressing mode"),
             AddressingMode::Immediate => {
@@ -679,12 +1953,14 @@ impl CPU {
             AddressingMode::AbsoluteX => {
                 let abs_addr = self.next_word();
                 let addr = abs_addr.wrapping_add(self.x.data as u16);
+                self.page_crossed = crosses_page(abs_addr, addr);
                 self.debug_operand = DebugOp::AbsoluteX(abs_addr, self.x.data);
                 (addr, self.read_byte(addr))
             }
             AddressingMode::AbsoluteY => {
                 let abs_addr = self.next_word();
                 let addr = abs_addr.wrapping_add(self.y.data as u16);
+                self.page_crossed = crosses_page(abs_addr, addr);
                 self.debug_operand = DebugOp::AbsoluteY(abs_addr, self.y.data);
                 (addr, self.read_byte(addr))
             }
@@ -692,13 +1968,15 @@ impl CPU {
             AddressingMode::XIndirect => {
                 let zp_addr = self.next_byte();
                 let indexed = zp_addr.wrapping_add(self.x.data);
-                let addr = self.read_word(indexed as u16);
+                let addr = self.read_zp_word(indexed);
                 self.debug_operand = DebugOp::XIndirect(zp_addr, self.x.data);
                 (addr, self.read_byte(addr))
             }
             AddressingMode::IndirectY => {
                 let zp_addr = self.next_byte();
-                let addr = self.read_word(zp_addr as u16) + self.y.data as u16;
+                let base_addr = self.read_zp_word(zp_addr);
+                let addr = base_addr.wrapping_add(self.y.data as u16);
+                self.page_crossed = crosses_page(base_addr, addr);
                 self.debug_operand = DebugOp::IndirectY(zp_addr, self.y.data);
                 (addr, self.read_byte(addr))
             }
@@ -720,62 +1998,88 @@ impl CPU {
                 self.debug_operand = DebugOp::ZeroPageY(zp_addr, self.y.data);
                 (addr, self.read_byte(addr))
             }
+            AddressingMode::ZeroPageIndirect => {
+                let zp_addr = self.next_byte();
+                let addr = self.read_zp_word(zp_addr);
+                self.debug_operand = DebugOp::ZeroPageIndirect(zp_addr);
+                (addr, self.read_byte(addr))
+            }
+            AddressingMode::AbsoluteXIndirect => {
+                unreachable!("AbsoluteXIndirect addressing mode is handled by JMP")
+            }
+            AddressingMode::ZeroPageRelative => {
+                unreachable!("ZeroPageRelative addressing mode is handled by BBR and BBS")
+            }
         }
     }
 
     fn write_byte_addressed(&mut self, data: u8, addr_mode: AddressingMode) {
-        match addr_mode {
+        let addr = match addr_mode {
             AddressingMode::Implied => unimplemented!("Implied addressing mode"),
             AddressingMode::Immediate => unimplemented!("Immediate addressing mode"),
             AddressingMode::Absolute => {
                 let addr = self.next_word();
                 self.debug_operand = DebugOp::Absolute(addr);
-                self.write_byte(addr, data);
+                addr
             }
             AddressingMode::AbsoluteX => {
                 let abs_addr = self.next_word();
                 let addr = abs_addr.wrapping_add(self.x.data as u16);
                 self.debug_operand = DebugOp::AbsoluteX(abs_addr, self.x.data);
-                self.write_byte(addr, data);
+                addr
             }
             AddressingMode::AbsoluteY => {
                 let abs_addr = self.next_word();
                 let addr = abs_addr.wrapping_add(self.y.data as u16);
                 self.debug_operand = DebugOp::AbsoluteY(abs_addr, self.y.data);
-                self.write_byte(addr, data);
+                addr
             }
             AddressingMode::Indirect => unimplemented!("Indirect addressing mode"),
             AddressingMode::XIndirect => {
                 let zp_addr = self.next_byte();
-                let addr = self.read_word(zp_addr.wrapping_add(self.x.data) as u16);
+                let addr = self.read_zp_word(zp_addr.wrapping_add(self.x.data));
                 self.debug_operand = DebugOp::XIndirect(zp_addr, self.x.data);
-                self.write_byte(addr, data);
+                addr
             }
             AddressingMode::IndirectY => {
                 let zp_addr = self.next_byte();
-                let addr = self.read_word(zp_addr as u16) + self.y.data as u16;
+                let addr = self.read_zp_word(zp_addr).wrapping_add(self.y.data as u16);
                 self.debug_operand = DebugOp::IndirectY(zp_addr, self.y.data);
-                self.write_byte(addr, data);
+                addr
             }
             AddressingMode::Relative => unimplemented!("Relative addressing mode"),
             AddressingMode::ZeroPage => {
                 let zp_addr = self.next_byte();
                 self.debug_operand = DebugOp::ZeroPage(zp_addr);
-                self.write_byte(zp_addr as u16, data);
+                zp_addr as u16
             }
             AddressingMode::ZeroPageX => {
                 let zp_addr = self.next_byte();
                 let addr = zp_addr.wrapping_add(self.x.data) as u16;
                 self.debug_operand = DebugOp::ZeroPageX(zp_addr, self.x.data);
-                self.write_byte(addr, data);
+                addr
             }
             AddressingMode::ZeroPageY => {
                 let zp_addr = self.next_byte();
                 let addr = zp_addr.wrapping_add(self.y.data) as u16;
                 self.debug_operand = DebugOp::ZeroPageY(zp_addr, self.y.data);
-                self.write_byte(addr, data);
+                addr
             }
-        }
+            AddressingMode::ZeroPageIndirect => {
+                let zp_addr = self.next_byte();
+                let addr = self.read_zp_word(zp_addr);
+                self.debug_operand = DebugOp::ZeroPageIndirect(zp_addr);
+                addr
+            }
+            AddressingMode::AbsoluteXIndirect => {
+                unreachable!("AbsoluteXIndirect addressing mode is handled by JMP")
+            }
+            AddressingMode::ZeroPageRelative => {
+                unreachable!("ZeroPageRelative addressing mode is handled by BBR and BBS")
+            }
+        };
+        self.debug_access = Some((addr, data));
+        self.write_byte(addr, data);
     }
 
     fn next_byte(&mut self) -> u8 {
@@ -791,12 +2095,20 @@ impl CPU {
     }
 
     pub fn read_byte(&mut self, addr: u16) -> u8 {
+        if let (Some(port), 0x0000..=0x0001) = (&self.io_port, addr) {
+            return port.read(addr);
+        }
         match self.layout.read(addr as usize) {
             Some(v) => v,
             None => {
                 if log_enabled!(Level::Trace) {
                     trace!("read byte at {:#06x} failed", addr);
                 }
+                self.fault(ExecutionError::UnmappedAccess {
+                    addr,
+                    pc: self.fault_pc,
+                    access: BusAccess::Read,
+                });
                 0
             }
         }
@@ -804,13 +2116,65 @@ impl CPU {
 
     fn read_word(&mut self, addr: u16) -> u16 {
         let lo = self.read_byte(addr) as u16;
-        let hi = self.read_byte(addr + 1) as u16;
+        let hi = self.read_byte(addr.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    /// read a pointer from the zero page, wrapping around within it like the CPU does.
+    fn read_zp_word(&mut self, zp_addr: u8) -> u16 {
+        let lo = self.read_byte(zp_addr as u16) as u16;
+        let hi = self.read_byte(zp_addr.wrapping_add(1) as u16) as u16;
         (hi << 8) | lo
     }
 
     pub fn write_byte(&mut self, addr: u16, data: u8) {
-        // not going to verify write result
-        self.layout.write(addr as usize, data);
+        if self.write_watch == Some(addr) {
+            self.write_watch_hit = Some(data);
+        }
+        if let (Some(port), 0x0000..=0x0001) = (&mut self.io_port, addr) {
+            port.write(addr, data);
+            return;
+        }
+        if self.layout.write(addr as usize, data).is_none() && self.fault_checking {
+            let pc = self.fault_pc;
+            self.fault(match self.layout.is_mapped(addr as usize) {
+                true => ExecutionError::RomWrite { addr, pc },
+                false => ExecutionError::UnmappedAccess {
+                    addr,
+                    pc,
+                    access: BusAccess::Write,
+                },
+            });
+        }
+    }
+
+    /// make [`CPU::step`] fail on bus faults instead of carrying on, e.g. to find wiring bugs:
+    /// reads nothing answers, which read as 0 otherwise, refused writes, SP wrapping around
+    /// the stack page and stepping a CPU stopped by STP or JAM. the faulting instruction still
+    /// completes, and the first fault is returned after it. off by default.
+    pub fn set_fault_checking(&mut self, enabled: bool) {
+        self.fault_checking = enabled;
+    }
+
+    pub fn is_fault_checking(&self) -> bool {
+        self.fault_checking
+    }
+
+    fn fault(&mut self, fault: ExecutionError) {
+        if self.fault_checking && self.fault.is_none() {
+            self.fault = Some(fault);
+        }
+    }
+
+    /// watch _addr_ for writes, replacing any previous watch.
+    pub(crate) fn watch_write(&mut self, addr: Option<u16>) {
+        self.write_watch = addr;
+        self.write_watch_hit = None;
+    }
+
+    /// take the last value written to the watched address, if any.
+    pub(crate) fn take_write_watch_hit(&mut self) -> Option<u8> {
+        self.write_watch_hit.take()
     }
 
     pub fn set_pc(&mut self, addr: u16) {
@@ -820,11 +2184,330 @@ impl CPU {
     pub fn get_pc(&self) -> u16 {
         self.pc
     }
+
+    pub fn get_a(&self) -> u8 {
+        self.a.data
+    }
+
+    pub fn set_a(&mut self, data: u8) {
+        self.a.data = data;
+    }
+
+    pub fn get_x(&self) -> u8 {
+        self.x.data
+    }
+
+    pub fn set_x(&mut self, data: u8) {
+        self.x.data = data;
+    }
+
+    pub fn get_y(&self) -> u8 {
+        self.y.data
+    }
+
+    pub fn set_y(&mut self, data: u8) {
+        self.y.data = data;
+    }
+
+    /// the low byte of the stack pointer, the stack is in page 1.
+    pub fn get_sp(&self) -> u8 {
+        self.sp
+    }
+
+    pub fn set_sp(&mut self, sp: u8) {
+        self.sp = sp;
+    }
+
+    /// the status register as `NV-BDIZC` bits, the unused bit 5 reads as set.
+    pub fn get_status(&self) -> u8 {
+        self.status.into()
+    }
+
+    pub fn set_status(&mut self, status: u8) {
+        self.status = Status::from(status);
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.status.negative
+    }
+
+    pub fn set_negative(&mut self, set: bool) {
+        self.status.negative = set;
+    }
+
+    pub fn is_overflow(&self) -> bool {
+        self.status.overflow
+    }
+
+    pub fn set_overflow(&mut self, set: bool) {
+        self.status.overflow = set;
+    }
+
+    pub fn is_decimal(&self) -> bool {
+        self.status.decimal
+    }
+
+    pub fn set_decimal(&mut self, set: bool) {
+        self.status.decimal = set;
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.status.zero
+    }
+
+    pub fn set_zero(&mut self, set: bool) {
+        self.status.zero = set;
+    }
+
+    pub fn is_carry(&self) -> bool {
+        self.status.carry
+    }
+
+    pub fn set_carry(&mut self, set: bool) {
+        self.status.carry = set;
+    }
+
+    pub fn set_irq_enabled(&mut self, enabled: bool) {
+        self.status.int_disable = !enabled;
+    }
+
+    /// snapshot the registers, e.g. to compare them across steps.
+    pub fn save_state(&self) -> CpuState {
+        self.registers()
+    }
+
+    pub fn restore_state(&mut self, state: CpuState) {
+        self.set_registers(state);
+    }
 }
 
-#[derive(Debug)]
+/// the instructions a CPU executes, see [`CPU::iter_steps`].
+pub struct Steps<'a> {
+    cpu: &'a mut CPU,
+}
+impl Iterator for Steps<'_> {
+    type Item = Result<TraceEvent, ExecutionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.cpu.run_state() != RunState::Running {
+                return None;
+            }
+            // nothing is executed while RDY holds the CPU
+            let ready = self.cpu.rdy.is_ready();
+            match self.cpu.step() {
+                Ok(_) if !ready => continue,
+                Ok(_) => return Some(Ok(self.cpu.trace_event())),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl Cpu for CPU {
+    type Registers = Registers;
+
+    fn reset(&mut self) {
+        CPU::reset(self)
+    }
+
+    fn step(&mut self) -> Result<u64, ExecutionError> {
+        CPU::step(self)
+    }
+
+    fn irq(&mut self) {
+        CPU::irq(self)
+    }
+
+    fn set_irq_line(&mut self, asserted: bool) {
+        CPU::set_irq_line(self, asserted)
+    }
+
+    fn nmi(&mut self) {
+        CPU::nmi(self)
+    }
+
+    fn set_nmi_line(&mut self, asserted: bool) {
+        CPU::set_nmi_line(self, asserted)
+    }
+
+    fn get_pc(&self) -> u16 {
+        CPU::get_pc(self)
+    }
+
+    fn set_pc(&mut self, addr: u16) {
+        CPU::set_pc(self, addr)
+    }
+
+    fn cycles(&self) -> u64 {
+        CPU::cycles(self)
+    }
+
+    fn reset_cycles(&mut self) {
+        CPU::reset_cycles(self)
+    }
+
+    fn fast_forward_idle(&mut self, max_cycles: u64, irq_line: &mut dyn FnMut() -> bool) -> u64 {
+        let repeat = self.idle_loop_cycles as u64;
+        let mut skipped = 0;
+        while repeat > 0 && skipped + repeat <= max_cycles && !self.is_idle_interrupted(irq_line())
+        {
+            self.spend_cycles(repeat);
+            skipped += repeat;
+        }
+        skipped
+    }
+
+    fn registers(&self) -> Registers {
+        Registers {
+            pc: self.pc,
+            sp: self.sp,
+            a: self.a.data,
+            x: self.x.data,
+            y: self.y.data,
+            status: self.status.into(),
+        }
+    }
+
+    fn set_registers(&mut self, regs: Registers) {
+        self.pc = regs.pc;
+        self.sp = regs.sp;
+        self.a.data = regs.a;
+        self.x.data = regs.x;
+        self.y.data = regs.y;
+        self.status = Status::from(regs.status);
+    }
+
+    fn layout(&self) -> &Layout {
+        CPU::layout(self)
+    }
+
+    fn layout_mut(&mut self) -> &mut Layout {
+        CPU::layout_mut(self)
+    }
+}
+
+/// programmer-visible registers of the 6502.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Registers {
+    pub pc: u16,
+    pub sp: u8,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+}
+
+/// a snapshot of the registers from [`CPU::save_state`].
+pub type CpuState = Registers;
+
+/// decides whether a breakpoint stops the CPU, see [`CPU::add_conditional_breakpoint`].
+pub type BreakCondition = Box<dyn Fn(&CPU) -> bool>;
+
+/// called on BRK with the CPU, the signature byte and the address of the BRK,
+/// see [`CPU::set_brk_handler`].
+pub type BrkHandler = Box<dyn FnMut(&mut CPU, u8, u16) -> BrkAction>;
+
+/// what the CPU does after a [`BrkHandler`] returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrkAction {
+    /// push the return address and status and jump through the IRQ/BRK vector as usual.
+    Interrupt,
+    /// the host handled the BRK, continue at PC as the handler left it.
+    Handled,
+}
+
+/// the memory an instruction accesses, see [`CPU::effective_operand`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EffectiveOperand {
+    /// the address read, written or jumped to. None for implied and immediate operands.
+    pub addr: Option<u16>,
+    /// the byte currently at _addr_, or the immediate value. None for jumps and branches.
+    pub value: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ExecutionError {
-    UnknownInst(u8),
+    /// _opcode_ does not exist on _model_. _elsewhere_ names another model having it, and as what.
+    UndefinedInst {
+        opcode: u8,
+        model: CpuModel,
+        elsewhere: Option<(&'static str, CpuModel)>,
+    },
+    /// _opcode_ is _mnemonic_ on _model_, but the emulator does not implement it.
+    UnimplementedInst {
+        opcode: u8,
+        model: CpuModel,
+        mnemonic: &'static str,
+    },
+    /// PC reached a breakpoint at the address, see [`CPU::add_breakpoint`].
+    Breakpoint(u16),
+    /// the device at _addr_ refused a write by the instruction at _pc_, e.g. ROM.
+    /// the faults below are only raised with [`CPU::set_fault_checking`].
+    RomWrite { addr: u16, pc: u16 },
+    /// nothing answered a read of _addr_ by the instruction at _pc_, or _addr_ is a hole
+    /// in the layout or past its end.
+    UnmappedAccess {
+        addr: u16,
+        pc: u16,
+        access: BusAccess,
+    },
+    /// the instruction at _pc_ pushed to or pulled from _addr_ at the edge of the stack page,
+    /// wrapping SP around.
+    StackFault {
+        addr: u16,
+        pc: u16,
+        access: BusAccess,
+    },
+    /// stepping the CPU stopped by STP or a JAM opcode, with _pc_ past the opcode.
+    /// only a reset gets it going again.
+    Halted { pc: u16 },
+}
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::UndefinedInst {
+                opcode,
+                model,
+                elsewhere,
+            } => {
+                write!(f, "opcode ${:02X} is undefined on the {}", opcode, model)?;
+                if let Some((mnemonic, other)) = elsewhere {
+                    write!(f, " (it is {} on the {})", mnemonic, other)?;
+                }
+                Ok(())
+            }
+            ExecutionError::UnimplementedInst {
+                opcode,
+                model,
+                mnemonic,
+            } => write!(
+                f,
+                "opcode ${:02X} ({}) of the {} is not implemented",
+                opcode, mnemonic, model
+            ),
+            ExecutionError::Breakpoint(addr) => write!(f, "breakpoint at ${:04X}", addr),
+            ExecutionError::RomWrite { addr, pc } => {
+                write!(f, "write to read-only ${:04X} at ${:04X}", addr, pc)
+            }
+            ExecutionError::UnmappedAccess { addr, pc, access } => {
+                let access = match access {
+                    BusAccess::Read => "read of",
+                    BusAccess::Write => "write to",
+                };
+                write!(f, "{} unmapped ${:04X} at ${:04X}", access, addr, pc)
+            }
+            ExecutionError::StackFault { addr, pc, access } => {
+                let fault = match access {
+                    BusAccess::Read => "underflow",
+                    BusAccess::Write => "overflow",
+                };
+                write!(f, "stack {} to ${:04X} at ${:04X}", fault, addr, pc)
+            }
+            ExecutionError::Halted { pc } => write!(f, "CPU stopped at ${:04X}", pc),
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -862,6 +2545,24 @@ impl From<u8> for Status {
         }
     }
 }
+impl Status {
+    /// flags as `NV-BDIZC` letters, upper case when set and lower case when clear.
+    fn letters(&self) -> String {
+        [
+            (self.negative, 'N'),
+            (self.overflow, 'V'),
+            (true, '-'),
+            (self.break_, 'B'),
+            (self.decimal, 'D'),
+            (self.int_disable, 'I'),
+            (self.zero, 'Z'),
+            (self.carry, 'C'),
+        ]
+        .into_iter()
+        .map(|(set, c)| if set { c } else { c.to_ascii_lowercase() })
+        .collect()
+    }
+}
 impl fmt::Display for Status {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -892,6 +2593,10 @@ impl Register {
     }
 }
 
+fn crosses_page(from: u16, to: u16) -> bool {
+    (from & 0xFF00) != (to & 0xFF00)
+}
+
 #[derive(Debug)]
 enum DebugOp {
     Implied,
@@ -906,6 +2611,10 @@ enum DebugOp {
     Relative(i8),
     XIndirect(u8, u8),
     IndirectY(u8, u8),
+    ZeroPageIndirect(u8),
+    AbsoluteXIndirect(u16, u8),
+    /// zero page address and branch target.
+    ZeroPageRelative(u8, u16),
 }
 
 #[derive(Debug)]
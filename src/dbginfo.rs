@@ -0,0 +1,321 @@
+use core::fmt;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::Path,
+};
+
+/// source-level information from a cc65 toolchain debug file, as written by `ld65 --dbgfile`.
+/// maps addresses to source lines and symbols and back, for annotating traces
+/// and setting breakpoints like `main.s:120`.
+#[derive(Debug, Default, Clone)]
+pub struct DebugInfo {
+    files: Vec<String>,
+    /// address ranges by their start, with the line they were assembled from.
+    lines: BTreeMap<u16, LineRange>,
+    /// qualified symbol names by address, and back.
+    symbols: BTreeMap<u16, Vec<String>>,
+    symbol_addrs: HashMap<String, u16>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LineRange {
+    end: u16,
+    file: usize,
+    line: usize,
+    kind: u8,
+}
+
+#[derive(Debug)]
+pub enum DebugInfoError {
+    Io(io::Error),
+    /// the record on _line_ could not be parsed.
+    Malformed {
+        line: usize,
+    },
+    /// the record on _line_ lacks the _field_ attribute.
+    MissingField {
+        line: usize,
+        field: &'static str,
+    },
+    /// the record on _line_ refers to a _kind_ record that does not exist.
+    UnknownId {
+        line: usize,
+        kind: &'static str,
+        id: usize,
+    },
+}
+impl From<io::Error> for DebugInfoError {
+    fn from(value: io::Error) -> Self {
+        DebugInfoError::Io(value)
+    }
+}
+
+/// a line in one of the source files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLocation<'a> {
+    pub file: &'a str,
+    pub line: usize,
+}
+impl fmt::Display for SourceLocation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+impl DebugInfo {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DebugInfoError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, DebugInfoError> {
+        let mut files = HashMap::new();
+        let mut segs = HashMap::new();
+        let mut spans = HashMap::new();
+        let mut scopes = HashMap::new();
+        let mut lines = vec![];
+        let mut syms = vec![];
+
+        for (i, text) in text.lines().enumerate() {
+            let line = i + 1;
+            let Some((kind, attrs)) = text.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let rec = Record::parse(attrs, line)?;
+            match kind {
+                "file" => {
+                    files.insert(rec.num("id")?, rec.string("name")?.to_owned());
+                }
+                "seg" => {
+                    segs.insert(rec.num("id")?, rec.num("start")?);
+                }
+                "span" => {
+                    let seg = rec.num("seg")?;
+                    let seg_start = *segs.get(&seg).ok_or(DebugInfoError::UnknownId {
+                        line,
+                        kind: "seg",
+                        id: seg,
+                    })?;
+                    spans.insert(
+                        rec.num("id")?,
+                        (seg_start + rec.num("start")?, rec.num("size")?),
+                    );
+                }
+                "scope" => {
+                    let parent = rec.opt_num("parent")?;
+                    scopes.insert(rec.num("id")?, (rec.string("name")?.to_owned(), parent));
+                }
+                "line" => {
+                    let Some(span) = rec.get("span") else {
+                        // lines without code, e.g. comments
+                        continue;
+                    };
+                    let kind = rec.opt_num("type")?.unwrap_or(0) as u8;
+                    let ids = span
+                        .split('+')
+                        .map(|v| parse_num(v).ok_or(DebugInfoError::Malformed { line }))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    lines.push((line, rec.num("file")?, rec.num("line")?, kind, ids));
+                }
+                "sym" => {
+                    // only labels and equates carry a value, imports do not
+                    if let (Some(val), Some(scope)) = (rec.opt_num("val")?, rec.opt_num("scope")?) {
+                        syms.push((line, rec.string("name")?.to_owned(), scope, val));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut info = DebugInfo::default();
+        let mut file_idx = HashMap::new();
+        for (id, name) in files {
+            file_idx.insert(id, info.files.len());
+            info.files.push(name);
+        }
+
+        for (line, file, src_line, kind, ids) in lines {
+            let &file = file_idx.get(&file).ok_or(DebugInfoError::UnknownId {
+                line,
+                kind: "file",
+                id: file,
+            })?;
+            for id in ids {
+                let &(start, size) = spans.get(&id).ok_or(DebugInfoError::UnknownId {
+                    line,
+                    kind: "span",
+                    id,
+                })?;
+                if size == 0 || start > u16::MAX as usize {
+                    continue;
+                }
+                let range = LineRange {
+                    end: (start + size - 1).min(u16::MAX as usize) as u16,
+                    file,
+                    line: src_line,
+                    kind,
+                };
+                // macro expansions (type 2) lose against the line that invoked them
+                match info.lines.get(&(start as u16)) {
+                    Some(v) if v.kind != 2 || kind == 2 => {}
+                    _ => {
+                        info.lines.insert(start as u16, range);
+                    }
+                }
+            }
+        }
+
+        for (line, name, scope, val) in syms {
+            if val > u16::MAX as usize {
+                continue;
+            }
+            let mut qualified = name;
+            let mut scope = Some(scope);
+            while let Some(id) = scope {
+                let (name, parent) = scopes.get(&id).ok_or(DebugInfoError::UnknownId {
+                    line,
+                    kind: "scope",
+                    id,
+                })?;
+                if !name.is_empty() {
+                    qualified = format!("{}::{}", name, qualified);
+                }
+                scope = *parent;
+            }
+            info.symbols
+                .entry(val as u16)
+                .or_default()
+                .push(qualified.clone());
+            info.symbol_addrs.insert(qualified, val as u16);
+        }
+
+        Ok(info)
+    }
+
+    /// the source line the byte at _addr_ was assembled from.
+    pub fn location(&self, addr: u16) -> Option<SourceLocation<'_>> {
+        let (_, range) = self.lines.range(..=addr).next_back()?;
+        (addr <= range.end).then(|| SourceLocation {
+            file: &self.files[range.file],
+            line: range.line,
+        })
+    }
+
+    /// the first address assembled from _line_ of _file_.
+    /// _file_ may leave out leading directories, `main.s` matches `src/main.s`.
+    pub fn line_addr(&self, file: &str, line: usize) -> Option<u16> {
+        self.lines
+            .iter()
+            .find(|(_, v)| v.line == line && Path::new(&self.files[v.file]).ends_with(file))
+            .map(|(addr, _)| *addr)
+    }
+
+    /// symbols whose value is _addr_, qualified by their scopes like `scope::name`.
+    pub fn symbols_at(&self, addr: u16) -> &[String] {
+        self.symbols.get(&addr).map_or(&[], |v| v.as_slice())
+    }
+
+    /// the first symbol of every address with symbols.
+    pub(crate) fn symbols(&self) -> impl Iterator<Item = (u16, &str)> + '_ {
+        self.symbols
+            .iter()
+            .filter_map(|(addr, names)| Some((*addr, names.first()?.as_str())))
+    }
+
+    pub fn symbol_addr(&self, name: &str) -> Option<u16> {
+        self.symbol_addrs.get(name).copied()
+    }
+
+    /// resolve _spec_ given as `file:line`, a symbol name or a hex address like `$8000`.
+    pub fn resolve(&self, spec: &str) -> Option<u16> {
+        let spec = spec.trim();
+        if let Some((file, line)) = spec.rsplit_once(':').filter(|(v, _)| !v.ends_with(':')) {
+            if let Ok(line) = line.parse() {
+                return self.line_addr(file, line);
+            }
+        }
+        self.symbol_addr(spec).or_else(|| {
+            let hex = spec.trim_start_matches('$').trim_start_matches("0x");
+            u16::from_str_radix(hex, 16).ok()
+        })
+    }
+
+    /// `file:line symbol+offset` describing _addr_, for annotating traces and disassembly.
+    pub fn describe(&self, addr: u16) -> Option<String> {
+        let loc = self.location(addr);
+        let sym = self
+            .symbols
+            .range(..=addr)
+            .next_back()
+            .and_then(|(base, names)| Some((addr - base, names.first()?)));
+        match (loc, sym) {
+            (None, None) => None,
+            (Some(loc), None) => Some(loc.to_string()),
+            (loc, Some((offset, name))) => {
+                let mut desc = loc.map(|v| format!("{} ", v)).unwrap_or_default();
+                desc.push_str(name);
+                if offset != 0 {
+                    desc.push_str(&format!("+{}", offset));
+                }
+                Some(desc)
+            }
+        }
+    }
+}
+
+/// the comma separated `key=value` attributes of one record.
+struct Record<'a> {
+    line: usize,
+    attrs: Vec<(&'a str, &'a str)>,
+}
+impl<'a> Record<'a> {
+    fn parse(text: &'a str, line: usize) -> Result<Self, DebugInfoError> {
+        let mut attrs = vec![];
+        let mut rest = text.trim();
+        while !rest.is_empty() {
+            let (key, value) = rest
+                .split_once('=')
+                .ok_or(DebugInfoError::Malformed { line })?;
+            let end = match value.strip_prefix('"') {
+                // quoted strings may contain commas
+                Some(v) => v.find('"').ok_or(DebugInfoError::Malformed { line })? + 2,
+                None => value.find(',').unwrap_or(value.len()),
+            };
+            attrs.push((key.trim(), &value[..end]));
+            rest = value[end..].trim_start_matches(',');
+        }
+        Ok(Self { line, attrs })
+    }
+
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.attrs.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+
+    fn string(&self, key: &'static str) -> Result<&'a str, DebugInfoError> {
+        let value = self.get(key).ok_or(DebugInfoError::MissingField {
+            line: self.line,
+            field: key,
+        })?;
+        Ok(value.trim_matches('"'))
+    }
+
+    fn opt_num(&self, key: &'static str) -> Result<Option<usize>, DebugInfoError> {
+        self.get(key)
+            .map(|v| parse_num(v).ok_or(DebugInfoError::Malformed { line: self.line }))
+            .transpose()
+    }
+
+    fn num(&self, key: &'static str) -> Result<usize, DebugInfoError> {
+        self.opt_num(key)?.ok_or(DebugInfoError::MissingField {
+            line: self.line,
+            field: key,
+        })
+    }
+}
+
+fn parse_num(v: &str) -> Option<usize> {
+    match v.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => v.parse().ok(),
+    }
+}
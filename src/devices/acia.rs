@@ -0,0 +1,275 @@
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+};
+
+use super::IrqLine;
+use crate::Device;
+
+/// crystal the baud rate generator divides, the standard 1.8432 MHz.
+const XTAL_HZ: u64 = 1_843_200;
+/// divisors of the 16x receiver/transmitter clock by the low nibble of CONTROL.
+/// the external clock setting is taken as the crystal itself, 115200 baud.
+const BAUD_DIVISORS: [u64; 16] = [
+    1, 2304, 1536, 1047, 856, 768, 384, 192, 96, 64, 48, 32, 24, 16, 12, 6,
+];
+
+/// a 6551 ACIA whose serial line is the stream _s_, with transmit and receive timed
+/// by the programmed baud rate against the emulated clock.
+/// a byte occupies the line for a whole frame, start, data, parity and stop bits,
+/// before the next one is shifted, so TDRE and RDRF behave like on real hardware.
+/// reads of _s_ should not block, see [`Pty`](super::Pty).
+///
+/// registers:
+/// - +0 DATA (rw): received byte, clearing RDRF and overrun / byte to transmit, clearing TDRE.
+/// - +1 STATUS (r): see the `STATUS_` constants, reading clears the IRQ bit.
+/// - +1 RESET (w): programmed reset, clears COMMAND bits 0-4 and the overrun flag.
+/// - +2 COMMAND (rw): bit 0 enables the receiver, bit 1 disables its IRQ,
+///   bits 2-3 transmitter control with $01 enabling its IRQ and $00 turning it off.
+/// - +3 CONTROL (rw): bits 0-3 baud rate, bits 5-6 word length (8 - _n_), bit 7 two stop bits.
+pub struct Acia<S> {
+    s: S,
+    cpu_hz: u64,
+    control: u8,
+    command: u8,
+    /// transmit data register, None while TDRE.
+    tdr: Option<u8>,
+    /// byte being shifted out and cycles until it is on the line.
+    tx_shift: Option<(u8, u64)>,
+    rdr: u8,
+    rdrf: bool,
+    overrun: bool,
+    /// cycles until the byte being shifted in is complete, or the line is checked again.
+    rx_wait: u64,
+    rx_shift: Option<u8>,
+    input: VecDeque<u8>,
+    irq: bool,
+    irq_line: Option<IrqLine>,
+}
+
+impl<S: Read + Write> Acia<S> {
+    pub const DATA: usize = 0;
+    pub const STATUS: usize = 1;
+    pub const COMMAND: usize = 2;
+    pub const CONTROL: usize = 3;
+
+    pub const STATUS_OVERRUN: u8 = 0x04;
+    /// a received byte waits in DATA.
+    pub const STATUS_RDRF: u8 = 0x08;
+    /// DATA can take the next byte to transmit.
+    pub const STATUS_TDRE: u8 = 0x10;
+    pub const STATUS_IRQ: u8 = 0x80;
+
+    /// create an ACIA for a CPU clocked at _cpu_hz_, which the baud rates are derived from.
+    pub fn new(s: S, cpu_hz: u64) -> Self {
+        assert!(cpu_hz > 0, "clock frequency must be non-zero");
+        Self {
+            s,
+            cpu_hz,
+            control: 0,
+            command: 0x02,
+            tdr: None,
+            tx_shift: None,
+            rdr: 0,
+            rdrf: false,
+            overrun: false,
+            rx_wait: 0,
+            rx_shift: None,
+            input: VecDeque::new(),
+            irq: false,
+            irq_line: None,
+        }
+    }
+
+    /// drive _line_ while the ACIA requests an interrupt.
+    pub fn set_irq_line(&mut self, line: Option<IrqLine>) {
+        self.irq_line = line;
+        self.update_irq();
+    }
+
+    pub fn stream(&self) -> &S {
+        &self.s
+    }
+
+    pub fn stream_mut(&mut self) -> &mut S {
+        &mut self.s
+    }
+
+    /// clock cycles one frame takes at the programmed baud rate and format.
+    pub fn frame_cycles(&self) -> u64 {
+        let divisor = BAUD_DIVISORS[(self.control & 0x0F) as usize];
+        let bit_cycles = (self.cpu_hz * 16 * divisor / XTAL_HZ).max(1);
+        let parity = (self.command & 0x20 != 0) as u64;
+        let stop = 1 + (self.control >> 7) as u64;
+        bit_cycles * (1 + self.word_len() as u64 + parity + stop)
+    }
+
+    fn word_len(&self) -> u32 {
+        8 - ((self.control >> 5) & 0x03) as u32
+    }
+
+    fn word_mask(&self) -> u8 {
+        (0xFFu16 >> (8 - self.word_len())) as u8
+    }
+
+    fn is_receiver_enabled(&self) -> bool {
+        self.command & 0x01 != 0
+    }
+
+    fn is_transmitter_enabled(&self) -> bool {
+        self.command & 0x0C != 0
+    }
+
+    fn update_irq(&mut self) {
+        if let Some(line) = &self.irq_line {
+            line.set(self.irq);
+        }
+    }
+
+    fn raise_irq(&mut self) {
+        self.irq = true;
+        self.update_irq();
+    }
+
+    fn tick_tx(&mut self, mut cycles: u64) {
+        while self.is_transmitter_enabled() {
+            if self.tx_shift.is_none() {
+                let Some(data) = self.tdr.take() else {
+                    return;
+                };
+                self.tx_shift = Some((data, self.frame_cycles()));
+                if self.command & 0x0C == 0x04 {
+                    self.raise_irq();
+                }
+            }
+            let Some((data, left)) = &mut self.tx_shift else {
+                return;
+            };
+            if cycles < *left {
+                *left -= cycles;
+                return;
+            }
+            cycles -= *left;
+            let _ = self.s.write_all(&[*data]).and_then(|_| self.s.flush());
+            self.tx_shift = None;
+        }
+    }
+
+    fn tick_rx(&mut self, mut cycles: u64) {
+        if !self.is_receiver_enabled() {
+            self.rx_shift = None;
+            self.rx_wait = 0;
+            return;
+        }
+        while cycles >= self.rx_wait {
+            cycles -= self.rx_wait;
+            if let Some(data) = self.rx_shift.take() {
+                if self.rdrf {
+                    self.overrun = true;
+                } else {
+                    self.rdr = data & self.word_mask();
+                    self.rdrf = true;
+                }
+                if self.command & 0x02 == 0 {
+                    self.raise_irq();
+                }
+            }
+
+            if self.input.is_empty() {
+                let mut buf = [0; 64];
+                // nothing waiting, e.g. WouldBlock from a non-blocking stream, leaves the line idle
+                if let Ok(n) = self.s.read(&mut buf) {
+                    self.input.extend(&buf[..n]);
+                }
+            }
+            // an idle line is checked again after a frame's time
+            self.rx_shift = self.input.pop_front();
+            self.rx_wait = self.frame_cycles();
+        }
+        self.rx_wait -= cycles;
+    }
+
+    fn read_data(&mut self) -> u8 {
+        self.rdrf = false;
+        self.overrun = false;
+        self.rdr
+    }
+
+    fn get_status(&self) -> u8 {
+        let mut status = 0;
+        if self.overrun {
+            status |= Self::STATUS_OVERRUN;
+        }
+        if self.rdrf {
+            status |= Self::STATUS_RDRF;
+        }
+        if self.tdr.is_none() {
+            status |= Self::STATUS_TDRE;
+        }
+        if self.irq {
+            status |= Self::STATUS_IRQ;
+        }
+        status
+    }
+
+    fn status(&mut self) -> u8 {
+        let status = self.get_status();
+        self.irq = false;
+        self.update_irq();
+        status
+    }
+}
+impl<S: Read + Write + 'static> Device for Acia<S> {
+    fn reset(&mut self) {
+        self.control = 0;
+        self.command = 0x02;
+        self.tdr = None;
+        self.tx_shift = None;
+        self.rdrf = false;
+        self.overrun = false;
+        self.rx_wait = 0;
+        self.rx_shift = None;
+        self.irq = false;
+        self.update_irq();
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.tick_tx(cycles);
+        self.tick_rx(cycles);
+    }
+
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        match addr {
+            Self::DATA => Some(self.read_data()),
+            Self::STATUS => Some(self.status()),
+            Self::COMMAND => Some(self.command),
+            Self::CONTROL => Some(self.control),
+            _ => None,
+        }
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        match addr {
+            Self::DATA => Some(self.rdr),
+            Self::STATUS => Some(self.get_status()),
+            Self::COMMAND => Some(self.command),
+            Self::CONTROL => Some(self.control),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        match addr {
+            Self::DATA => self.tdr = Some(data & self.word_mask()),
+            // programmed reset
+            Self::STATUS => {
+                self.command &= 0xE0;
+                self.overrun = false;
+            }
+            Self::COMMAND => self.command = data,
+            Self::CONTROL => self.control = data,
+            _ => return None,
+        }
+        Some(())
+    }
+}
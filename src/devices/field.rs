@@ -0,0 +1,54 @@
+/// a value that `#[derive(Device)]` can map into a device's address space.
+/// multi-byte values are laid out little endian.
+pub trait Field {
+    /// number of bytes the value occupies.
+    const SIZE: usize;
+
+    fn read_byte(&self, index: usize) -> u8;
+
+    fn write_byte(&mut self, index: usize, data: u8);
+
+    /// the value held after a reset.
+    fn clear(&mut self);
+}
+
+macro_rules! impl_field {
+    ($($ty:ty),*) => {
+        $(
+            impl Field for $ty {
+                const SIZE: usize = std::mem::size_of::<$ty>();
+
+                fn read_byte(&self, index: usize) -> u8 {
+                    self.to_le_bytes()[index]
+                }
+
+                fn write_byte(&mut self, index: usize, data: u8) {
+                    let mut bytes = self.to_le_bytes();
+                    bytes[index] = data;
+                    *self = <$ty>::from_le_bytes(bytes);
+                }
+
+                fn clear(&mut self) {
+                    *self = 0;
+                }
+            }
+        )*
+    };
+}
+impl_field!(u8, u16, u32, u64);
+
+impl<const N: usize> Field for [u8; N] {
+    const SIZE: usize = N;
+
+    fn read_byte(&self, index: usize) -> u8 {
+        self[index]
+    }
+
+    fn write_byte(&mut self, index: usize, data: u8) {
+        self[index] = data;
+    }
+
+    fn clear(&mut self) {
+        self.fill(0);
+    }
+}
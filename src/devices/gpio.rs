@@ -0,0 +1,109 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use log::warn;
+
+use crate::IoPort;
+
+/// an 8 pin I/O port whose pins are mirrored to an external process over a byte stream,
+/// e.g. a TCP or Unix socket to a board driving real pins.
+///
+/// registers, laid out like the 6510's port:
+/// - +0 DIR (rw): a set bit makes the pin an output.
+/// - +1 DATA (rw): writes set the output latch, reads return the pin levels.
+///
+/// the protocol is line based text with the levels as two hex digits:
+/// - `out XX` is sent whenever the pin levels change.
+/// - `in XX` is received to set the levels driven onto the input pins.
+pub struct GpioBridge {
+    port: IoPort,
+    input: Arc<AtomicU8>,
+}
+impl GpioBridge {
+    /// bridge through _reader_ and _writer_, usually the two halves of the same stream.
+    pub fn new(reader: impl Read + Send + 'static, mut writer: impl Write + 'static) -> Self {
+        let input = Arc::new(AtomicU8::new(0xFF));
+
+        let inp = input.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                match line
+                    .trim()
+                    .strip_prefix("in ")
+                    .and_then(|v| u8::from_str_radix(v.trim(), 16).ok())
+                {
+                    Some(pins) => inp.store(pins, Ordering::Release),
+                    None => warn!("gpio bridge: ignoring malformed line {:?}", line),
+                }
+            }
+        });
+
+        let mut port = IoPort::new();
+        let mut last = None;
+        port.set_on_change(move |pins| {
+            if last == Some(pins) {
+                return;
+            }
+            last = Some(pins);
+            if let Err(e) = writeln!(writer, "out {:02X}", pins).and_then(|_| writer.flush()) {
+                warn!("gpio bridge: cannot send pin levels: {}", e);
+            }
+        });
+
+        Self { port, input }
+    }
+
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Self::new(stream.try_clone()?, stream))
+    }
+
+    pub fn port(&self) -> &IoPort {
+        &self.port
+    }
+
+    fn sync_inputs(&mut self) {
+        let pins = self.input.load(Ordering::Acquire);
+        if pins != self.port.get_input_pins() {
+            self.port.set_input_pins(pins);
+        }
+    }
+
+    fn read_direction(&mut self) -> u8 {
+        self.port.get_direction()
+    }
+
+    fn write_direction(&mut self, data: u8) {
+        self.sync_inputs();
+        self.port.write(0x0000, data);
+    }
+
+    fn read_data(&mut self) -> u8 {
+        self.sync_inputs();
+        self.port.pins()
+    }
+
+    fn write_data(&mut self, data: u8) {
+        self.sync_inputs();
+        self.port.write(0x0001, data);
+    }
+}
+crate::registers! {
+    GpioBridge {
+        0 DIR r(read_direction) w(write_direction),
+        1 DATA r(read_data) w(write_data),
+    }
+    fn reset(&mut self) {
+        self.port.reset();
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+};
+
+/// a file system device backed by a host directory, for programs that load and save files
+/// without emulating a disk controller. one file is open at a time, named by
+/// plain file names inside the directory.
+///
+/// registers:
+/// - +0 CMD (w): run a command, see the `CMD_` constants.
+/// - +0 STATUS (r): result of the last command or data access, see the `STATUS_` constants.
+/// - +1 DATA (rw): next byte of the open file.
+/// - +2 NAME (w): append a character to the file name for the next command, $00 clears it.
+///   every command clears it as well.
+pub struct HostFs {
+    root: PathBuf,
+    name: Vec<u8>,
+    file: Option<OpenFile>,
+    status: u8,
+}
+
+enum OpenFile {
+    Read(BufReader<File>),
+    Write(BufWriter<File>),
+}
+
+impl HostFs {
+    pub const CMD_OPEN_READ: u8 = 0x01;
+    /// open for writing, creating or truncating the file.
+    pub const CMD_OPEN_WRITE: u8 = 0x02;
+    pub const CMD_CLOSE: u8 = 0x03;
+    pub const CMD_DELETE: u8 = 0x04;
+
+    pub const STATUS_OK: u8 = 0x00;
+    pub const STATUS_NOT_FOUND: u8 = 0x01;
+    pub const STATUS_IO_ERROR: u8 = 0x02;
+    /// the name is empty or would leave the directory.
+    pub const STATUS_BAD_NAME: u8 = 0x03;
+    /// no file is open, or not in the direction accessed.
+    pub const STATUS_NOT_OPEN: u8 = 0x04;
+    /// a read went past the end of the file, it returned $00.
+    pub const STATUS_EOF: u8 = 0x05;
+    pub const STATUS_BAD_COMMAND: u8 = 0x06;
+
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            name: vec![],
+            file: None,
+            status: Self::STATUS_OK,
+        }
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        let name = std::str::from_utf8(&self.name).ok()?;
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && !name.contains(['/', '\\'])
+            && !name.chars().any(|c| c.is_control());
+        valid.then(|| self.root.join(name))
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        match self.file.take() {
+            Some(OpenFile::Write(mut f)) => f.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    fn run(&mut self, cmd: u8) -> u8 {
+        if cmd == Self::CMD_CLOSE {
+            return match self.close() {
+                Ok(()) => Self::STATUS_OK,
+                Err(e) => io_status(e),
+            };
+        }
+
+        let Some(path) = self.path() else {
+            return Self::STATUS_BAD_NAME;
+        };
+        let res = match cmd {
+            Self::CMD_OPEN_READ => self.close().and_then(|_| {
+                self.file = Some(OpenFile::Read(BufReader::new(File::open(path)?)));
+                Ok(())
+            }),
+            Self::CMD_OPEN_WRITE => self.close().and_then(|_| {
+                self.file = Some(OpenFile::Write(BufWriter::new(File::create(path)?)));
+                Ok(())
+            }),
+            Self::CMD_DELETE => fs::remove_file(path),
+            _ => return Self::STATUS_BAD_COMMAND,
+        };
+        match res {
+            Ok(()) => Self::STATUS_OK,
+            Err(e) => io_status(e),
+        }
+    }
+
+    fn status(&mut self) -> u8 {
+        self.status
+    }
+
+    fn command(&mut self, cmd: u8) {
+        self.status = self.run(cmd);
+        self.name.clear();
+    }
+
+    fn read_data(&mut self) -> u8 {
+        let mut byte = [0];
+        let (data, status) = match &mut self.file {
+            Some(OpenFile::Read(f)) => match f.read(&mut byte) {
+                Ok(0) => (0, Self::STATUS_EOF),
+                Ok(_) => (byte[0], Self::STATUS_OK),
+                Err(e) => (0, io_status(e)),
+            },
+            _ => (0, Self::STATUS_NOT_OPEN),
+        };
+        self.status = status;
+        data
+    }
+
+    fn write_data(&mut self, data: u8) {
+        self.status = match &mut self.file {
+            Some(OpenFile::Write(f)) => match f.write_all(&[data]) {
+                Ok(()) => Self::STATUS_OK,
+                Err(e) => io_status(e),
+            },
+            _ => Self::STATUS_NOT_OPEN,
+        };
+    }
+
+    fn write_name(&mut self, data: u8) {
+        if data == 0 {
+            self.name.clear();
+        } else {
+            self.name.push(data);
+        }
+    }
+}
+crate::registers! {
+    HostFs {
+        0 CMD r(status) w(command),
+        1 DATA r(read_data) w(write_data),
+        2 NAME w(write_name),
+    }
+    fn reset(&mut self) {
+        let _ = self.close();
+        self.name.clear();
+        self.status = Self::STATUS_OK;
+    }
+
+    fn detach(&mut self) {
+        let _ = self.close();
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        (addr == Self::CMD).then_some(self.status)
+    }
+}
+
+fn io_status(e: io::Error) -> u8 {
+    match e.kind() {
+        io::ErrorKind::NotFound => HostFs::STATUS_NOT_FOUND,
+        _ => HostFs::STATUS_IO_ERROR,
+    }
+}
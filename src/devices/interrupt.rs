@@ -0,0 +1,132 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU8, Ordering},
+    Arc, Mutex,
+};
+
+/// the hardware interrupts of the 6502.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interrupt {
+    Irq,
+    Nmi,
+}
+impl Interrupt {
+    /// where the CPU fetches the handler address from.
+    pub fn vector_addr(self) -> u16 {
+        match self {
+            Interrupt::Irq => 0xFFFE,
+            Interrupt::Nmi => 0xFFFA,
+        }
+    }
+}
+
+/// anything that can drive the CPU's interrupt request input.
+pub trait IrqSource {
+    fn is_asserted(&self) -> bool;
+}
+
+/// an interrupt request output of a single device. clones refer to the same line.
+#[derive(Debug, Clone, Default)]
+pub struct IrqLine(Arc<AtomicBool>);
+impl IrqLine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn assert(&self) {
+        self.set(true);
+    }
+
+    pub fn release(&self) {
+        self.set(false);
+    }
+
+    pub fn set(&self, asserted: bool) {
+        self.0.store(asserted, Ordering::Release);
+    }
+}
+impl IrqSource for IrqLine {
+    fn is_asserted(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// aggregates up to 8 interrupt sources into the single CPU IRQ line.
+/// lower numbered sources have higher priority. clones refer to the same controller,
+/// so one can be mapped into a layout while another is connected to the machine.
+///
+/// registers:
+/// - +0 PENDING (r): bit _n_ is set while source _n_ asserts its line.
+/// - +1 MASK (rw): bit _n_ lets source _n_ interrupt the CPU.
+/// - +2 ACTIVE (r): number of the highest priority source that is pending and enabled, $FF if none.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptController {
+    inner: Arc<ControllerInner>,
+}
+
+#[derive(Debug, Default)]
+struct ControllerInner {
+    sources: Mutex<Vec<IrqLine>>,
+    mask: AtomicU8,
+}
+
+impl InterruptController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// create the next source line, to be handed to the device raising it.
+    pub fn add_source(&self) -> IrqLine {
+        let mut sources = self.inner.sources.lock().unwrap();
+        assert!(
+            sources.len() < 8,
+            "interrupt controller supports at most 8 sources"
+        );
+        let line = IrqLine::new();
+        sources.push(line.clone());
+        line
+    }
+
+    pub fn pending(&self) -> u8 {
+        self.inner
+            .sources
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.is_asserted())
+            .fold(0, |acc, (i, _)| acc | (1 << i))
+    }
+
+    pub fn get_mask(&self) -> u8 {
+        self.inner.mask.load(Ordering::Acquire)
+    }
+
+    pub fn set_mask(&self, mask: u8) {
+        self.inner.mask.store(mask, Ordering::Release);
+    }
+
+    /// the highest priority source that is both pending and enabled.
+    pub fn active(&self) -> Option<u8> {
+        let active = self.pending() & self.get_mask();
+        (active != 0).then(|| active.trailing_zeros() as u8)
+    }
+
+    fn active_reg(&mut self) -> u8 {
+        self.active().unwrap_or(0xFF)
+    }
+}
+impl IrqSource for InterruptController {
+    fn is_asserted(&self) -> bool {
+        self.active().is_some()
+    }
+}
+crate::registers! {
+    InterruptController {
+        0 PENDING r(pending),
+        1 MASK r(get_mask) w(set_mask),
+        2 ACTIVE r(active_reg),
+    }
+    fn reset(&mut self) {
+        self.set_mask(0);
+    }
+}
@@ -1,21 +1,142 @@
+use std::{any::Any, cell::Cell};
+
+mod acia;
+mod field;
+mod gpio;
+mod hostfs;
+mod interrupt;
+mod mux;
+mod protect;
+#[cfg(unix)]
+mod pty;
+mod rdy;
+mod registers;
 mod serial;
+mod shadow;
+mod shared;
+mod snoop;
 
-pub use serial::SerialIO;
+pub use acia::Acia;
+pub use field::Field;
+pub use gpio::GpioBridge;
+pub use hostfs::HostFs;
+pub use interrupt::{Interrupt, InterruptController, IrqLine, IrqSource};
+pub use mux::{Mux, MuxSelect};
+pub use protect::WriteProtect;
+#[cfg(unix)]
+pub use pty::Pty;
+pub use rdy::RdyLine;
+pub use registers::RegisterBlock;
+pub use serial::{BreakSignal, SerialIO};
+pub use shadow::{Shadow, ShadowSwitch};
+pub use snoop::Snoop;
+#[cfg(feature = "derive")]
+pub use tbo2_derive::Device;
 
 #[allow(unused_variables)]
-pub trait Device {
+pub trait Device: AsAny {
     fn attach(&mut self) {}
 
     fn detach(&mut self) {}
 
     fn reset(&mut self) {}
 
+    /// called after the CPU spent _cycles_ clock cycles, for devices that model timing.
+    fn tick(&mut self, cycles: u64) {}
+
     #[must_use]
     fn read(&mut self, addr: usize) -> Option<u8> {
         None
     }
 
+    /// what a read of _addr_ would return, without any of its side effects,
+    /// e.g. for debuggers. devices with no side-effect free view return None.
+    fn peek(&self, addr: usize) -> Option<u8> {
+        None
+    }
+
     fn write(&mut self, addr: usize, data: u8) -> Option<()> {
         None
     }
+
+    /// change what reads of _addr_ return without any other effect, e.g. for debuggers
+    /// patching memory, also where writes are refused like in ROM. devices with no
+    /// side-effect free way to do it return None.
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        None
+    }
+
+    /// memory the layout may read and write directly instead of calling the device, for speed.
+    /// only for devices whose reads and writes are plain memory accesses without side effects,
+    /// asked when the layout is built and again after the device may have been replaced.
+    fn direct_memory(&mut self) -> Option<DirectMemory> {
+        None
+    }
+
+    /// the device's state for a [`SaveState`](crate::SaveState), None if it has nothing to save.
+    fn save_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// restore a state from [`Device::save_state`], None if the device rejects it.
+    /// states saved by an older version of the device should be accepted.
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        None
+    }
+
+    /// called when the CPU takes _interrupt_ and fetches its vector, e.g. for devices that
+    /// clear their pending flag on acknowledge. a device may supply the address
+    /// to continue at instead of the vector in memory.
+    fn acknowledge(&mut self, interrupt: Interrupt) -> Option<u16> {
+        None
+    }
+}
+
+/// a block of memory a device hands out through [`Device::direct_memory`].
+pub struct DirectMemory {
+    pub(crate) ptr: *mut u8,
+    pub(crate) len: usize,
+    pub(crate) writable: bool,
+}
+impl DirectMemory {
+    /// # Safety
+    /// _ptr_ must be valid for reads, and for writes if _writable_, of _len_ bytes until the
+    /// device is dropped, replaced or moved out of its layout, and stay at the same address
+    /// until then. the layout reads and writes the bytes while holding no borrow of the device,
+    /// so the device must never borrow them uniquely, not even through `&mut self`: they must
+    /// be behind interior mutability and not inline in the device, e.g. a boxed slice of
+    /// [`Cell`]s the device only accesses through shared references. reading and writing the
+    /// bytes must have the same effect as [`Device::read`] and [`Device::write`] at the same
+    /// offsets.
+    pub unsafe fn new(ptr: *mut u8, len: usize, writable: bool) -> Self {
+        Self { ptr, len, writable }
+    }
+
+    /// direct access to _mem_, see [`DirectMemory::new`].
+    /// # Safety
+    /// _mem_ must stay allocated and at the same address until the device is dropped, replaced
+    /// or moved out of its layout, and must never be borrowed uniquely, e.g. through
+    /// [`Cell::get_mut`].
+    pub unsafe fn from_cells(mem: &[Cell<u8>], writable: bool) -> Self {
+        // Cell is transparent over u8, and the pointer may be written through as it comes
+        // from a shared reference to interior mutable memory
+        Self::new(mem.as_ptr().cast::<u8>().cast_mut(), mem.len(), writable)
+    }
+}
+
+/// lets a boxed [`Device`] be downcast back to its concrete type.
+/// implemented for every `'static` type, devices never implement it themselves.
+pub trait AsAny: Any {
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
@@ -0,0 +1,140 @@
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+use crate::{devices::Interrupt, Device};
+
+/// routes every access of its window to the currently selected child device.
+/// the selection is a latch that is either written through a [`MuxSelect`]
+/// mapped elsewhere, or through a control register inside the window itself.
+/// accesses while no existing child is selected are refused.
+#[derive(Default)]
+pub struct Mux {
+    devs: Vec<Box<dyn Device>>,
+    select: MuxSelect,
+    control: Option<usize>,
+}
+impl Mux {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add a child device, returning the selection value that routes to it.
+    pub fn add_device(&mut self, dev: impl Device + 'static) -> u8 {
+        assert!(self.devs.len() < 256, "mux supports at most 256 devices");
+        self.devs.push(Box::new(dev));
+        (self.devs.len() - 1) as u8
+    }
+
+    /// the selection latch, to be mapped into a layout as a single register.
+    pub fn selector(&self) -> MuxSelect {
+        self.select.clone()
+    }
+
+    /// reserve _addr_ within the window for the selection latch instead of passing it through.
+    pub fn set_control_register(&mut self, addr: Option<usize>) {
+        self.control = addr;
+    }
+
+    pub fn get_selected(&self) -> u8 {
+        self.select.get()
+    }
+
+    pub fn set_selected(&self, index: u8) {
+        self.select.set(index);
+    }
+
+    fn selected(&mut self) -> Option<&mut Box<dyn Device>> {
+        let index = self.get_selected() as usize;
+        self.devs.get_mut(index)
+    }
+}
+impl Device for Mux {
+    fn attach(&mut self) {
+        self.devs.iter_mut().for_each(|v| v.attach());
+    }
+
+    fn detach(&mut self) {
+        self.devs.iter_mut().for_each(|v| v.detach());
+    }
+
+    fn reset(&mut self) {
+        self.select.set(0);
+        self.devs.iter_mut().for_each(|v| v.reset());
+    }
+
+    /// every child keeps time, selected or not.
+    fn tick(&mut self, cycles: u64) {
+        self.devs.iter_mut().for_each(|v| v.tick(cycles));
+    }
+
+    /// every child is told, selected or not.
+    fn acknowledge(&mut self, interrupt: Interrupt) -> Option<u16> {
+        self.devs
+            .iter_mut()
+            .fold(None, |vector, v| vector.or(v.acknowledge(interrupt)))
+    }
+
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        if self.control == Some(addr) {
+            return Some(self.get_selected());
+        }
+        self.selected()?.read(addr)
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        if self.control == Some(addr) {
+            return Some(self.get_selected());
+        }
+        self.devs.get(self.get_selected() as usize)?.peek(addr)
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        if self.control == Some(addr) {
+            self.set_selected(data);
+            return Some(());
+        }
+        self.selected()?.write(addr, data)
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        if self.control == Some(addr) {
+            self.set_selected(data);
+            return Some(());
+        }
+        self.selected()?.poke(addr, data)
+    }
+}
+
+/// the selection latch of a [`Mux`]. clones refer to the same latch.
+/// as a device it is a single read/write register, repeated across its whole mapping.
+#[derive(Debug, Clone, Default)]
+pub struct MuxSelect(Arc<AtomicU8>);
+impl MuxSelect {
+    pub fn get(&self) -> u8 {
+        self.0.load(Ordering::Acquire)
+    }
+
+    pub fn set(&self, index: u8) {
+        self.0.store(index, Ordering::Release);
+    }
+}
+impl Device for MuxSelect {
+    fn read(&mut self, _: usize) -> Option<u8> {
+        Some(self.get())
+    }
+
+    fn peek(&self, _: usize) -> Option<u8> {
+        Some(self.get())
+    }
+
+    fn write(&mut self, _: usize, data: u8) -> Option<()> {
+        self.set(data);
+        Some(())
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.write(addr, data)
+    }
+}
@@ -0,0 +1,111 @@
+use std::ops::Range;
+
+use crate::{devices::Interrupt, Device};
+
+/// a device whose writes can be blocked at runtime, wholly or in ranges of its addresses,
+/// like write-protect jumpers or protected OS pages. blocked writes are dropped,
+/// or refused with [`WriteProtect::set_fault_on_violation`] so that a CPU with fault
+/// checking stops on them. pokes are never blocked.
+pub struct WriteProtect<D> {
+    inner: D,
+    /// ranges and whether they are protected, later ones taking precedence.
+    ranges: Vec<(Range<usize>, bool)>,
+    fault: bool,
+}
+impl<D: Device> WriteProtect<D> {
+    /// wrap _inner_ with nothing protected yet.
+    pub fn new(inner: D) -> Self {
+        Self {
+            inner,
+            ranges: vec![],
+            fault: false,
+        }
+    }
+
+    /// protect the device addresses in _range_ from writes, or allow them again.
+    pub fn set_protected(&mut self, range: Range<usize>, protected: bool) {
+        // ranges wholly covered by the new one can never take effect again
+        self.ranges
+            .retain(|(v, _)| v.start < range.start || v.end > range.end);
+        self.ranges.push((range, protected));
+    }
+
+    /// protect every address of the device, or allow writes to all of them again.
+    pub fn set_all_protected(&mut self, protected: bool) {
+        self.set_protected(0..usize::MAX, protected);
+    }
+
+    pub fn is_protected(&self, addr: usize) -> bool {
+        self.ranges
+            .iter()
+            .rev()
+            .find(|(v, _)| v.contains(&addr))
+            .is_some_and(|(_, protected)| *protected)
+    }
+
+    /// refuse blocked writes instead of dropping them.
+    pub fn set_fault_on_violation(&mut self, fault: bool) {
+        self.fault = fault;
+    }
+
+    pub fn is_fault_on_violation(&self) -> bool {
+        self.fault
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+}
+impl<D: Device + 'static> Device for WriteProtect<D> {
+    fn attach(&mut self) {
+        self.inner.attach();
+    }
+
+    fn detach(&mut self) {
+        self.inner.detach();
+    }
+
+    /// the protection stays as it is, like jumpers would.
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.inner.tick(cycles);
+    }
+
+    fn acknowledge(&mut self, interrupt: Interrupt) -> Option<u16> {
+        self.inner.acknowledge(interrupt)
+    }
+
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        self.inner.read(addr)
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        self.inner.peek(addr)
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        if self.is_protected(addr) {
+            return (!self.fault).then_some(());
+        }
+        self.inner.write(addr, data)
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.inner.poke(addr, data)
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        self.inner.save_state()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        self.inner.restore_state(state)
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    ffi::CStr,
+    fs::File,
+    io::{self, Read, Write},
+    os::fd::{AsRawFd, FromRawFd},
+    path::{Path, PathBuf},
+};
+
+/// the master side of a host pseudo-terminal, to be handed to [`SerialIO`](super::SerialIO)
+/// so that tools like minicom or screen can talk to the emulated machine through
+/// the slave side at [`Pty::path`].
+///
+/// the terminal is raw, and reads never block: they fail with `WouldBlock` while
+/// nothing is waiting or no one has the slave side open.
+#[derive(Debug)]
+pub struct Pty {
+    master: File,
+    path: PathBuf,
+}
+impl Pty {
+    pub fn open() -> io::Result<Self> {
+        // SAFETY: plain libc calls on a descriptor owned by the File once created,
+        // ptsname's buffer is copied out before any other call can reuse it.
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let master = File::from_raw_fd(fd);
+
+            if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let name = libc::ptsname(fd);
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let path = PathBuf::from(CStr::from_ptr(name).to_string_lossy().into_owned());
+
+            Ok(Self { master, path })
+        }
+    }
+
+    /// the slave device node, e.g. /dev/pts/3.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+impl Read for Pty {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.master.read(buf) {
+            // EIO is what the master sees while the slave side is closed
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => Err(io::ErrorKind::WouldBlock.into()),
+            res => res,
+        }
+    }
+}
+impl Write for Pty {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.master.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.master.flush()
+    }
+}
+impl AsRawFd for Pty {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.master.as_raw_fd()
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+
+/// the CPU's RDY input, for devices that stall the processor, e.g. slow memory inserting
+/// wait states or video circuitry taking over the bus. clones refer to the same line,
+/// see [`CPU::rdy_line`](crate::CPU::rdy_line).
+#[derive(Debug, Clone)]
+pub struct RdyLine(Arc<RdyInner>);
+
+#[derive(Debug)]
+struct RdyInner {
+    ready: AtomicBool,
+    wait_states: AtomicU64,
+}
+
+impl RdyLine {
+    pub fn new() -> Self {
+        Self(Arc::new(RdyInner {
+            ready: AtomicBool::new(true),
+            wait_states: AtomicU64::new(0),
+        }))
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.0.ready.load(Ordering::Acquire)
+    }
+
+    /// pull the line low with _ready_ unset, the CPU then stalls a cycle at a time
+    /// until it is released again.
+    pub fn set_ready(&self, ready: bool) {
+        self.0.ready.store(ready, Ordering::Release);
+    }
+
+    /// stall the CPU for _cycles_ clock cycles before its next instruction.
+    pub fn add_wait_states(&self, cycles: u64) {
+        self.0.wait_states.fetch_add(cycles, Ordering::AcqRel);
+    }
+
+    pub(crate) fn take_wait_states(&self) -> u64 {
+        self.0.wait_states.swap(0, Ordering::AcqRel)
+    }
+}
+impl Default for RdyLine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
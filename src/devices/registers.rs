@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+
+use crate::Device;
+
+/// implement [`Device`](crate::Device) for a type from a map of its registers.
+///
+/// each register gets an associated offset constant, a read handler `fn(&mut self) -> u8`
+/// after `r` and a write handler `fn(&mut self, u8)` after `w`. accesses to offsets
+/// without a matching handler are rejected. anything after the register list,
+/// e.g. `fn reset(&mut self)`, goes into the generated `Device` impl unchanged.
+///
+/// ```
+/// # use tbo2::registers;
+/// #[derive(Default)]
+/// struct Latch {
+///     data: u8,
+///     ctrl: u8,
+/// }
+/// impl Latch {
+///     fn data(&mut self) -> u8 {
+///         self.data
+///     }
+///     fn set_data(&mut self, data: u8) {
+///         self.data = data;
+///     }
+///     fn status(&mut self) -> u8 {
+///         (self.data != 0) as u8
+///     }
+///     fn set_ctrl(&mut self, ctrl: u8) {
+///         self.ctrl = ctrl;
+///     }
+/// }
+/// registers! {
+///     Latch {
+///         0x0 DATA r(data) w(set_data),
+///         0x1 STATUS r(status),
+///         0x2 CTRL w(set_ctrl),
+///     }
+///     fn reset(&mut self) {
+///         *self = Self::default();
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! registers {
+    (
+        $ty:ty {
+            $(
+                $(#[$meta:meta])*
+                $off:literal $name:ident $(r($read:ident))? $(w($write:ident))?
+            ),* $(,)?
+        }
+        $($rest:tt)*
+    ) => {
+        #[allow(dead_code)]
+        impl $ty {
+            $(
+                $(#[$meta])*
+                pub const $name: usize = $off;
+            )*
+        }
+        impl $crate::Device for $ty {
+            fn read(&mut self, addr: usize) -> Option<u8> {
+                match addr {
+                    $($($off => Some(self.$read()),)?)*
+                    _ => None,
+                }
+            }
+
+            fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+                match addr {
+                    $($($off => {
+                        self.$write(data);
+                        Some(())
+                    })?)*
+                    _ => None,
+                }
+            }
+
+            $($rest)*
+        }
+    };
+}
+
+/// a device made of registers bound to closures or plain latches, for peripherals too small
+/// to be worth a type of their own. offsets without a register refuse accesses.
+///
+/// ```
+/// # use std::{cell::Cell, rc::Rc};
+/// # use tbo2::devices::RegisterBlock;
+/// let ticks = Rc::new(Cell::new(0u8));
+/// let mut block = RegisterBlock::new();
+/// block
+///     .add_latch(0x0, 0xFF)
+///     .add_read(0x1, {
+///         let ticks = ticks.clone();
+///         move || ticks.get()
+///     })
+///     .add_write(0x2, move |v| ticks.set(v));
+/// ```
+#[derive(Default)]
+pub struct RegisterBlock {
+    regs: BTreeMap<usize, Register>,
+}
+
+enum Register {
+    Latch {
+        value: u8,
+        initial: u8,
+    },
+    Handlers {
+        read: Option<Box<dyn FnMut() -> u8>>,
+        write: Option<Box<dyn FnMut(u8)>>,
+    },
+}
+
+impl RegisterBlock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// a read/write register at _offset_ holding what was last written, _initial_ after a reset.
+    pub fn add_latch(&mut self, offset: usize, initial: u8) -> &mut Self {
+        let value = initial;
+        self.regs.insert(offset, Register::Latch { value, initial });
+        self
+    }
+
+    /// a read only register at _offset_, reading calls _read_.
+    pub fn add_read(&mut self, offset: usize, read: impl FnMut() -> u8 + 'static) -> &mut Self {
+        self.add_handlers(offset, Some(Box::new(read)), None)
+    }
+
+    /// a write only register at _offset_, writing calls _write_ with the data.
+    pub fn add_write(&mut self, offset: usize, write: impl FnMut(u8) + 'static) -> &mut Self {
+        self.add_handlers(offset, None, Some(Box::new(write)))
+    }
+
+    pub fn add_read_write(
+        &mut self,
+        offset: usize,
+        read: impl FnMut() -> u8 + 'static,
+        write: impl FnMut(u8) + 'static,
+    ) -> &mut Self {
+        self.add_handlers(offset, Some(Box::new(read)), Some(Box::new(write)))
+    }
+
+    fn add_handlers(
+        &mut self,
+        offset: usize,
+        read: Option<Box<dyn FnMut() -> u8>>,
+        write: Option<Box<dyn FnMut(u8)>>,
+    ) -> &mut Self {
+        self.regs.insert(offset, Register::Handlers { read, write });
+        self
+    }
+
+    /// the value of the latch at _offset_, None if there is no latch there.
+    pub fn get_latch(&self, offset: usize) -> Option<u8> {
+        match self.regs.get(&offset)? {
+            Register::Latch { value, .. } => Some(*value),
+            Register::Handlers { .. } => None,
+        }
+    }
+
+    /// false if there is no latch at _offset_.
+    pub fn set_latch(&mut self, offset: usize, data: u8) -> bool {
+        match self.regs.get_mut(&offset) {
+            Some(Register::Latch { value, .. }) => {
+                *value = data;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn latches(&mut self) -> impl Iterator<Item = &mut u8> {
+        self.regs.values_mut().filter_map(|v| match v {
+            Register::Latch { value, .. } => Some(value),
+            Register::Handlers { .. } => None,
+        })
+    }
+}
+impl Device for RegisterBlock {
+    /// latches go back to their initial values, handlers are not called.
+    fn reset(&mut self) {
+        for reg in self.regs.values_mut() {
+            if let Register::Latch { value, initial } = reg {
+                *value = *initial;
+            }
+        }
+    }
+
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        match self.regs.get_mut(&addr)? {
+            Register::Latch { value, .. } => Some(*value),
+            Register::Handlers { read, .. } => read.as_mut().map(|v| v()),
+        }
+    }
+
+    /// only latches can be peeked, handlers might have side effects.
+    fn peek(&self, addr: usize) -> Option<u8> {
+        self.get_latch(addr)
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        match self.regs.get_mut(&addr)? {
+            Register::Latch { value, .. } => *value = data,
+            Register::Handlers { write, .. } => write.as_mut()?(data),
+        }
+        Some(())
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.set_latch(addr, data).then_some(())
+    }
+
+    /// the latches in offset order, handlers keep their state outside the block.
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let latches = self.regs.keys().filter_map(|v| self.get_latch(*v));
+        Some(latches.collect())
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        if state.len() != self.latches().count() {
+            return None;
+        }
+        self.latches().zip(state).for_each(|(to, from)| *to = *from);
+        Some(())
+    }
+}
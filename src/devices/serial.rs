@@ -1,6 +1,9 @@
 use std::{
     io::{Read, Write},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     thread::{self},
 };
 
@@ -11,6 +14,7 @@ pub struct SerialIO<S> {
     detached: Arc<Mutex<bool>>,
     read_keys: Vec<u8>,
     display_keys: Arc<Mutex<Vec<u8>>>,
+    break_key: Option<(u8, BreakSignal)>,
 }
 impl<S: Write + Read + Send + 'static> SerialIO<S> {
     pub fn new(s: S) -> Self {
@@ -19,8 +23,22 @@ impl<S: Write + Read + Send + 'static> SerialIO<S> {
             detached: Arc::new(Mutex::new(true)),
             read_keys: vec![],
             display_keys: Arc::new(Mutex::new(vec![])),
+            break_key: None,
         }
     }
+
+    /// swallow _key_ when it arrives from the host and raise the returned signal instead,
+    /// so a frontend can break into its monitor while every other byte,
+    /// Ctrl-C ($03) included, still reaches the emulated program.
+    pub fn set_break_key(&mut self, key: u8) -> BreakSignal {
+        let signal = BreakSignal::default();
+        self.break_key = Some((key, signal.clone()));
+        signal
+    }
+
+    pub fn clear_break_key(&mut self) {
+        self.break_key = None;
+    }
 }
 impl<S: Write + Read + Send + 'static> Device for SerialIO<S> {
     fn reset(&mut self) {
@@ -59,11 +77,19 @@ impl<S: Write + Read + Send + 'static> Device for SerialIO<S> {
 
     fn read(&mut self, _: usize) -> Option<u8> {
         if self.read_keys.is_empty() {
-            {
-                let mut s = self.s.lock().unwrap();
-                s.read_to_end(&mut self.read_keys).ok()?;
+            let mut s = self.s.lock().unwrap();
+            // bytes read before an error, e.g. WouldBlock from a non-blocking stream, still count
+            let _ = s.read_to_end(&mut self.read_keys);
+            if let Some((key, signal)) = &self.break_key {
+                let len = self.read_keys.len();
+                self.read_keys.retain(|v| v != key);
+                if self.read_keys.len() != len {
+                    signal.raise();
+                }
             }
-            self.read(0)
+        }
+        if self.read_keys.is_empty() {
+            None
         } else {
             Some(self.read_keys.remove(0))
         }
@@ -74,3 +100,22 @@ impl<S: Write + Read + Send + 'static> Device for SerialIO<S> {
         Some(())
     }
 }
+
+/// raised by a device to ask the frontend to stop emulation, e.g. to enter a monitor.
+/// clones refer to the same signal.
+#[derive(Debug, Clone, Default)]
+pub struct BreakSignal(Arc<AtomicBool>);
+impl BreakSignal {
+    pub fn raise(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_raised(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// check and lower the signal at once.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::AcqRel)
+    }
+}
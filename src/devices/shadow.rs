@@ -0,0 +1,161 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::{devices::Interrupt, Device};
+
+/// a device laid over another, like ROM shadowing RAM or a language card. reads go to
+/// _overlay_ while it is switched in and to _underlay_ otherwise, writes always go to _underlay_.
+/// the switch is either flipped through a [`ShadowSwitch`] mapped elsewhere, or directly.
+/// the overlay is switched in after a reset.
+pub struct Shadow<O, U> {
+    overlay: O,
+    underlay: U,
+    switch: ShadowSwitch,
+}
+impl<O: Device, U: Device> Shadow<O, U> {
+    pub fn new(overlay: O, underlay: U) -> Self {
+        let switch = ShadowSwitch::default();
+        switch.set(true);
+        Self {
+            overlay,
+            underlay,
+            switch,
+        }
+    }
+
+    /// the switch, to be mapped into a layout as a single register.
+    pub fn switch(&self) -> ShadowSwitch {
+        self.switch.clone()
+    }
+
+    pub fn is_overlay_enabled(&self) -> bool {
+        self.switch.get()
+    }
+
+    pub fn set_overlay_enabled(&self, enabled: bool) {
+        self.switch.set(enabled);
+    }
+
+    pub fn overlay(&self) -> &O {
+        &self.overlay
+    }
+
+    pub fn overlay_mut(&mut self) -> &mut O {
+        &mut self.overlay
+    }
+
+    pub fn underlay(&self) -> &U {
+        &self.underlay
+    }
+
+    pub fn underlay_mut(&mut self) -> &mut U {
+        &mut self.underlay
+    }
+}
+impl<O: Device + 'static, U: Device + 'static> Device for Shadow<O, U> {
+    fn attach(&mut self) {
+        self.overlay.attach();
+        self.underlay.attach();
+    }
+
+    fn detach(&mut self) {
+        self.overlay.detach();
+        self.underlay.detach();
+    }
+
+    fn reset(&mut self) {
+        self.switch.set(true);
+        self.overlay.reset();
+        self.underlay.reset();
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.overlay.tick(cycles);
+        self.underlay.tick(cycles);
+    }
+
+    fn acknowledge(&mut self, interrupt: Interrupt) -> Option<u16> {
+        let vector = self.overlay.acknowledge(interrupt);
+        vector.or(self.underlay.acknowledge(interrupt))
+    }
+
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        if self.is_overlay_enabled() {
+            self.overlay.read(addr)
+        } else {
+            self.underlay.read(addr)
+        }
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        if self.is_overlay_enabled() {
+            self.overlay.peek(addr)
+        } else {
+            self.underlay.peek(addr)
+        }
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.underlay.write(addr, data)
+    }
+
+    /// patches the device reads currently go to.
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        if self.is_overlay_enabled() {
+            self.overlay.poke(addr, data)
+        } else {
+            self.underlay.poke(addr, data)
+        }
+    }
+
+    /// the switch followed by the state of the underlay, the overlay is expected to be ROM.
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let mut state = vec![self.is_overlay_enabled() as u8];
+        state.extend(self.underlay.save_state().unwrap_or_default());
+        Some(state)
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        let (&enabled, underlay) = state.split_first()?;
+        if !underlay.is_empty() {
+            self.underlay.restore_state(underlay)?;
+        }
+        self.set_overlay_enabled(enabled != 0);
+        Some(())
+    }
+}
+
+/// the switch of a [`Shadow`]. clones refer to the same switch.
+/// as a device it is a single read/write register, repeated across its whole mapping,
+/// any value but 0 switches the overlay in.
+#[derive(Debug, Clone, Default)]
+pub struct ShadowSwitch(Arc<AtomicBool>);
+impl ShadowSwitch {
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Release);
+    }
+}
+impl Device for ShadowSwitch {
+    fn read(&mut self, _: usize) -> Option<u8> {
+        Some(self.get() as u8)
+    }
+
+    fn peek(&self, _: usize) -> Option<u8> {
+        Some(self.get() as u8)
+    }
+
+    fn write(&mut self, _: usize, data: u8) -> Option<()> {
+        self.set(data != 0);
+        Some(())
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.write(addr, data)
+    }
+}
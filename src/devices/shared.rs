@@ -0,0 +1,101 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use crate::{devices::Interrupt, Device};
+
+/// a device the host keeps a handle to, e.g. to press keys, while the layout routes bus accesses to it.
+impl<D: Device + 'static> Device for Rc<RefCell<D>> {
+    fn attach(&mut self) {
+        self.borrow_mut().attach();
+    }
+
+    fn detach(&mut self) {
+        self.borrow_mut().detach();
+    }
+
+    fn reset(&mut self) {
+        self.borrow_mut().reset();
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.borrow_mut().tick(cycles);
+    }
+
+    fn acknowledge(&mut self, interrupt: Interrupt) -> Option<u16> {
+        self.borrow_mut().acknowledge(interrupt)
+    }
+
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        self.borrow_mut().read(addr)
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        self.borrow().peek(addr)
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.borrow_mut().poke(addr, data)
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        self.borrow().save_state()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        self.borrow_mut().restore_state(state)
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.borrow_mut().write(addr, data)
+    }
+}
+
+/// like the [`Rc<RefCell<D>>`] impl, for devices also touched from other threads.
+impl<D: Device + 'static> Device for Arc<Mutex<D>> {
+    fn attach(&mut self) {
+        self.lock().unwrap().attach();
+    }
+
+    fn detach(&mut self) {
+        self.lock().unwrap().detach();
+    }
+
+    fn reset(&mut self) {
+        self.lock().unwrap().reset();
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.lock().unwrap().tick(cycles);
+    }
+
+    fn acknowledge(&mut self, interrupt: Interrupt) -> Option<u16> {
+        self.lock().unwrap().acknowledge(interrupt)
+    }
+
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        self.lock().unwrap().read(addr)
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        self.lock().unwrap().peek(addr)
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.lock().unwrap().poke(addr, data)
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        self.lock().unwrap().save_state()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        self.lock().unwrap().restore_state(state)
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.lock().unwrap().write(addr, data)
+    }
+}
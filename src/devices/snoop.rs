@@ -0,0 +1,92 @@
+use std::ops::Range;
+
+use crate::{devices::Interrupt, Device};
+
+/// a device that lets _primary_ handle every access while also forwarding
+/// writes within _range_ to _secondary_, with _range.start_ becoming address 0 on _secondary_.
+pub struct Snoop<P, S> {
+    primary: P,
+    secondary: S,
+    range: Range<usize>,
+}
+impl<P: Device, S: Device> Snoop<P, S> {
+    pub fn new(primary: P, secondary: S, range: Range<usize>) -> Self {
+        Self {
+            primary,
+            secondary,
+            range,
+        }
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    pub fn primary_mut(&mut self) -> &mut P {
+        &mut self.primary
+    }
+
+    pub fn secondary(&self) -> &S {
+        &self.secondary
+    }
+
+    pub fn secondary_mut(&mut self) -> &mut S {
+        &mut self.secondary
+    }
+}
+impl<P: Device + 'static, S: Device + 'static> Device for Snoop<P, S> {
+    fn attach(&mut self) {
+        self.primary.attach();
+        self.secondary.attach();
+    }
+
+    fn detach(&mut self) {
+        self.primary.detach();
+        self.secondary.detach();
+    }
+
+    fn reset(&mut self) {
+        self.primary.reset();
+        self.secondary.reset();
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.primary.tick(cycles);
+        self.secondary.tick(cycles);
+    }
+
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        self.primary.read(addr)
+    }
+
+    fn acknowledge(&mut self, interrupt: Interrupt) -> Option<u16> {
+        let vector = self.primary.acknowledge(interrupt);
+        vector.or(self.secondary.acknowledge(interrupt))
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        self.primary.peek(addr)
+    }
+
+    /// the secondary does not see pokes.
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.primary.poke(addr, data)
+    }
+
+    /// only the primary is saved, the secondary merely observes.
+    fn save_state(&self) -> Option<Vec<u8>> {
+        self.primary.save_state()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        self.primary.restore_state(state)
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        if self.range.contains(&addr) {
+            // the secondary only observes, its result does not matter
+            let _ = self.secondary.write(addr - self.range.start, data);
+        }
+        self.primary.write(addr, data)
+    }
+}
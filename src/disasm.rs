@@ -0,0 +1,121 @@
+use core::fmt;
+use std::ops::Range;
+
+use crate::{inst::decode_inst, AddressingMode, CpuModel, Device, Layout, SymbolTable};
+
+/// a disassembled instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    /// the opcode and its operand bytes.
+    pub bytes: Vec<u8>,
+    /// as written in assembly, e.g. `LDA` or `BBS3`. bytes that are no instruction on the model
+    /// disassemble as `.byte`, unreadable bytes as `???`.
+    pub mnemonic: String,
+    /// as written in assembly, e.g. `($12),Y`. branch targets are absolute addresses.
+    /// addresses with a symbol show as the symbol.
+    pub operand: String,
+}
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<_> = self.bytes.iter().map(|v| format!("{:02X}", v)).collect();
+        write!(
+            f,
+            "{:04X}  {: <8}  {}",
+            self.addr,
+            bytes.join(" "),
+            self.mnemonic
+        )?;
+        if !self.operand.is_empty() {
+            write!(f, " {}", self.operand)?;
+        }
+        Ok(())
+    }
+}
+
+/// disassemble the instructions of _model_ starting within _range_ of _layout_, reading it
+/// through [`Device::peek`] so that no device observes it. the stable undocumented opcodes
+/// of NMOS parts disassemble to their common names. operands are shown by their symbol
+/// in _symbols_ where they have one.
+pub fn disassemble(
+    layout: &Layout,
+    range: Range<usize>,
+    model: CpuModel,
+    symbols: Option<&SymbolTable>,
+) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut addr = range.start;
+    while addr < range.end {
+        let line = disassemble_one(layout, addr as u16, model, symbols);
+        addr += line.bytes.len().max(1);
+        lines.push(line);
+    }
+    lines
+}
+
+/// disassemble the single instruction at _addr_, see [`disassemble`].
+pub fn disassemble_one(
+    layout: &Layout,
+    addr: u16,
+    model: CpuModel,
+    symbols: Option<&SymbolTable>,
+) -> Line {
+    let Some(opcode) = layout.peek(addr as usize) else {
+        return Line {
+            addr,
+            bytes: vec![],
+            mnemonic: "???".to_owned(),
+            operand: String::new(),
+        };
+    };
+    let Ok((inst, addr_mode)) = decode_inst(opcode, model, true) else {
+        return Line {
+            addr,
+            bytes: vec![opcode],
+            mnemonic: ".byte".to_owned(),
+            operand: format!("${:02X}", opcode),
+        };
+    };
+
+    let arg = |n: u16| {
+        layout
+            .peek(addr.wrapping_add(n) as usize)
+            .unwrap_or_default()
+    };
+    let len = addr_mode.operand_len();
+    let bytes: Vec<u8> = (0..=len).map(arg).collect();
+    let name = |addr: u16| symbols?.name(addr).map(|v| v.to_owned());
+    let zp = |v: u8| name(v as u16).unwrap_or_else(|| format!("${:02X}", v));
+    let abs = |v: u16| name(v).unwrap_or_else(|| format!("${:04X}", v));
+    let byte = arg(1);
+    let word = u16::from_le_bytes([arg(1), arg(2)]);
+    let branch = |offset: u8, next: u16| addr.wrapping_add(next).wrapping_add(offset as i8 as u16);
+
+    let mnemonic = inst.to_string();
+    let operand = match addr_mode {
+        AddressingMode::Implied => match mnemonic.as_str() {
+            "ASL" | "LSR" | "ROL" | "ROR" | "INC" | "DEC" => "A".to_owned(),
+            _ => String::new(),
+        },
+        AddressingMode::Immediate => format!("#${:02X}", byte),
+        AddressingMode::ZeroPage => zp(byte),
+        AddressingMode::ZeroPageX => format!("{},X", zp(byte)),
+        AddressingMode::ZeroPageY => format!("{},Y", zp(byte)),
+        AddressingMode::Absolute => abs(word),
+        AddressingMode::AbsoluteX => format!("{},X", abs(word)),
+        AddressingMode::AbsoluteY => format!("{},Y", abs(word)),
+        AddressingMode::Indirect => format!("({})", abs(word)),
+        AddressingMode::XIndirect => format!("({},X)", zp(byte)),
+        AddressingMode::IndirectY => format!("({}),Y", zp(byte)),
+        AddressingMode::ZeroPageIndirect => format!("({})", zp(byte)),
+        AddressingMode::AbsoluteXIndirect => format!("({},X)", abs(word)),
+        AddressingMode::Relative => abs(branch(byte, 2)),
+        AddressingMode::ZeroPageRelative => format!("{},{}", zp(byte), abs(branch(arg(2), 3))),
+    };
+    Line {
+        addr,
+        bytes,
+        mnemonic,
+        operand,
+    }
+}
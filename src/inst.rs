@@ -1,3 +1,7 @@
+use core::fmt;
+
+use crate::{CpuModel, ExecutionError};
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Inst {
@@ -8,6 +12,7 @@ pub enum Inst {
     STA,
     STX,
     STY,
+    STZ,
 
     TAX,
     TAY,
@@ -74,11 +79,55 @@ pub enum Inst {
     RTI,
 
     BIT,
+    TRB,
+    TSB,
+
+    /// reset the bit of a zero page byte.
+    RMB(u8),
+    /// set the bit of a zero page byte.
+    SMB(u8),
+    /// branch if the bit of a zero page byte is reset.
+    BBR(u8),
+    /// branch if the bit of a zero page byte is set.
+    BBS(u8),
 
     NOP,
+    WAI,
+    STP,
+
+    // undocumented NMOS instructions
+    /// ASL then ORA.
+    SLO,
+    /// ROL then AND.
+    RLA,
+    /// LSR then EOR.
+    SRE,
+    /// ROR then ADC.
+    RRA,
+    /// store A AND X.
+    SAX,
+    /// LDA and LDX at once.
+    LAX,
+    /// DEC then CMP.
+    DCP,
+    /// INC then SBC.
+    ISC,
+    /// AND, copying N to C.
+    ANC,
+    /// AND then LSR A.
+    ALR,
+    /// AND then ROR A, with C and V taken from the result bits 6 and 5.
+    ARR,
+    /// X = A AND X minus the operand, setting flags like CMP.
+    SBX,
+    /// A, X and SP = memory AND SP.
+    LAS,
+    /// lock up until reset.
+    JAM,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// how an instruction finds its operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AddressingMode {
     Implied,
     Immediate,
@@ -92,11 +141,92 @@ pub enum AddressingMode {
     ZeroPage,
     ZeroPageX,
     ZeroPageY,
+    /// `(zp)`, 65C02 only.
+    ZeroPageIndirect,
+    /// `(abs,X)`, only taken by the 65C02's JMP.
+    AbsoluteXIndirect,
+    /// `zp,rel`, a zero page address followed by a branch offset, taken by BBR and BBS.
+    ZeroPageRelative,
 }
 
-pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
+impl AddressingMode {
+    /// bytes following the opcode.
+    pub fn operand_len(self) -> u16 {
+        match self {
+            AddressingMode::Implied => 0,
+            AddressingMode::Immediate
+            | AddressingMode::XIndirect
+            | AddressingMode::IndirectY
+            | AddressingMode::Relative
+            | AddressingMode::ZeroPage
+            | AddressingMode::ZeroPageX
+            | AddressingMode::ZeroPageY
+            | AddressingMode::ZeroPageIndirect => 1,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect
+            | AddressingMode::AbsoluteXIndirect
+            | AddressingMode::ZeroPageRelative => 2,
+        }
+    }
+}
+
+/// the mnemonic as written in assembly, with the bit number of the bit instructions appended.
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Inst::RMB(bit) => write!(f, "RMB{}", bit),
+            Inst::SMB(bit) => write!(f, "SMB{}", bit),
+            Inst::BBR(bit) => write!(f, "BBR{}", bit),
+            Inst::BBS(bit) => write!(f, "BBS{}", bit),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// decode _byte_ as executed by _model_, telling apart opcodes that do not exist on _model_
+/// from ones that exist but are not emulated.
+/// with _undocumented_ set, the stable undocumented opcodes of NMOS parts decode too.
+pub fn decode_inst(
+    byte: u8,
+    model: CpuModel,
+    undocumented: bool,
+) -> Result<(Inst, AddressingMode), ExecutionError> {
+    let decoded = match mnemonic(byte, model) {
+        Some("NOP") => nop_addressing_mode(byte).map(|v| (Inst::NOP, v)),
+        Some(_) => decode(byte),
+        // the NMOS parts lock up on these whether or not undocumented opcodes are wanted
+        None if !model.is_cmos() && is_jam(byte) => Some((Inst::JAM, AddressingMode::Implied)),
+        None if undocumented && !model.is_cmos() => decode_undocumented(byte),
+        None => None,
+    };
+
+    decoded.ok_or_else(|| match mnemonic(byte, model) {
+        Some(mnemonic) => ExecutionError::UnimplementedInst {
+            opcode: byte,
+            model,
+            mnemonic,
+        },
+        None => ExecutionError::UndefinedInst {
+            opcode: byte,
+            model,
+            elsewhere: [
+                CpuModel::NMOS6502,
+                CpuModel::WDC65C02,
+                CpuModel::Rockwell65C02,
+            ]
+            .into_iter()
+            .filter(|v| *v != model)
+            .find_map(|v| mnemonic(byte, v).filter(|m| *m != "NOP").map(|m| (m, v))),
+        },
+    })
+}
+
+fn decode(byte: u8) -> Option<(Inst, AddressingMode)> {
     use AddressingMode::*;
     use Inst::*;
+    let bit = (byte >> 4) & 0b111;
     Some(match byte {
         0xA9 => (LDA, Immediate),
         0xA5 => (LDA, ZeroPage),
@@ -106,6 +236,7 @@ pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
         0xB9 => (LDA, AbsoluteY),
         0xA1 => (LDA, XIndirect),
         0xB1 => (LDA, IndirectY),
+        0xB2 => (LDA, ZeroPageIndirect),
 
         0xA2 => (LDX, Immediate),
         0xA6 => (LDX, ZeroPage),
@@ -126,6 +257,7 @@ pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
         0x99 => (STA, AbsoluteY),
         0x81 => (STA, XIndirect),
         0x91 => (STA, IndirectY),
+        0x92 => (STA, ZeroPageIndirect),
 
         0x86 => (STX, ZeroPage),
         0x96 => (STX, ZeroPageY),
@@ -135,6 +267,11 @@ pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
         0x94 => (STY, ZeroPageX),
         0x8C => (STY, Absolute),
 
+        0x64 => (STZ, ZeroPage),
+        0x74 => (STZ, ZeroPageX),
+        0x9C => (STZ, Absolute),
+        0x9E => (STZ, AbsoluteX),
+
         0xAA => (TAX, Implied),
         0xA8 => (TAY, Implied),
         0xBA => (TSX, Implied),
@@ -174,6 +311,7 @@ pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
         0x79 => (ADC, AbsoluteY),
         0x61 => (ADC, XIndirect),
         0x71 => (ADC, IndirectY),
+        0x72 => (ADC, ZeroPageIndirect),
 
         0xE9 => (SBC, Immediate),
         0xE5 => (SBC, ZeroPage),
@@ -183,6 +321,7 @@ pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
         0xF9 => (SBC, AbsoluteY),
         0xE1 => (SBC, XIndirect),
         0xF1 => (SBC, IndirectY),
+        0xF2 => (SBC, ZeroPageIndirect),
 
         0x29 => (AND, Immediate),
         0x25 => (AND, ZeroPage),
@@ -192,6 +331,7 @@ pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
         0x39 => (AND, AbsoluteY),
         0x21 => (AND, XIndirect),
         0x31 => (AND, IndirectY),
+        0x32 => (AND, ZeroPageIndirect),
 
         0x49 => (EOR, Immediate),
         0x45 => (EOR, ZeroPage),
@@ -201,6 +341,7 @@ pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
         0x59 => (EOR, AbsoluteY),
         0x41 => (EOR, XIndirect),
         0x51 => (EOR, IndirectY),
+        0x52 => (EOR, ZeroPageIndirect),
 
         0x09 => (ORA, Immediate),
         0x05 => (ORA, ZeroPage),
@@ -210,6 +351,7 @@ pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
         0x19 => (ORA, AbsoluteY),
         0x01 => (ORA, XIndirect),
         0x11 => (ORA, IndirectY),
+        0x12 => (ORA, ZeroPageIndirect),
 
         0x0A => (ASL, Implied),
         0x06 => (ASL, ZeroPage),
@@ -251,6 +393,7 @@ pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
         0xD9 => (CMP, AbsoluteY),
         0xC1 => (CMP, XIndirect),
         0xD1 => (CMP, IndirectY),
+        0xD2 => (CMP, ZeroPageIndirect),
 
         0xE0 => (CPX, Immediate),
         0xE4 => (CPX, ZeroPage),
@@ -272,6 +415,7 @@ pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
 
         0x4C => (JMP, Absolute),
         0x6C => (JMP, Indirect),
+        0x7C => (JMP, AbsoluteXIndirect),
         0x20 => (JSR, Absolute),
         0x60 => (RTS, Implied),
 
@@ -280,9 +424,262 @@ pub fn decode_inst(byte: u8) -> Option<(Inst, AddressingMode)> {
 
         0x24 => (BIT, ZeroPage),
         0x2C => (BIT, Absolute),
+        0x89 => (BIT, Immediate),
+        0x34 => (BIT, ZeroPageX),
+        0x3C => (BIT, AbsoluteX),
+
+        0x04 => (TSB, ZeroPage),
+        0x0C => (TSB, Absolute),
+        0x14 => (TRB, ZeroPage),
+        0x1C => (TRB, Absolute),
+
+        0xCB => (WAI, Implied),
+        0xDB => (STP, Implied),
+
+        _ if byte & 0x0F == 0x07 && byte < 0x80 => (RMB(bit), ZeroPage),
+        _ if byte & 0x0F == 0x07 => (SMB(bit), ZeroPage),
+        _ if byte & 0x0F == 0x0F && byte < 0x80 => (BBR(bit), ZeroPageRelative),
+        _ if byte & 0x0F == 0x0F => (BBS(bit), ZeroPageRelative),
+
+        _ => return None,
+    })
+}
+
+/// the NMOS opcodes that lock up the CPU until a reset.
+fn is_jam(byte: u8) -> bool {
+    matches!(
+        byte,
+        0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2
+    )
+}
+
+/// the undocumented NMOS opcodes that behave the same on every chip. the ones depending on
+/// analog effects, e.g. XAA and SHA, are left out, the ones locking up the CPU are
+/// decoded as JAM by [`is_jam`] instead.
+fn decode_undocumented(byte: u8) -> Option<(Inst, AddressingMode)> {
+    use AddressingMode::*;
+    use Inst::*;
+    // the read-modify-write combinations share the addressing modes of their column
+    let rmw_mode = || {
+        Some(match byte & 0x1F {
+            0x03 => XIndirect,
+            0x07 => ZeroPage,
+            0x0F => Absolute,
+            0x13 => IndirectY,
+            0x17 => ZeroPageX,
+            0x1B => AbsoluteY,
+            0x1F => AbsoluteX,
+            _ => return None,
+        })
+    };
+    Some(match byte {
+        0x87 => (SAX, ZeroPage),
+        0x97 => (SAX, ZeroPageY),
+        0x8F => (SAX, Absolute),
+        0x83 => (SAX, XIndirect),
+
+        0xA7 => (LAX, ZeroPage),
+        0xB7 => (LAX, ZeroPageY),
+        0xAF => (LAX, Absolute),
+        0xBF => (LAX, AbsoluteY),
+        0xA3 => (LAX, XIndirect),
+        0xB3 => (LAX, IndirectY),
+
+        0x0B | 0x2B => (ANC, Immediate),
+        0x4B => (ALR, Immediate),
+        0x6B => (ARR, Immediate),
+        0xCB => (SBX, Immediate),
+        0xEB => (SBC, Immediate),
+        0xBB => (LAS, AbsoluteY),
+
+        0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => (NOP, Implied),
+        0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 => (NOP, Immediate),
+        0x04 | 0x44 | 0x64 => (NOP, ZeroPage),
+        0x14 | 0x34 | 0x54 | 0x74 | 0xD4 | 0xF4 => (NOP, ZeroPageX),
+        0x0C => (NOP, Absolute),
+        0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => (NOP, AbsoluteX),
+
+        0x00..=0x1F => (SLO, rmw_mode()?),
+        0x20..=0x3F => (RLA, rmw_mode()?),
+        0x40..=0x5F => (SRE, rmw_mode()?),
+        0x60..=0x7F => (RRA, rmw_mode()?),
+        0xC0..=0xDF => (DCP, rmw_mode()?),
+        0xE0..=0xFF => (ISC, rmw_mode()?),
+        _ => return None,
+    })
+}
 
-        0xEA => (NOP, Implied),
+/// cycles taken by _inst_ in _addr_mode_ on _model_, before any page crossing or branch penalties.
+pub fn base_cycles(inst: Inst, addr_mode: AddressingMode, model: CpuModel) -> u8 {
+    use AddressingMode::*;
+    use Inst::*;
+    match inst {
+        LDA | LDX | LDY | ADC | SBC | AND | EOR | ORA | CMP | CPX | CPY | BIT | LAX | LAS | ANC
+        | ALR | ARR | SBX => match addr_mode {
+            Immediate => 2,
+            ZeroPage => 3,
+            ZeroPageX | ZeroPageY | Absolute | AbsoluteX | AbsoluteY => 4,
+            XIndirect => 6,
+            IndirectY | ZeroPageIndirect => 5,
+            _ => 2,
+        },
+        STA | STX | STY | STZ | SAX => match addr_mode {
+            ZeroPage => 3,
+            ZeroPageX | ZeroPageY | Absolute => 4,
+            AbsoluteX | AbsoluteY | ZeroPageIndirect => 5,
+            XIndirect | IndirectY => 6,
+            _ => 2,
+        },
+        DEC | INC | ASL | LSR | ROL | ROR | TRB | TSB | RMB(_) | SMB(_) => match addr_mode {
+            Implied => 2,
+            ZeroPage => 5,
+            ZeroPageX | Absolute => 6,
+            // the 65C02 shifts skip the extra cycle unless the index crosses a page
+            AbsoluteX if model.is_cmos() && matches!(inst, ASL | LSR | ROL | ROR) => 6,
+            AbsoluteX => 7,
+            _ => 2,
+        },
+        PHA | PHP | PHX | PHY => 3,
+        PLA | PLP | PLX | PLY => 4,
+        JMP => match addr_mode {
+            // the 65C02 spends a cycle fixing the page wrap of the NMOS part
+            Indirect if !model.is_cmos() => 5,
+            Indirect | AbsoluteXIndirect => 6,
+            _ => 3,
+        },
+        JSR | RTS | RTI => 6,
+        BRK => 7,
+        // taken branches add their penalty when executed
+        BBR(_) | BBS(_) => 5,
+        SLO | RLA | SRE | RRA | DCP | ISC => match addr_mode {
+            ZeroPage => 5,
+            ZeroPageX | Absolute => 6,
+            AbsoluteX | AbsoluteY => 7,
+            _ => 8,
+        },
+        WAI | STP | JAM => 3,
+        NOP => match addr_mode {
+            ZeroPage => 3,
+            ZeroPageX | Absolute | AbsoluteX => 4,
+            _ => 2,
+        },
+        TAX | TAY | TSX | TXA | TXS | TYA | DEX | DEY | INX | INY | CLC | CLD | CLI | CLV | SEC
+        | SED | SEI | BCC | BCS | BEQ | BNE | BMI | BPL | BVC | BVS | BRA => 2,
+    }
+}
 
+/// operand _byte_ takes when it is a NOP, the 65C02's unused opcodes skip the bytes
+/// and spend the cycles of the instruction they sit next to.
+fn nop_addressing_mode(byte: u8) -> Option<AddressingMode> {
+    use AddressingMode::*;
+    Some(match byte {
+        0xEA => Implied,
+        _ if byte & 0x03 == 0x03 => Implied,
+        _ if byte & 0x0F == 0x02 => Immediate,
+        0x44 => ZeroPage,
+        0x54 | 0xD4 | 0xF4 => ZeroPageX,
+        0x5C | 0xDC | 0xFC => Absolute,
         _ => return None,
     })
 }
+
+/// whether _inst_ on _model_ takes an extra cycle when its indexed operand lies in another page
+/// than the base address. stores and most read-modify-write instructions always take it,
+/// which [`base_cycles`] includes.
+pub fn has_page_penalty(inst: Inst, model: CpuModel) -> bool {
+    use Inst::*;
+    match inst {
+        LDA | LDX | LDY | ADC | SBC | AND | EOR | ORA | CMP | BIT | LAX | LAS | NOP => true,
+        ASL | LSR | ROL | ROR => model.is_cmos(),
+        _ => false,
+    }
+}
+
+/// cycles taken by the 65C02 NOP at _byte_, which unlike other instructions differ between
+/// opcodes of the same addressing mode.
+pub fn nop_cycles(byte: u8) -> u8 {
+    match byte {
+        _ if byte & 0x03 == 0x03 => 1,
+        0x5C => 8,
+        _ => base_cycles(
+            Inst::NOP,
+            nop_addressing_mode(byte).unwrap_or(AddressingMode::Implied),
+            CpuModel::WDC65C02,
+        ),
+    }
+}
+
+/// documented mnemonic of _byte_ on _model_, `None` if the opcode is undefined there.
+/// CMOS parts define every opcode, unused ones act as NOPs.
+pub fn mnemonic(byte: u8, model: CpuModel) -> Option<&'static str> {
+    const RMB: [&str; 8] = [
+        "RMB0", "RMB1", "RMB2", "RMB3", "RMB4", "RMB5", "RMB6", "RMB7",
+    ];
+    const SMB: [&str; 8] = [
+        "SMB0", "SMB1", "SMB2", "SMB3", "SMB4", "SMB5", "SMB6", "SMB7",
+    ];
+    const BBR: [&str; 8] = [
+        "BBR0", "BBR1", "BBR2", "BBR3", "BBR4", "BBR5", "BBR6", "BBR7",
+    ];
+    const BBS: [&str; 8] = [
+        "BBS0", "BBS1", "BBS2", "BBS3", "BBS4", "BBS5", "BBS6", "BBS7",
+    ];
+
+    let nmos = NMOS_MNEMONICS[byte as usize];
+    if !model.is_cmos() {
+        return (!nmos.is_empty()).then_some(nmos);
+    }
+
+    let bit = (byte >> 4) as usize & 0b111;
+    Some(match byte {
+        0x04 | 0x0C => "TSB",
+        0x14 | 0x1C => "TRB",
+        0x12 => "ORA",
+        0x32 => "AND",
+        0x52 => "EOR",
+        0x72 => "ADC",
+        0x92 => "STA",
+        0xB2 => "LDA",
+        0xD2 => "CMP",
+        0xF2 => "SBC",
+        0x1A => "INC",
+        0x3A => "DEC",
+        0x34 | 0x3C | 0x89 => "BIT",
+        0x5A => "PHY",
+        0x7A => "PLY",
+        0xDA => "PHX",
+        0xFA => "PLX",
+        0x64 | 0x74 | 0x9C | 0x9E => "STZ",
+        0x7C => "JMP",
+        0x80 => "BRA",
+        0xCB if model == CpuModel::WDC65C02 => "WAI",
+        0xDB if model == CpuModel::WDC65C02 => "STP",
+        _ if byte & 0x0F == 0x07 && byte < 0x80 => RMB[bit],
+        _ if byte & 0x0F == 0x07 => SMB[bit],
+        _ if byte & 0x0F == 0x0F && byte < 0x80 => BBR[bit],
+        _ if byte & 0x0F == 0x0F => BBS[bit],
+        _ if !nmos.is_empty() => nmos,
+        _ => "NOP",
+    })
+}
+
+/// mnemonics of the documented NMOS 6502 opcodes, empty where the opcode is undefined.
+#[rustfmt::skip]
+const NMOS_MNEMONICS: [&str; 256] = [
+    "BRK", "ORA", "", "", "", "ORA", "ASL", "", "PHP", "ORA", "ASL", "", "", "ORA", "ASL", "",
+    "BPL", "ORA", "", "", "", "ORA", "ASL", "", "CLC", "ORA", "", "", "", "ORA", "ASL", "",
+    "JSR", "AND", "", "", "BIT", "AND", "ROL", "", "PLP", "AND", "ROL", "", "BIT", "AND", "ROL", "",
+    "BMI", "AND", "", "", "", "AND", "ROL", "", "SEC", "AND", "", "", "", "AND", "ROL", "",
+    "RTI", "EOR", "", "", "", "EOR", "LSR", "", "PHA", "EOR", "LSR", "", "JMP", "EOR", "LSR", "",
+    "BVC", "EOR", "", "", "", "EOR", "LSR", "", "CLI", "EOR", "", "", "", "EOR", "LSR", "",
+    "RTS", "ADC", "", "", "", "ADC", "ROR", "", "PLA", "ADC", "ROR", "", "JMP", "ADC", "ROR", "",
+    "BVS", "ADC", "", "", "", "ADC", "ROR", "", "SEI", "ADC", "", "", "", "ADC", "ROR", "",
+    "", "STA", "", "", "STY", "STA", "STX", "", "DEY", "", "TXA", "", "STY", "STA", "STX", "",
+    "BCC", "STA", "", "", "STY", "STA", "STX", "", "TYA", "STA", "TXS", "", "", "STA", "", "",
+    "LDY", "LDA", "LDX", "", "LDY", "LDA", "LDX", "", "TAY", "LDA", "TAX", "", "LDY", "LDA", "LDX", "",
+    "BCS", "LDA", "", "", "LDY", "LDA", "LDX", "", "CLV", "LDA", "TSX", "", "LDY", "LDA", "LDX", "",
+    "CPY", "CMP", "", "", "CPY", "CMP", "DEC", "", "INY", "CMP", "DEX", "", "CPY", "CMP", "DEC", "",
+    "BNE", "CMP", "", "", "", "CMP", "DEC", "", "CLD", "CMP", "", "", "", "CMP", "DEC", "",
+    "CPX", "SBC", "", "", "CPX", "SBC", "INC", "", "INX", "SBC", "NOP", "", "CPX", "SBC", "INC", "",
+    "BEQ", "SBC", "", "", "", "SBC", "INC", "", "SED", "SBC", "", "", "", "SBC", "INC", "",
+];
@@ -1,17 +1,15 @@
-use std::{
-    collections::{BTreeMap, HashMap},
-    ops::Range,
-};
+use std::{cell::RefCell, collections::HashMap, fmt, ops::Range, rc::Rc};
 
-use crate::Device;
+use crate::{devices::Interrupt, Device};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct DevId(usize);
 
 pub struct LayoutBuilder {
     max_byte_cnt: usize,
-    devs: Vec<Box<dyn Device>>,
+    devs: Vec<DeviceSource>,
     mappings: Vec<MappingRequest>,
+    hole_policy: HolePolicy,
 }
 impl LayoutBuilder {
     pub fn new(max_byte_cnt: usize) -> Self {
@@ -19,12 +17,40 @@ impl LayoutBuilder {
             max_byte_cnt,
             devs: vec![],
             mappings: vec![],
+            hole_policy: HolePolicy::default(),
         }
     }
 
+    /// what happens with bytes left unassigned, like unpopulated ranges of a board.
+    /// by default they fail the build. accesses to holes are counted in the stats
+    /// of a device of their own, after the ones added.
+    pub fn set_hole_policy(&mut self, policy: HolePolicy) -> &mut Self {
+        self.hole_policy = policy;
+        self
+    }
+
     pub fn add_device(&mut self, dev: impl Device + 'static) -> DevId {
+        self.add_source(DeviceSource::Instance(Box::new(dev)))
+    }
+
+    /// add a device while keeping a handle to it, for interacting with it outside of bus accesses.
+    pub fn add_shared_device<D: Device + 'static>(&mut self, dev: D) -> (DevId, Rc<RefCell<D>>) {
+        let dev = Rc::new(RefCell::new(dev));
+        (self.add_device(dev.clone()), dev)
+    }
+
+    /// add a device that is only constructed by _factory_ once the mappings are known to be valid.
+    /// a builder holding nothing but factory devices can be instantiated any number of times.
+    pub fn add_device_factory<D: Device + 'static>(
+        &mut self,
+        factory: impl Fn() -> D + 'static,
+    ) -> DevId {
+        self.add_source(DeviceSource::Factory(Box::new(move || Box::new(factory()))))
+    }
+
+    fn add_source(&mut self, source: DeviceSource) -> DevId {
         let mem_id = DevId(self.devs.len());
-        self.devs.push(Box::new(dev));
+        self.devs.push(source);
         mem_id
     }
 
@@ -40,86 +66,274 @@ impl LayoutBuilder {
         self.mappings.push(MappingRequest {
             addr_start,
             byte_cnt,
-            dev_id,
+            dev_id: Some(dev_id),
+            period: None,
         });
 
         self
     }
 
+    /// map _byte_cnt_ bytes from _addr_start_ to the first _period_ bytes of _dev_id_,
+    /// repeated every _period_ bytes, e.g. a 4 register chip aliased across an I/O page.
+    /// the repeated bytes always start at the device's address 0.
+    pub fn assign_repeated(
+        &mut self,
+        addr_start: usize,
+        byte_cnt: usize,
+        dev_id: DevId,
+        period: usize,
+    ) -> &mut Self {
+        assert!(period > 0, "repeat period must be non-zero");
+        if byte_cnt == 0 {
+            return self;
+        }
+
+        self.mappings.push(MappingRequest {
+            addr_start,
+            byte_cnt,
+            dev_id: Some(dev_id),
+            period: Some(period),
+        });
+
+        self
+    }
+
+    /// map the first _byte_cnt_ bytes of _dev_id_ from _addr_start_, followed by
+    /// _repeat_count_ - 1 mirrors of them, e.g. 2K of RAM mirrored 4 times through $0000-$1FFF.
+    /// see [`LayoutBuilder::assign_repeated`].
+    pub fn assign_mirrored(
+        &mut self,
+        addr_start: usize,
+        byte_cnt: usize,
+        dev_id: DevId,
+        repeat_count: usize,
+    ) -> &mut Self {
+        if byte_cnt == 0 {
+            return self;
+        }
+        self.assign_repeated(addr_start, byte_cnt * repeat_count, dev_id, byte_cnt)
+    }
+
+    pub fn unassign(&mut self, addr: usize) -> &mut Self {
+        self.unassign_range(addr, 1)
+    }
+
+    /// leave _byte_cnt_ bytes from _addr_start_ unassigned, overriding any earlier assignment.
+    /// a later assignment can map the range again, otherwise it is a hole,
+    /// see [`LayoutBuilder::set_hole_policy`].
+    pub fn unassign_range(&mut self, addr_start: usize, byte_cnt: usize) -> &mut Self {
+        if byte_cnt == 0 {
+            return self;
+        }
+
+        self.mappings.push(MappingRequest {
+            addr_start,
+            byte_cnt,
+            dev_id: None,
+            period: None,
+        });
+
+        self
+    }
+
+    /// drop every assignment made to _dev_id_ so far, as if it was never mapped.
+    /// ranges it used to override fall back to whatever was assigned there before.
+    pub fn clear_device(&mut self, dev_id: DevId) -> &mut Self {
+        self.mappings.retain(|v| v.dev_id != Some(dev_id));
+        self
+    }
+
     pub fn build(self) -> Result<Layout, BuildError> {
+        let mappings = self.resolve_mappings()?;
+        let hole = self.hole_device(&mappings);
+
+        let devs = self
+            .devs
+            .into_iter()
+            .map(|source| match source {
+                DeviceSource::Instance(dev) => dev,
+                DeviceSource::Factory(factory) => factory(),
+            })
+            .chain(hole)
+            .collect();
+
+        Ok(Layout::new(self.max_byte_cnt, devs, mappings))
+    }
+
+    /// build a fresh layout without consuming the builder.
+    /// every device must have been added with [`LayoutBuilder::add_device_factory`].
+    pub fn instantiate(&self) -> Result<Layout, BuildError> {
+        let mappings = self.resolve_mappings()?;
+
+        let mut devs = Vec::with_capacity(self.devs.len());
+        for (i, source) in self.devs.iter().enumerate() {
+            match source {
+                DeviceSource::Instance(_) => return Err(BuildError::DeviceNotReusable(DevId(i))),
+                DeviceSource::Factory(factory) => devs.push(factory),
+            }
+        }
+
+        Ok(Layout::new(
+            self.max_byte_cnt,
+            devs.into_iter()
+                .map(|factory| factory())
+                .chain(self.hole_device(&mappings))
+                .collect(),
+            mappings,
+        ))
+    }
+
+    /// the device answering for the holes in _mappings_, if there are any.
+    fn hole_device(&self, mappings: &[Mapping]) -> Option<Box<dyn Device>> {
+        let hole_id = DevId(self.devs.len());
+        mappings
+            .iter()
+            .any(|v| v.mem_id == hole_id)
+            .then(|| Box::new(Hole(self.hole_policy)) as Box<dyn Device>)
+    }
+
+    /// the mappings in address order, together covering the whole address space.
+    fn resolve_mappings(&self) -> Result<Vec<Mapping>, BuildError> {
         // heresy below
 
-        let mut space: Vec<DevId> = vec![DevId(usize::MAX); self.max_byte_cnt];
+        let unassigned = Slot {
+            dev_id: DevId(usize::MAX),
+            repeat: None,
+        };
+        let mut space: Vec<Slot> = vec![unassigned; self.max_byte_cnt];
 
-        for MappingRequest {
+        for &MappingRequest {
             addr_start,
             byte_cnt,
             dev_id,
-        } in self.mappings
+            period,
+        } in &self.mappings
         {
+            let dev_id = dev_id.unwrap_or(DevId(usize::MAX));
+            if dev_id.0 != usize::MAX && dev_id.0 >= self.devs.len() {
+                return Err(BuildError::InvalidMemoryId(dev_id));
+            }
             if addr_start + byte_cnt > self.max_byte_cnt {
                 return Err(BuildError::VirtualAddressOutOfRange(
                     addr_start..(addr_start + byte_cnt),
                 ));
             }
 
-            for slot in space.iter_mut().skip(addr_start).take(byte_cnt) {
-                *slot = dev_id;
+            let slot = Slot {
+                dev_id,
+                repeat: period.map(|period| (addr_start, period)),
+            };
+            for v in space.iter_mut().skip(addr_start).take(byte_cnt) {
+                *v = slot;
             }
         }
 
         for (i, slot) in space.iter().enumerate() {
-            if slot.0 == usize::MAX {
-                let range = space.iter().skip(i + 1).take_while(|v| v.0 == usize::MAX);
-                return Err(BuildError::UnassignedRange(i..(i + range.count())));
+            if slot.dev_id.0 == usize::MAX && self.hole_policy == HolePolicy::Forbidden {
+                let range = space
+                    .iter()
+                    .skip(i + 1)
+                    .take_while(|v| v.dev_id.0 == usize::MAX);
+                return Err(BuildError::UnassignedRange(i..(i + 1 + range.count())));
             }
         }
+        let hole_id = DevId(self.devs.len());
+        space
+            .iter_mut()
+            .filter(|v| v.dev_id.0 == usize::MAX)
+            .for_each(|v| v.dev_id = hole_id);
 
-        let mut mappings = BTreeMap::new();
+        let mut mappings = vec![];
         let mut phys_mapping = HashMap::new();
         let mut start = 0;
-        let mut mem_id = space[0];
+        let mut current = space[0];
 
-        for (i, slot) in space.into_iter().enumerate() {
-            if slot != mem_id {
-                let phys_addr_base = phys_mapping.entry(mem_id).or_default();
-                let offset_cnt = i - start;
-
-                mappings.insert(
-                    start,
-                    Mapping {
+        for (i, slot) in space
+            .into_iter()
+            .chain(std::iter::once(unassigned))
+            .enumerate()
+        {
+            if slot != current {
+                let mapping = match current.repeat {
+                    // repeated ranges do not take up physical addresses of their own
+                    Some((origin, period)) => Mapping {
                         virtual_addr_start: start,
-                        physical_addr_start: *phys_addr_base,
-                        mem_id,
+                        physical_addr_start: (start - origin) % period,
+                        mem_id: current.dev_id,
+                        period: Some(period),
                     },
-                );
-                *phys_addr_base += offset_cnt;
-                mem_id = slot;
+                    None => {
+                        let phys_addr_base: &mut usize =
+                            phys_mapping.entry(current.dev_id).or_default();
+                        let mapping = Mapping {
+                            virtual_addr_start: start,
+                            physical_addr_start: *phys_addr_base,
+                            mem_id: current.dev_id,
+                            period: None,
+                        };
+                        *phys_addr_base += i - start;
+                        mapping
+                    }
+                };
+                mappings.push(mapping);
+                current = slot;
                 start = i;
             }
         }
 
-        {
-            let phys_addr_base = phys_mapping.entry(mem_id).or_default();
+        Ok(mappings)
+    }
+}
 
-            mappings.insert(
-                start,
-                Mapping {
-                    virtual_addr_start: start,
-                    physical_addr_start: *phys_addr_base,
-                    mem_id,
-                },
-            );
-        }
+/// the owner of a single byte of the address space while resolving mappings.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Slot {
+    dev_id: DevId,
+    /// start and period of the repeated range the byte belongs to.
+    repeat: Option<(usize, usize)>,
+}
 
-        Ok(Layout::new(self.max_byte_cnt, self.devs, mappings))
-    }
+enum DeviceSource {
+    Instance(Box<dyn Device>),
+    Factory(Box<dyn Fn() -> Box<dyn Device>>),
 }
 
 struct MappingRequest {
     addr_start: usize,
     byte_cnt: usize,
-    dev_id: DevId,
+    dev_id: Option<DevId>,
+    period: Option<usize>,
+}
+
+/// what accesses to bytes no device is assigned to do, see [`LayoutBuilder::set_hole_policy`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HolePolicy {
+    /// unassigned bytes fail the build with [`BuildError::UnassignedRange`].
+    #[default]
+    Forbidden,
+    /// reads and writes are refused, so the CPU sees them as unmapped accesses.
+    Refused,
+    /// reads return the byte, like a floating bus settling on it, and writes are ignored.
+    Floating(u8),
+}
+
+/// the device standing in for the holes of a layout.
+struct Hole(HolePolicy);
+impl Device for Hole {
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        self.peek(addr)
+    }
+
+    fn peek(&self, _addr: usize) -> Option<u8> {
+        match self.0 {
+            HolePolicy::Floating(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, _addr: usize, _data: u8) -> Option<()> {
+        matches!(self.0, HolePolicy::Floating(_)).then_some(())
+    }
 }
 
 #[derive(Debug)]
@@ -128,38 +342,320 @@ pub enum BuildError {
     VirtualAddressOutOfRange(Range<usize>),
     MemoryOutOfRange(DevId),
     InvalidMemoryId(DevId),
+    DeviceNotReusable(DevId),
 }
 
 struct Mapping {
     virtual_addr_start: usize,
     physical_addr_start: usize,
     mem_id: DevId,
+    period: Option<usize>,
+}
+impl Mapping {
+    fn physical_addr(&self, addr: usize) -> usize {
+        let offset = self.physical_addr_start + (addr - self.virtual_addr_start);
+        match self.period {
+            Some(period) => offset % period,
+            None => offset,
+        }
+    }
+}
+
+/// bus traffic a single device has seen since the layout was built or its stats were cleared.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AccessStats {
+    pub reads: u64,
+    pub writes: u64,
+    /// reads the device did not answer.
+    pub refused_reads: u64,
+    /// writes the device did not accept.
+    pub refused_writes: u64,
+}
+impl fmt::Display for AccessStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} reads ({} refused), {} writes ({} refused)",
+            self.reads, self.refused_reads, self.writes, self.refused_writes
+        )
+    }
+}
+
+/// the direction of a bus access seen by a [`Layout::add_observer`] observer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusAccess {
+    Read,
+    Write,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(usize);
+
+type Observer = Box<dyn FnMut(usize, u8, BusAccess)>;
+
+/// the granularity of the page table and the direct memory fast path,
+/// see [`Device::direct_memory`].
+const PAGE_SIZE: usize = 0x100;
+
 pub struct Layout {
     byte_cnt: usize,
     devs: Vec<Box<dyn Device>>,
-    mappings: BTreeMap<usize, Mapping>,
+    stats: Vec<AccessStats>,
+    mappings: Vec<Mapping>,
+    /// for every page, the index of the mapping its first byte belongs to.
+    /// pages split between mappings go on from there to find the one an address is in.
+    page_table: Vec<usize>,
+    /// pages wholly mapped to direct memory, accessed without dispatching to their device.
+    pages: Vec<Option<DirectPage>>,
+    /// whether _pages_ were unmapped and must be asked for again before use.
+    pages_stale: bool,
+    /// removed observers leave a hole so ids stay valid.
+    observers: Vec<Option<Observer>>,
 }
+
+#[derive(Clone, Copy)]
+struct DirectPage {
+    ptr: *mut u8,
+    dev: usize,
+    writable: bool,
+}
+
 impl Layout {
-    fn new(
-        byte_cnt: usize,
-        devs: Vec<Box<dyn Device>>,
-        mappings: BTreeMap<usize, Mapping>,
-    ) -> Self {
-        Self {
+    fn new(byte_cnt: usize, devs: Vec<Box<dyn Device>>, mappings: Vec<Mapping>) -> Self {
+        let page_table = (0..byte_cnt.div_ceil(PAGE_SIZE))
+            .map(|page| {
+                let start = page * PAGE_SIZE;
+                mappings.partition_point(|v| v.virtual_addr_start <= start) - 1
+            })
+            .collect();
+        let mut layout = Self {
             byte_cnt,
+            stats: vec![AccessStats::default(); devs.len()],
             devs,
             mappings,
+            page_table,
+            pages: vec![],
+            pages_stale: true,
+            observers: vec![],
+        };
+        layout.map_direct_pages();
+        layout
+    }
+
+    /// ask the devices for their direct memory again, e.g. after one may have been replaced.
+    fn map_direct_pages(&mut self) {
+        let direct: Vec<_> = self.devs.iter_mut().map(|v| v.direct_memory()).collect();
+        self.pages = (0..self.byte_cnt / PAGE_SIZE)
+            .map(|page| {
+                let start = page * PAGE_SIZE;
+                let index = self.page_table[page];
+                let mapping = &self.mappings[index];
+                let next = self.mappings.get(index + 1);
+                if mapping.period.is_some()
+                    || next.is_some_and(|v| v.virtual_addr_start < start + PAGE_SIZE)
+                {
+                    return None;
+                }
+                let mem = direct[mapping.mem_id.0].as_ref()?;
+                let phys = mapping.physical_addr(start);
+                (phys + PAGE_SIZE <= mem.len).then(|| DirectPage {
+                    // SAFETY: in bounds of the memory the device vouched for
+                    ptr: unsafe { mem.ptr.add(phys) },
+                    dev: mapping.mem_id.0,
+                    writable: mem.writable,
+                })
+            })
+            .collect();
+        self.pages_stale = false;
+    }
+
+    /// forget the direct pages until the next access that may map them again, as the caller
+    /// gets the devices mutably and may replace them, freeing their memory.
+    fn unmap_direct_pages(&mut self) {
+        self.pages.clear();
+        self.pages_stale = true;
+    }
+
+    /// the direct pages are up to date, mapping them again if needed.
+    fn remap_direct_pages(&mut self) {
+        if self.pages_stale {
+            self.map_direct_pages();
         }
     }
 
+    fn direct_page(&self, addr: usize) -> Option<DirectPage> {
+        self.pages.get(addr / PAGE_SIZE).copied().flatten()
+    }
+
     pub fn get_byte_count(&self) -> usize {
         self.byte_cnt
     }
 
+    pub(crate) fn devices(&self) -> &[Box<dyn Device>] {
+        &self.devs
+    }
+
+    pub(crate) fn devices_mut(&mut self) -> &mut [Box<dyn Device>] {
+        self.unmap_direct_pages();
+        &mut self.devs
+    }
+
+    /// the device added as _dev_id_, if it is a _T_.
+    pub fn device<T: Device>(&self, dev_id: DevId) -> Option<&T> {
+        // deref the box first, the box itself is Any as well
+        (**self.devs.get(dev_id.0)?).as_any().downcast_ref()
+    }
+
+    /// the device added as _dev_id_, if it is a _T_. it may be replaced through the reference.
+    pub fn device_mut<T: Device>(&mut self, dev_id: DevId) -> Option<&mut T> {
+        self.unmap_direct_pages();
+        (**self.devs.get_mut(dev_id.0)?).as_any_mut().downcast_mut()
+    }
+
+    pub fn get_stats(&self, dev_id: DevId) -> Option<AccessStats> {
+        self.stats.get(dev_id.0).copied()
+    }
+
+    /// stats of every device, in the order they were added.
+    pub fn stats(&self) -> impl Iterator<Item = (DevId, AccessStats)> + '_ {
+        self.stats.iter().enumerate().map(|(i, v)| (DevId(i), *v))
+    }
+
+    pub fn clear_stats(&mut self) {
+        self.stats.fill(AccessStats::default());
+    }
+
+    /// one line per device, busiest first.
+    pub fn stats_report(&self) -> String {
+        let mut stats: Vec<_> = self.stats().collect();
+        stats.sort_by_key(|(_, v)| std::cmp::Reverse(v.reads + v.writes));
+        stats
+            .into_iter()
+            .map(|(id, v)| format!("device {}: {}\n", id.0, v))
+            .collect()
+    }
+
+    /// whether a device is assigned to _addr_, false for holes and addresses past the end.
+    pub fn is_mapped(&self, addr: usize) -> bool {
+        self.get_mapping_at_addr(addr)
+            .is_some_and(|v| !(*self.devs[v.mem_id.0]).as_any().is::<Hole>())
+    }
+
+    /// the resolved memory map, one line per mapped range in address order with the device
+    /// it goes to and the device address it starts at, e.g. `$8000-$8003: device 1 at $0000`.
+    pub fn describe(&self) -> String {
+        let width = if self.byte_cnt > 0x10000 { 6 } else { 4 };
+        let ends = self
+            .mappings
+            .iter()
+            .skip(1)
+            .map(|v| v.virtual_addr_start)
+            .chain([self.byte_cnt]);
+        self.mappings
+            .iter()
+            .zip(ends)
+            .map(|(v, end)| {
+                let range = format!(
+                    "${:0w$X}-${:0w$X}",
+                    v.virtual_addr_start,
+                    end - 1,
+                    w = width
+                );
+                if (*self.devs[v.mem_id.0]).as_any().is::<Hole>() {
+                    return format!("{}: hole\n", range);
+                }
+                let repeat = v
+                    .period
+                    .map(|period| format!(", repeated every {} bytes", period))
+                    .unwrap_or_default();
+                format!(
+                    "{}: device {} at ${:0w$X}{}\n",
+                    range,
+                    v.mem_id.0,
+                    v.physical_addr_start,
+                    repeat,
+                    w = width
+                )
+            })
+            .collect()
+    }
+
+    /// call _observer_ with the address, data and direction of every read and write through
+    /// the layout, e.g. for watch windows or bus logging. refused reads carry no data and are
+    /// not seen, refused writes are. peeks are not bus accesses and are not seen either.
+    pub fn add_observer(
+        &mut self,
+        observer: impl FnMut(usize, u8, BusAccess) + 'static,
+    ) -> ObserverId {
+        self.observers.push(Some(Box::new(observer)));
+        ObserverId(self.observers.len() - 1)
+    }
+
+    /// stop calling the observer added as _id_, false if it was already removed.
+    pub fn remove_observer(&mut self, id: ObserverId) -> bool {
+        self.observers
+            .get_mut(id.0)
+            .and_then(|v| v.take())
+            .is_some()
+    }
+
+    fn notify(&mut self, addr: usize, data: u8, access: BusAccess) {
+        self.observers
+            .iter_mut()
+            .flatten()
+            .for_each(|v| v(addr, data, access));
+    }
+
+    fn read_bus(&mut self, addr: usize) -> Option<u8> {
+        self.remap_direct_pages();
+        if let Some(page) = self.direct_page(addr) {
+            self.stats[page.dev].reads += 1;
+            // SAFETY: pages are unmapped whenever a device could be replaced, and devices
+            // never borrow their direct memory uniquely, see DirectMemory::new
+            return Some(unsafe { *page.ptr.add(addr % PAGE_SIZE) });
+        }
+        let mapping = self.get_mapping_at_addr(addr)?;
+        let (mem_id, phys_addr) = (mapping.mem_id, mapping.physical_addr(addr));
+
+        let data = self.devs[mem_id.0].read(phys_addr);
+        let stats = &mut self.stats[mem_id.0];
+        stats.reads += 1;
+        stats.refused_reads += data.is_none() as u64;
+        data
+    }
+
+    fn write_bus(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.remap_direct_pages();
+        if let Some(page) = self.direct_page(addr) {
+            let stats = &mut self.stats[page.dev];
+            stats.writes += 1;
+            if !page.writable {
+                stats.refused_writes += 1;
+                return None;
+            }
+            // SAFETY: see read_bus
+            unsafe { *page.ptr.add(addr % PAGE_SIZE) = data };
+            return Some(());
+        }
+        let mapping = self.get_mapping_at_addr(addr)?;
+        let (mem_id, phys_addr) = (mapping.mem_id, mapping.physical_addr(addr));
+
+        let res = self.devs[mem_id.0].write(phys_addr, data);
+        let stats = &mut self.stats[mem_id.0];
+        stats.writes += 1;
+        stats.refused_writes += res.is_none() as u64;
+        res
+    }
+
     fn get_mapping_at_addr(&self, addr: usize) -> Option<&Mapping> {
-        self.mappings.range(..=addr).next_back().map(|v| v.1)
+        if addr >= self.byte_cnt {
+            return None;
+        }
+        let index = self.page_table[addr / PAGE_SIZE];
+        self.mappings[index..]
+            .iter()
+            .take_while(|v| v.virtual_addr_start <= addr)
+            .last()
     }
 }
 impl Device for Layout {
@@ -175,23 +671,101 @@ impl Device for Layout {
         self.devs.iter_mut().for_each(|v| v.reset());
     }
 
+    fn tick(&mut self, cycles: u64) {
+        self.devs.iter_mut().for_each(|v| v.tick(cycles));
+    }
+
     fn read(&mut self, addr: usize) -> Option<u8> {
-        let Mapping {
-            virtual_addr_start,
-            physical_addr_start,
-            mem_id,
-        } = *self.get_mapping_at_addr(addr)?;
+        let data = self.read_bus(addr);
+        if let (Some(data), false) = (data, self.observers.is_empty()) {
+            self.notify(addr, data, BusAccess::Read);
+        }
+        data
+    }
+
+    /// every device is told, the first one supplying a vector wins.
+    fn acknowledge(&mut self, interrupt: Interrupt) -> Option<u16> {
+        self.devs
+            .iter_mut()
+            .fold(None, |vector, v| vector.or(v.acknowledge(interrupt)))
+    }
 
-        self.devs[mem_id.0].read(physical_addr_start + (addr - virtual_addr_start))
+    fn peek(&self, addr: usize) -> Option<u8> {
+        if let Some(page) = self.direct_page(addr) {
+            // SAFETY: see read_bus
+            return Some(unsafe { *page.ptr.add(addr % PAGE_SIZE) });
+        }
+        let mapping = self.get_mapping_at_addr(addr)?;
+        self.devs[mapping.mem_id.0].peek(mapping.physical_addr(addr))
     }
 
     fn write(&mut self, addr: usize, data: u8) -> Option<()> {
-        let Mapping {
-            virtual_addr_start,
-            physical_addr_start,
-            mem_id,
-        } = *self.get_mapping_at_addr(addr)?;
+        if !self.observers.is_empty() {
+            self.notify(addr, data, BusAccess::Write);
+        }
+        self.write_bus(addr, data)
+    }
 
-        self.devs[mem_id.0].write(physical_addr_start + (addr - virtual_addr_start), data)
+    /// not seen by observers nor counted in the stats.
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.remap_direct_pages();
+        if let Some(page) = self.direct_page(addr).filter(|v| v.writable) {
+            // SAFETY: see read_bus
+            unsafe { *page.ptr.add(addr % PAGE_SIZE) = data };
+            return Some(());
+        }
+        let mapping = self.get_mapping_at_addr(addr)?;
+        let (mem_id, phys_addr) = (mapping.mem_id, mapping.physical_addr(addr));
+        self.devs[mem_id.0].poke(phys_addr, data)
     }
 }
+
+/// build a [`Layout`] from an address map, evaluating to `Result<Layout, BuildError>`.
+///
+/// devices are named in `devices { .. }` and mapped by inclusive address ranges,
+/// later ranges taking precedence over earlier ones like with [`LayoutBuilder::assign_range`].
+/// range bounds must be constants. each range is checked at compile time to start no later
+/// than it ends and to fit the address space, whose size defaults to the 6502's 64K.
+/// overlapping ranges are not reported, as the later ones are meant to win.
+///
+/// ```
+/// # use tbo2::{layout, RAM, ROM};
+/// let layout = layout! {
+///     size = 0x10000;
+///     devices {
+///         ram: RAM::<0x8000>::default(),
+///         rom: ROM::<0x8000>::default(),
+///     }
+///     0x0000..=0x7FFF => ram,
+///     0x8000..=0xFFFF => rom,
+/// }
+/// .unwrap();
+/// ```
+#[macro_export]
+macro_rules! layout {
+    (
+        size = $size:expr;
+        devices { $($name:ident: $dev:expr),* $(,)? }
+        $($start:tt ..= $end:tt => $target:ident),* $(,)?
+    ) => {{
+        let mut builder = $crate::LayoutBuilder::new($size);
+        $(let $name = builder.add_device($dev);)*
+        $(
+            const {
+                assert!(
+                    ($start as usize) <= ($end as usize),
+                    "layout range ends before it starts"
+                );
+                assert!(
+                    ($end as usize) < $size,
+                    "layout range exceeds the address space"
+                );
+            }
+            builder.assign_range($start, $end - $start + 1, $target);
+        )*
+        builder.build()
+    }};
+    (devices $($rest:tt)*) => {
+        $crate::layout!(size = 0x10000; devices $($rest)*)
+    };
+}
@@ -1,10 +1,49 @@
+pub mod asm;
+mod clock;
+mod console;
 mod cpu;
+mod dbginfo;
 pub mod devices;
+pub mod disasm;
 mod inst;
 mod layout;
+mod machine;
 mod mem;
+mod model;
+mod port;
+mod profile;
+mod program;
+mod savestate;
+mod search;
+mod symbols;
+pub mod testing;
+mod trace;
+#[cfg(feature = "w65c816")]
+mod w65c816;
 
-pub use cpu::CPU;
+pub use clock::Clock;
+pub use console::Console;
+pub use cpu::{
+    BreakCondition, BrkAction, BrkHandler, Cpu, CpuState, EffectiveOperand, ExecutionError,
+    Registers, RunState, Steps, CPU, PC_HISTORY_LEN,
+};
+pub use dbginfo::{DebugInfo, DebugInfoError, SourceLocation};
 pub use devices::Device;
-pub use layout::{Layout, LayoutBuilder};
-pub use mem::{RAM, ROM};
+pub use inst::AddressingMode;
+pub use layout::{
+    AccessStats, BuildError, BusAccess, DevId, HolePolicy, Layout, LayoutBuilder, ObserverId,
+};
+pub use machine::Machine;
+pub use mem::{BankedROM, DualPortRam, DynRam, DynRom, FillPattern, ImageError, NvRam, RAM, ROM};
+pub use model::CpuModel;
+pub use port::IoPort;
+pub use profile::{PcStats, Profile};
+pub use program::{Program, ProgramError, Segment};
+pub use savestate::{SaveState, SaveStateError};
+pub use search::Charset;
+pub use symbols::SymbolTable;
+pub use trace::{
+    CallFrame, CallKind, JsonTrace, RotatingFile, TextTrace, TraceEvent, TraceRecord, TraceSink,
+};
+#[cfg(feature = "w65c816")]
+pub use w65c816::{Registers816, W65C816};
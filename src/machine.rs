@@ -0,0 +1,213 @@
+use core::fmt;
+use std::{
+    io::{Read, Write},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    devices::{Acia, IrqLine, IrqSource},
+    Clock, Cpu, ExecutionError, FillPattern, LayoutBuilder, CPU, RAM, ROM,
+};
+
+/// how long the host yields while the emulated program sits in an idle loop.
+const IDLE_SLICE: Duration = Duration::from_millis(1);
+/// cycles skipped per idle slice when there is no clock to derive them from.
+const IDLE_SLICE_CYCLES: u64 = 1000;
+
+pub struct Machine<C = CPU> {
+    cpu: C,
+    clock: Option<Clock>,
+    irq_sources: Vec<Box<dyn IrqSource>>,
+    idle_sleep: bool,
+}
+impl<C: fmt::Debug> fmt::Debug for Machine<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Machine")
+            .field("cpu", &self.cpu)
+            .field("clock", &self.clock)
+            .finish()
+    }
+}
+impl<C: Cpu> Machine<C> {
+    pub fn new(cpu: C) -> Self {
+        Self {
+            cpu,
+            clock: None,
+            irq_sources: vec![],
+            idle_sleep: true,
+        }
+    }
+
+    /// wire _source_ to the CPU's IRQ input. the line is sampled before every step
+    /// and an interrupt is requested for as long as any connected source asserts it.
+    pub fn connect_irq(&mut self, source: impl IrqSource + 'static) {
+        self.irq_sources.push(Box::new(source));
+    }
+
+    /// whether any connected source asserts the IRQ line.
+    pub fn is_irq_asserted(&self) -> bool {
+        self.irq_sources.iter().any(|v| v.is_asserted())
+    }
+
+    /// the sources asserting the IRQ line, numbered in the order they were connected.
+    pub fn asserting_irq_sources(&self) -> Vec<usize> {
+        self.irq_sources
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.is_asserted())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// the emulated clock frequency, `None` while the machine runs unthrottled.
+    pub fn get_clock_hz(&self) -> Option<u64> {
+        self.clock.as_ref().map(|v| v.get_hz())
+    }
+
+    /// throttle stepping to _hz_ emulated cycles per second, or run unthrottled with `None`.
+    /// this can be changed at any time while the machine is running.
+    pub fn set_clock_hz(&mut self, hz: Option<u64>) {
+        match (&mut self.clock, hz) {
+            (Some(clock), Some(hz)) => clock.set_hz(hz),
+            (clock, hz) => *clock = hz.map(Clock::new),
+        }
+    }
+
+    pub fn is_turbo(&self) -> bool {
+        self.clock.as_ref().is_some_and(|v| v.is_turbo())
+    }
+
+    /// temporarily lift the throttling without forgetting the configured frequency.
+    pub fn set_turbo(&mut self, turbo: bool) {
+        if let Some(clock) = &mut self.clock {
+            clock.set_turbo(turbo);
+        }
+    }
+
+    pub fn is_idle_sleep(&self) -> bool {
+        self.idle_sleep
+    }
+
+    /// whether an unthrottled machine sleeps for a slice of host time whenever it fast-forwards
+    /// an idle loop, on by default to spare the host. frontends stepping the machine on their
+    /// UI thread turn it off so that a program waiting for input does not block them.
+    pub fn set_idle_sleep(&mut self, sleep: bool) {
+        self.idle_sleep = sleep;
+    }
+
+    /// take an interrupt if requested and execute one instruction, returning the clock cycles
+    /// that passed, including those of an idle loop fast-forwarded afterwards.
+    pub fn step(&mut self) -> Result<u64, ExecutionError> {
+        let start = self.cpu.cycles();
+        self.cpu.set_irq_line(self.is_irq_asserted());
+        let result = self.cpu.step();
+        if result.is_ok() {
+            self.idle();
+        }
+        let cycles = self.cpu.cycles() - start;
+        if let Some(clock) = &mut self.clock {
+            clock.advance(cycles);
+        }
+        result.map(|_| cycles)
+    }
+
+    /// fast-forward an idle loop by one slice of host time instead of spinning through it.
+    fn idle(&mut self) {
+        let throttled_hz = self
+            .clock
+            .as_ref()
+            .filter(|v| !v.is_turbo())
+            .map(|v| v.get_hz());
+        let slice_cycles = match throttled_hz {
+            Some(hz) => hz * IDLE_SLICE.as_micros() as u64 / 1_000_000,
+            None => IDLE_SLICE_CYCLES,
+        };
+        let sources = &self.irq_sources;
+        let skipped = self.cpu.fast_forward_idle(slice_cycles, &mut || {
+            sources.iter().any(|v| v.is_asserted())
+        });
+        // a throttled clock sleeps on its own while catching up with the skipped cycles,
+        // a slice cut short by an interrupt is only slept for in part
+        if skipped > 0 && throttled_hz.is_none() && self.idle_sleep {
+            thread::sleep(IDLE_SLICE.mul_f64(skipped as f64 / slice_cycles as f64));
+        }
+    }
+
+    pub fn cpu(&self) -> &C {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut C {
+        &mut self.cpu
+    }
+}
+impl Machine {
+    /// where [`Machine::ehbasic`] maps the ACIA.
+    pub const EHBASIC_ACIA: u16 = 0x8000;
+
+    /// create a machine with 64K of RAM holding _program_ at _origin_.
+    /// the reset vector is pointed at _origin_ (overwriting whatever _program_ had there)
+    /// and the machine is reset, ready to step.
+    pub fn with_program(origin: u16, program: &[u8]) -> Self {
+        Self::with_program_filled(origin, program, FillPattern::Zero)
+    }
+
+    /// same as [`Machine::with_program`], but the RAM not covered by _program_ follows _pattern_.
+    /// with [`FillPattern::Random`] the registers power on random from the same seed too.
+    pub fn with_program_filled(origin: u16, program: &[u8], pattern: FillPattern) -> Self {
+        let mut ram = RAM::<0x10000>::filled(pattern);
+        ram.load_bytes(origin as usize, program);
+        ram.load_bytes(0xFFFC, &origin.to_le_bytes());
+
+        let mut builder = LayoutBuilder::new(0x10000);
+        let ram_id = builder.add_device(ram);
+        builder.assign_range(0x0000, 0x10000, ram_id);
+
+        let layout = builder.build().expect("64K RAM layout is always valid");
+        let mut cpu = CPU::new(layout).expect("64K RAM layout covers the address space");
+        if let FillPattern::Random(seed) = pattern {
+            cpu.randomize_registers(seed);
+        }
+        cpu.reset();
+
+        Self::new(cpu)
+    }
+
+    /// create a machine for running EhBASIC built for a 6551 ACIA, talking over _serial_,
+    /// e.g. a [`Console`](crate::Console) or [`Pty`](crate::devices::Pty). the memory map is
+    /// - $0000-$7FFF: RAM.
+    /// - $8000-$BFFF: the ACIA, its registers repeated every 4 bytes.
+    /// - $C000-$FFFF: ROM holding _rom_ at its end, so the vectors at the end of the image
+    ///   land at $FFFA-$FFFF.
+    ///
+    /// the ACIA interrupt line is connected to the IRQ input, and the machine is throttled
+    /// to _cpu_hz_, which the baud rates are derived from. the machine is reset, ready to step.
+    pub fn ehbasic<S: Read + Write + 'static>(rom: &[u8], serial: S, cpu_hz: u64) -> Self {
+        assert!(rom.len() <= 0x4000, "EhBASIC ROM must fit in 16K");
+        let mut rom_dev = ROM::<0x4000>::default();
+        rom_dev.load_bytes(0x4000 - rom.len(), rom);
+
+        let irq = IrqLine::new();
+        let mut acia = Acia::new(serial, cpu_hz);
+        acia.set_irq_line(Some(irq.clone()));
+
+        let mut builder = LayoutBuilder::new(0x10000);
+        let ram_id = builder.add_device(RAM::<0x8000>::default());
+        let acia_id = builder.add_device(acia);
+        let rom_id = builder.add_device(rom_dev);
+        builder
+            .assign_range(0x0000, 0x8000, ram_id)
+            .assign_repeated(Self::EHBASIC_ACIA as usize, 0x4000, acia_id, 4)
+            .assign_range(0xC000, 0x4000, rom_id);
+
+        let layout = builder.build().expect("EhBASIC layout is always valid");
+        let mut cpu = CPU::new(layout).expect("EhBASIC layout covers the address space");
+        cpu.reset();
+
+        let mut machine = Self::new(cpu);
+        machine.connect_irq(irq);
+        machine.set_clock_hz(Some(cpu_hz));
+        machine
+    }
+}
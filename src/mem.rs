@@ -1,75 +1,675 @@
-use crate::Device;
+use core::{cell::Cell, fmt};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+use log::warn;
+
+use crate::{devices::DirectMemory, Device};
+
+/// contents of memory right after power-on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FillPattern {
+    #[default]
+    Zero,
+    /// every byte is $FF.
+    Ones,
+    /// blocks of _n_ bytes alternating between $00 and $FF, starting with $00.
+    Alternating(usize),
+    /// pseudo-random bytes, the same _seed_ always gives the same contents.
+    Random(u64),
+}
+impl FillPattern {
+    pub(crate) fn fill(self, data: &mut [u8]) {
+        match self {
+            FillPattern::Zero => data.fill(0x00),
+            FillPattern::Ones => data.fill(0xFF),
+            FillPattern::Alternating(n) => {
+                for (i, chunk) in data.chunks_mut(n.max(1)).enumerate() {
+                    chunk.fill(if i % 2 == 0 { 0x00 } else { 0xFF });
+                }
+            }
+            FillPattern::Random(seed) => {
+                let mut rng = XorShift::new(seed);
+                data.iter_mut().for_each(|v| *v = rng.next_u8());
+            }
+        }
+    }
+}
+
+/// why an image could not be loaded into memory.
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    /// the image of _size_ bytes does not fit the _capacity_ of the memory.
+    TooLarge {
+        size: usize,
+        capacity: usize,
+    },
+}
+impl From<io::Error> for ImageError {
+    fn from(value: io::Error) -> Self {
+        ImageError::Io(value)
+    }
+}
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(e) => write!(f, "{}", e),
+            ImageError::TooLarge { size, capacity } => write!(
+                f,
+                "image of {} bytes exceeds the capacity ({})",
+                size, capacity
+            ),
+        }
+    }
+}
+
+/// the contents of the file at _path_, if they fit in _capacity_ bytes.
+fn read_image(path: &Path, capacity: usize) -> Result<Vec<u8>, ImageError> {
+    let data = fs::read(path)?;
+    if data.len() > capacity {
+        return Err(ImageError::TooLarge {
+            size: data.len(),
+            capacity,
+        });
+    }
+    Ok(data)
+}
+
+/// memory kept in cells on the heap, so that [`DirectMemory`] pointers into it stay valid
+/// across moves and mutable borrows of its device. it must only ever be accessed through
+/// shared references, see [`DirectMemory::from_cells`].
+type Cells = Box<[Cell<u8>]>;
+
+fn to_cells(bytes: &[u8]) -> Cells {
+    bytes.iter().copied().map(Cell::new).collect()
+}
+
+fn to_bytes(cells: &[Cell<u8>]) -> Vec<u8> {
+    cells.iter().map(Cell::get).collect()
+}
+
+/// copy _bytes_ into _cells_ from _start_, in place so direct memory stays valid.
+fn store(cells: &[Cell<u8>], start: usize, bytes: &[u8]) {
+    assert!(
+        start + bytes.len() <= cells.len(),
+        "ending address ({:#0x}) exceeds the capacity ({})",
+        start + bytes.len(),
+        cells.len()
+    );
+    cells[start..]
+        .iter()
+        .zip(bytes)
+        .for_each(|(to, from)| to.set(*from));
+}
+
+/// a tiny xorshift64* generator, good enough for power-on noise.
+pub(crate) struct XorShift(u64);
+impl XorShift {
+    pub(crate) fn new(seed: u64) -> Self {
+        // scramble the seed with a splitmix64 round so nearby seeds diverge,
+        // the state must never be zero
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Self(if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z })
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub(crate) fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
 
 pub struct RAM<const BYTE_CNT: usize> {
-    data: [u8; BYTE_CNT],
+    data: Cells,
 }
 impl<const BYTE_CNT: usize> Default for RAM<BYTE_CNT> {
     fn default() -> Self {
         Self {
-            data: [0; BYTE_CNT],
+            data: vec![Cell::new(0); BYTE_CNT].into_boxed_slice(),
         }
     }
 }
 impl<const BYTE_CNT: usize> RAM<BYTE_CNT> {
+    /// create a RAM whose contents follow _pattern_, like real memory after power-on.
+    pub fn filled(pattern: FillPattern) -> Self {
+        let mut data = [0; BYTE_CNT];
+        pattern.fill(&mut data);
+        Self {
+            data: to_cells(&data),
+        }
+    }
+
+    /// create a RAM holding the image in the file at _path_ from address 0, the rest zeroed.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ImageError> {
+        let mut ram = Self::default();
+        ram.load_bytes(0, &read_image(path.as_ref(), BYTE_CNT)?);
+        Ok(ram)
+    }
+
     pub fn load_bytes(&mut self, addr_start: usize, data: &[u8]) {
-        assert!(
-            addr_start + data.len() <= BYTE_CNT,
-            "ending address ({:#0x}) exceeds the capacity ({})",
-            addr_start + data.len(),
-            BYTE_CNT
-        );
-        self.data
-            .iter_mut()
-            .skip(addr_start)
-            .zip(data)
-            .for_each(|(to, from)| *to = *from);
+        store(&self.data, addr_start, data);
     }
 }
 impl<const BYTE_CNT: usize> Device for RAM<BYTE_CNT> {
     fn read(&mut self, addr: usize) -> Option<u8> {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
         let wrapped_addr = addr % BYTE_CNT;
-        Some(self.data[wrapped_addr])
+        Some(self.data[wrapped_addr].get())
     }
 
     fn write(&mut self, addr: usize, data: u8) -> Option<()> {
         let wrapped_addr = addr % BYTE_CNT;
-        self.data[wrapped_addr] = data;
+        self.data[wrapped_addr].set(data);
+        Some(())
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.write(addr, data)
+    }
+
+    fn direct_memory(&mut self) -> Option<DirectMemory> {
+        // SAFETY: the cells are only accessed through shared references, and are only freed
+        // or reallocated when the device is dropped or replaced
+        Some(unsafe { DirectMemory::from_cells(&self.data, true) })
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(to_bytes(&self.data))
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        if state.len() != BYTE_CNT {
+            return None;
+        }
+        store(&self.data, 0, state);
+        Some(())
+    }
+}
+
+/// RAM whose contents are kept in a host file, like battery-backed NVRAM holding settings
+/// or high scores across runs. the file is loaded when the device is attached, and written
+/// back by [`NvRam::flush`], when the device is detached and when it is dropped.
+/// writes are skipped while the contents match the file.
+pub struct NvRam<const BYTE_CNT: usize> {
+    data: Cells,
+    /// the contents as last loaded or flushed.
+    flushed: [u8; BYTE_CNT],
+    path: PathBuf,
+}
+impl<const BYTE_CNT: usize> NvRam<BYTE_CNT> {
+    /// create a RAM backed by the file at _path_, loading its contents if it exists.
+    /// a missing file starts out zeroed and is created by the first flush, a file
+    /// that is not exactly `BYTE_CNT` bytes long is rejected.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut ram = Self {
+            data: vec![Cell::new(0); BYTE_CNT].into_boxed_slice(),
+            flushed: [0; BYTE_CNT],
+            path: path.as_ref().to_owned(),
+        };
+        ram.reload()?;
+        Ok(ram)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// whether the contents changed since they were last loaded or flushed.
+    pub fn is_dirty(&self) -> bool {
+        self.data.iter().map(Cell::get).ne(self.flushed)
+    }
+
+    /// replace the contents with the backing file, if it exists.
+    pub fn reload(&mut self) -> io::Result<()> {
+        match fs::read(&self.path) {
+            Ok(contents) => {
+                self.flushed = contents.try_into().map_err(|v: Vec<u8>| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("expected {} bytes, found {}", BYTE_CNT, v.len()),
+                    )
+                })?;
+                store(&self.data, 0, &self.flushed);
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// write the contents to the backing file, if they changed.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.is_dirty() {
+            let data = to_bytes(&self.data);
+            fs::write(&self.path, &data)?;
+            self.flushed.copy_from_slice(&data);
+        }
+        Ok(())
+    }
+
+    fn flush_or_warn(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("nvram: cannot write {}: {}", self.path.display(), e);
+        }
+    }
+}
+impl<const BYTE_CNT: usize> Drop for NvRam<BYTE_CNT> {
+    fn drop(&mut self) {
+        self.flush_or_warn();
+    }
+}
+impl<const BYTE_CNT: usize> Device for NvRam<BYTE_CNT> {
+    fn attach(&mut self) {
+        if let Err(e) = self.reload() {
+            warn!("nvram: cannot load {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn detach(&mut self) {
+        self.flush_or_warn();
+    }
+
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        Some(self.data[addr % BYTE_CNT].get())
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.data[addr % BYTE_CNT].set(data);
+        Some(())
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.write(addr, data)
+    }
+
+    fn direct_memory(&mut self) -> Option<DirectMemory> {
+        // SAFETY: the cells are only accessed through shared references, and are only freed
+        // or reallocated when the device is dropped or replaced
+        Some(unsafe { DirectMemory::from_cells(&self.data, true) })
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(to_bytes(&self.data))
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        if state.len() != BYTE_CNT {
+            return None;
+        }
+        store(&self.data, 0, state);
         Some(())
     }
 }
 
 pub struct ROM<const BYTE_CNT: usize> {
-    data: [u8; BYTE_CNT],
+    data: Cells,
 }
 impl<const BYTE_CNT: usize> Default for ROM<BYTE_CNT> {
     fn default() -> Self {
         Self {
-            data: [0; BYTE_CNT],
+            data: vec![Cell::new(0); BYTE_CNT].into_boxed_slice(),
         }
     }
 }
 impl<const BYTE_CNT: usize> ROM<BYTE_CNT> {
+    /// create a ROM holding the image in the file at _path_ from address 0, the rest zeroed.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ImageError> {
+        let mut rom = Self::default();
+        rom.load_bytes(0, &read_image(path.as_ref(), BYTE_CNT)?);
+        Ok(rom)
+    }
+
+    pub fn load_bytes(&mut self, addr_start: usize, data: &[u8]) {
+        store(&self.data, addr_start, data);
+    }
+}
+impl<const BYTE_CNT: usize> Device for ROM<BYTE_CNT> {
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        let wrapped_addr = addr % BYTE_CNT;
+        Some(self.data[wrapped_addr].get())
+    }
+
+    fn write(&mut self, _addr: usize, _data: u8) -> Option<()> {
+        None
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        let wrapped_addr = addr % BYTE_CNT;
+        self.data[wrapped_addr].set(data);
+        Some(())
+    }
+
+    fn direct_memory(&mut self) -> Option<DirectMemory> {
+        // SAFETY: see RAM
+        Some(unsafe { DirectMemory::from_cells(&self.data, false) })
+    }
+}
+
+/// RAM of a size chosen at runtime, e.g. from a config file, kept on the heap.
+/// unlike [`RAM`] it has no direct memory, as its bytes are borrowed mutably by every write.
+pub struct DynRam {
+    data: Box<[u8]>,
+}
+impl DynRam {
+    /// create a zeroed RAM of _byte_cnt_ bytes.
+    pub fn new(byte_cnt: usize) -> Self {
+        Self::filled(byte_cnt, FillPattern::Zero)
+    }
+
+    /// create a RAM of _byte_cnt_ bytes whose contents follow _pattern_, see [`RAM::filled`].
+    pub fn filled(byte_cnt: usize, pattern: FillPattern) -> Self {
+        assert!(byte_cnt > 0, "RAM size must be non-zero");
+        let mut data = vec![0; byte_cnt].into_boxed_slice();
+        pattern.fill(&mut data);
+        Self { data }
+    }
+
+    pub fn get_byte_count(&self) -> usize {
+        self.data.len()
+    }
+
     pub fn load_bytes(&mut self, addr_start: usize, data: &[u8]) {
         assert!(
-            addr_start + data.len() <= BYTE_CNT,
+            addr_start + data.len() <= self.data.len(),
             "ending address ({:#0x}) exceeds the capacity ({})",
             addr_start + data.len(),
-            BYTE_CNT
+            self.data.len()
         );
-        self.data
-            .iter_mut()
-            .skip(addr_start)
-            .zip(data)
-            .for_each(|(to, from)| *to = *from);
+        self.data[addr_start..addr_start + data.len()].copy_from_slice(data);
     }
 }
-impl<const BYTE_CNT: usize> Device for ROM<BYTE_CNT> {
+impl Device for DynRam {
     fn read(&mut self, addr: usize) -> Option<u8> {
-        let wrapped_addr = addr % BYTE_CNT;
-        Some(self.data[wrapped_addr])
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        Some(self.data[addr % self.data.len()])
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        let len = self.data.len();
+        self.data[addr % len] = data;
+        Some(())
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.write(addr, data)
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(self.data.to_vec())
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        if state.len() != self.data.len() {
+            return None;
+        }
+        self.data.copy_from_slice(state);
+        Some(())
+    }
+}
+
+/// ROM of a size chosen at runtime, holding the image it was created from on the heap.
+/// it has no direct memory, see [`DynRam`].
+pub struct DynRom {
+    data: Box<[u8]>,
+}
+impl From<Vec<u8>> for DynRom {
+    fn from(image: Vec<u8>) -> Self {
+        assert!(!image.is_empty(), "ROM size must be non-zero");
+        Self {
+            data: image.into_boxed_slice(),
+        }
+    }
+}
+impl DynRom {
+    pub fn get_byte_count(&self) -> usize {
+        self.data.len()
+    }
+}
+impl Device for DynRom {
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        Some(self.data[addr % self.data.len()])
     }
 
     fn write(&mut self, _addr: usize, _data: u8) -> Option<()> {
         None
     }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        let len = self.data.len();
+        self.data[addr % len] = data;
+        Some(())
+    }
+}
+
+/// RAM with two ports, each a device of its own, so two CPUs can share it, e.g. as a mailbox
+/// to an I/O co-processor. the CPUs may run on different threads. clones are further ports
+/// to the same memory. resets and ticks of either port leave the contents alone.
+#[derive(Clone)]
+pub struct DualPortRam {
+    data: Arc<[AtomicU8]>,
+}
+impl DualPortRam {
+    /// create _byte_cnt_ bytes of zeroed RAM, returning both of its ports.
+    pub fn new(byte_cnt: usize) -> (Self, Self) {
+        assert!(byte_cnt > 0, "RAM size must be non-zero");
+        let ram = Self {
+            data: (0..byte_cnt).map(|_| AtomicU8::new(0)).collect(),
+        };
+        (ram.clone(), ram)
+    }
+
+    pub fn get_byte_count(&self) -> usize {
+        self.data.len()
+    }
+}
+impl Device for DualPortRam {
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        Some(self.data[addr % self.data.len()].load(Ordering::Acquire))
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.data[addr % self.data.len()].store(data, Ordering::Release);
+        Some(())
+    }
+
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        self.write(addr, data)
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(
+            self.data
+                .iter()
+                .map(|v| v.load(Ordering::Acquire))
+                .collect(),
+        )
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        if state.len() != self.data.len() {
+            return None;
+        }
+        for (to, from) in self.data.iter().zip(state) {
+            to.store(*from, Ordering::Release);
+        }
+        Some(())
+    }
+}
+
+/// a ROM image larger than the window it is seen through, one bank at a time, like
+/// cartridges and boards with more ROM than address space. device addresses below the window
+/// size read the selected bank, writing a bank number to the select register switches banks.
+/// the select register may be inside the window, as with latches decoding writes to ROM,
+/// or past it, mapped on its own.
+pub struct BankedROM {
+    data: Vec<u8>,
+    window: usize,
+    select_addr: usize,
+    bank: usize,
+}
+impl BankedROM {
+    /// create a ROM holding _image_, seen _window_ bytes at a time, switched by writes to the
+    /// device address _select_addr_. an image not filling its last bank is padded with $FF.
+    pub fn new(mut image: Vec<u8>, window: usize, select_addr: usize) -> Self {
+        assert!(window > 0, "bank window must be non-zero");
+        let len = image.len().div_ceil(window).max(1) * window;
+        image.resize(len, 0xFF);
+        Self {
+            data: image,
+            window,
+            select_addr,
+            bank: 0,
+        }
+    }
+
+    pub fn get_bank(&self) -> usize {
+        self.bank
+    }
+
+    /// select _bank_, wrapping around the bank count like unconnected select bits.
+    pub fn set_bank(&mut self, bank: usize) {
+        self.bank = bank % self.bank_count();
+    }
+
+    pub fn bank_count(&self) -> usize {
+        self.data.len() / self.window
+    }
+
+    fn offset(&self, addr: usize) -> Option<usize> {
+        (addr < self.window).then_some(self.bank * self.window + addr)
+    }
+}
+impl Device for BankedROM {
+    /// the first bank is selected again.
+    fn reset(&mut self) {
+        self.bank = 0;
+    }
+
+    fn read(&mut self, addr: usize) -> Option<u8> {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        Some(self.data[self.offset(addr)?])
+    }
+
+    fn write(&mut self, addr: usize, data: u8) -> Option<()> {
+        if addr != self.select_addr {
+            return None;
+        }
+        self.set_bank(data as usize);
+        Some(())
+    }
+
+    /// patches the selected bank.
+    fn poke(&mut self, addr: usize, data: u8) -> Option<()> {
+        let offset = self.offset(addr)?;
+        self.data[offset] = data;
+        Some(())
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some((self.bank as u32).to_le_bytes().to_vec())
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Option<()> {
+        let bank = u32::from_le_bytes(state.try_into().ok()?) as usize;
+        (bank < self.bank_count()).then(|| self.bank = bank)
+    }
+}
+
+/// RAM and ROM serialize as their contents, as bytes in formats that support them.
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use core::fmt;
+
+    use serde::{
+        de::{self, SeqAccess, Visitor},
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    use super::{RAM, ROM};
+
+    struct Contents<const BYTE_CNT: usize>;
+    impl<'de, const BYTE_CNT: usize> Visitor<'de> for Contents<BYTE_CNT> {
+        type Value = [u8; BYTE_CNT];
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} bytes", BYTE_CNT)
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            v.try_into().map_err(|_| E::invalid_length(v.len(), &self))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut data = [0; BYTE_CNT];
+            for (i, byte) in data.iter_mut().enumerate() {
+                *byte = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+            }
+            if seq.next_element::<u8>()?.is_some() {
+                return Err(de::Error::invalid_length(BYTE_CNT + 1, &self));
+            }
+            Ok(data)
+        }
+    }
+
+    macro_rules! impl_serde {
+        ($mem:ident) => {
+            impl<const BYTE_CNT: usize> Serialize for $mem<BYTE_CNT> {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_bytes(&super::to_bytes(&self.data))
+                }
+            }
+            impl<'de, const BYTE_CNT: usize> Deserialize<'de> for $mem<BYTE_CNT> {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let data = deserializer.deserialize_bytes(Contents::<BYTE_CNT>)?;
+                    Ok(Self {
+                        data: super::to_cells(&data),
+                    })
+                }
+            }
+        };
+    }
+    impl_serde!(RAM);
+    impl_serde!(ROM);
 }
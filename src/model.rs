@@ -0,0 +1,29 @@
+use core::fmt;
+
+/// the flavour of 6502 being emulated.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CpuModel {
+    /// the original MOS 6502 and its NMOS second sources.
+    NMOS6502,
+    /// the WDC W65C02S.
+    #[default]
+    WDC65C02,
+    /// the Rockwell R65C02.
+    Rockwell65C02,
+}
+impl CpuModel {
+    pub fn is_cmos(self) -> bool {
+        !matches!(self, CpuModel::NMOS6502)
+    }
+}
+impl fmt::Display for CpuModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CpuModel::NMOS6502 => "NMOS 6502",
+            CpuModel::WDC65C02 => "WDC 65C02",
+            CpuModel::Rockwell65C02 => "Rockwell 65C02",
+        })
+    }
+}
@@ -0,0 +1,92 @@
+use core::fmt;
+
+/// the on-chip I/O port of the 6510, living at $0000 (data direction) and $0001 (data).
+/// a set direction bit makes the pin an output driven by the data latch,
+/// a cleared one leaves the pin as an input that is pulled up unless driven externally.
+pub struct IoPort {
+    direction: u8,
+    data: u8,
+    input_pins: u8,
+    on_change: Option<Box<dyn FnMut(u8)>>,
+}
+impl Default for IoPort {
+    fn default() -> Self {
+        Self {
+            direction: 0,
+            data: 0,
+            input_pins: 0xFF,
+            on_change: None,
+        }
+    }
+}
+impl fmt::Debug for IoPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IoPort")
+            .field("direction", &self.direction)
+            .field("data", &self.data)
+            .field("input_pins", &self.input_pins)
+            .finish()
+    }
+}
+impl IoPort {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// call _hook_ with the pin levels every time they may have changed,
+    /// e.g. to drive external banking logic.
+    pub fn set_on_change(&mut self, hook: impl FnMut(u8) + 'static) {
+        self.on_change = Some(Box::new(hook));
+    }
+
+    /// set the levels external hardware drives onto the pins configured as inputs.
+    pub fn set_input_pins(&mut self, pins: u8) {
+        self.input_pins = pins;
+        self.notify();
+    }
+
+    pub fn get_input_pins(&self) -> u8 {
+        self.input_pins
+    }
+
+    pub fn get_direction(&self) -> u8 {
+        self.direction
+    }
+
+    pub fn get_data(&self) -> u8 {
+        self.data
+    }
+
+    /// levels currently present on the port pins.
+    pub fn pins(&self) -> u8 {
+        (self.data & self.direction) | (self.input_pins & !self.direction)
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.direction = 0;
+        self.data = 0;
+        self.notify();
+    }
+
+    pub(crate) fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000 => self.direction,
+            _ => self.pins(),
+        }
+    }
+
+    pub(crate) fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x0000 => self.direction = data,
+            _ => self.data = data,
+        }
+        self.notify();
+    }
+
+    fn notify(&mut self) {
+        let pins = self.pins();
+        if let Some(hook) = &mut self.on_change {
+            hook(pins);
+        }
+    }
+}
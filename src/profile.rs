@@ -0,0 +1,80 @@
+use core::fmt;
+
+/// how often an instruction address was executed, see [`Profile`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PcStats {
+    pub executions: u64,
+    /// clock cycles spent in the instruction at the address, without interrupts taken before it.
+    pub cycles: u64,
+}
+impl fmt::Display for PcStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} executions, {} cycles", self.executions, self.cycles)
+    }
+}
+
+/// executions and cycles per instruction address, see [`CPU::set_profiling`](crate::CPU::set_profiling).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    stats: Vec<PcStats>,
+}
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            stats: vec![PcStats::default(); 0x10000],
+        }
+    }
+}
+impl Profile {
+    pub fn get(&self, pc: u16) -> PcStats {
+        self.stats[pc as usize]
+    }
+
+    /// the addresses that were executed, lowest first.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, PcStats)> + '_ {
+        self.stats
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.executions > 0)
+            .map(|(pc, v)| (pc as u16, *v))
+    }
+
+    /// the _count_ addresses most cycles were spent at, most first.
+    pub fn hot_spots(&self, count: usize) -> Vec<(u16, PcStats)> {
+        let mut spots: Vec<_> = self.iter().collect();
+        spots.sort_by_key(|(pc, v)| (std::cmp::Reverse(v.cycles), *pc));
+        spots.truncate(count);
+        spots
+    }
+
+    /// cycles spent at all addresses.
+    pub fn total_cycles(&self) -> u64 {
+        self.stats.iter().map(|v| v.cycles).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.stats.fill(PcStats::default());
+    }
+
+    /// one line per address of [`Profile::hot_spots`], with its share of the total cycles.
+    pub fn report(&self, count: usize) -> String {
+        let total = self.total_cycles().max(1) as f64;
+        self.hot_spots(count)
+            .into_iter()
+            .map(|(pc, v)| {
+                format!(
+                    "${:04X}: {} ({:.1}%)\n",
+                    pc,
+                    v,
+                    v.cycles as f64 * 100.0 / total
+                )
+            })
+            .collect()
+    }
+
+    pub(crate) fn record(&mut self, pc: u16, cycles: u64) {
+        let stats = &mut self.stats[pc as usize];
+        stats.executions += 1;
+        stats.cycles += cycles;
+    }
+}
@@ -0,0 +1,118 @@
+use core::fmt;
+
+use crate::{Device, Layout};
+
+/// the Atari DOS address of the run vector, jumped to once a .xex is loaded.
+const XEX_RUNAD: u16 = 0x02E0;
+
+/// a run of bytes to be loaded at _addr_.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub addr: u16,
+    pub data: Vec<u8>,
+}
+
+/// a program in a binary format that carries its own load addresses.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    pub segments: Vec<Segment>,
+    /// where the program wants to be started, if the format says.
+    pub entry: Option<u16>,
+}
+impl Program {
+    /// parse a Commodore .prg, the load address followed by the bytes to load there.
+    pub fn prg(bytes: &[u8]) -> Result<Self, ProgramError> {
+        let [lo, hi, data @ ..] = bytes else {
+            return Err(ProgramError::Truncated(bytes.len()));
+        };
+        let segment = Segment {
+            addr: u16::from_le_bytes([*lo, *hi]),
+            data: data.to_vec(),
+        };
+        if segment.addr as usize + segment.data.len() > 0x10000 {
+            return Err(ProgramError::OutOfRange(segment.addr));
+        }
+        Ok(Self {
+            segments: vec![segment],
+            entry: None,
+        })
+    }
+
+    /// parse an Atari .xex, segments of an inclusive start and end address followed by the
+    /// bytes between them, the first one preceded by $FFFF. the entry is the run address a
+    /// segment stores at $02E0, which is loaded like any other segment.
+    pub fn xex(bytes: &[u8]) -> Result<Self, ProgramError> {
+        let word = |at: usize| {
+            let pair = bytes.get(at..at + 2).ok_or(ProgramError::Truncated(at))?;
+            Ok(u16::from_le_bytes([pair[0], pair[1]]))
+        };
+        if word(0)? != 0xFFFF {
+            return Err(ProgramError::MissingHeader);
+        }
+
+        let mut program = Self::default();
+        let mut at = 2;
+        while at < bytes.len() {
+            // later segments may repeat the header
+            if word(at)? == 0xFFFF {
+                at += 2;
+            }
+            let (start, end) = (word(at)?, word(at + 2)?);
+            if end < start {
+                return Err(ProgramError::OutOfRange(start));
+            }
+            at += 4;
+            let len = (end - start) as usize + 1;
+            let data = bytes.get(at..at + len).ok_or(ProgramError::Truncated(at))?;
+            at += len;
+
+            if start <= XEX_RUNAD && XEX_RUNAD < end {
+                let offset = (XEX_RUNAD - start) as usize;
+                program.entry = Some(u16::from_le_bytes([data[offset], data[offset + 1]]));
+            }
+            program.segments.push(Segment {
+                addr: start,
+                data: data.to_vec(),
+            });
+        }
+        Ok(program)
+    }
+
+    /// write the segments into _layout_ through [`Device::poke`], so ROM can be loaded too.
+    /// stops at the first address no device lets be poked.
+    pub fn load_into(&self, layout: &mut Layout) -> Result<(), ProgramError> {
+        for segment in &self.segments {
+            for (i, byte) in segment.data.iter().enumerate() {
+                let addr = segment.addr as usize + i;
+                layout
+                    .poke(addr, *byte)
+                    .ok_or(ProgramError::Unwritable(addr as u16))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgramError {
+    /// the file ends at the offset in the middle of a header or segment.
+    Truncated(usize),
+    /// a .xex without the $FFFF header.
+    MissingHeader,
+    /// the segment starting at the address runs past $FFFF or ends before it starts.
+    OutOfRange(u16),
+    /// no device lets the address be written while loading.
+    Unwritable(u16),
+}
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramError::Truncated(at) => write!(f, "file ends early at offset {}", at),
+            ProgramError::MissingHeader => write!(f, "missing $FFFF header"),
+            ProgramError::OutOfRange(addr) => {
+                write!(f, "segment at ${:04X} is out of range", addr)
+            }
+            ProgramError::Unwritable(addr) => write!(f, "cannot load into ${:04X}", addr),
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use core::fmt;
+use std::io::{self, Read, Write};
+
+use crate::{Cpu, CpuModel, Registers, CPU};
+
+const MAGIC: &[u8; 8] = b"TbO2SAVE";
+const CHUNK_RLE: u8 = 0x01;
+
+/// the state of a whole machine, the CPU and every device that supports saving,
+/// see [`Device::save_state`](crate::Device::save_state), and its on-disk format.
+///
+/// a file starts with a header:
+/// - 8 bytes magic `TbO2SAVE`.
+/// - 1 byte major and 1 byte minor format version, see [`SaveState::VERSION`].
+/// - 2 bytes flags, reserved and written as zero.
+///
+/// followed by chunks up to the end of the file, each being:
+/// - 4 bytes kind, e.g. `CPU `.
+/// - 1 byte flags, bit 0 set when the data is run-length encoded.
+/// - 1 byte name length and the name in UTF-8, empty for chunks that need no name.
+/// - 4 bytes data length (little endian) and the data.
+///
+/// chunk kinds:
+/// - `CPU `: PC (2 bytes), SP, A, X, Y, P, the model (0 NMOS 6502, 1 WDC 65C02,
+///   2 Rockwell 65C02) and the cycle count (8 bytes). multi-byte values are little endian.
+/// - `PORT`: the 6510 I/O port, direction, data and input pins.
+/// - `DEV `: the state of a device named after its index in the layout, e.g. `dev0`.
+///   the contents are up to the device, RAM stores its memory image.
+///
+/// compatibility rules: a new major version may change anything and older readers reject it.
+/// a new minor version only adds chunk kinds or appends fields to existing chunks,
+/// so readers skip chunk kinds they do not know and ignore trailing bytes they do not expect.
+///
+/// with the `serde` feature a save state can also be stored in any serde format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SaveState {
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Chunk {
+    kind: [u8; 4],
+    name: String,
+    data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(io::Error),
+    /// the file does not start with the save-state magic.
+    NotASaveState,
+    /// the file was written in an incompatible format version.
+    UnsupportedVersion {
+        major: u8,
+        minor: u8,
+    },
+    /// a chunk of _kind_ is cut short or holds invalid data.
+    Malformed {
+        kind: [u8; 4],
+    },
+    /// the state was saved from a CPU of another model.
+    ModelMismatch {
+        saved: CpuModel,
+        current: CpuModel,
+    },
+    /// the state has a block for a device the layout does not have.
+    UnknownDevice(String),
+    /// the device refused its saved state.
+    DeviceRejected(String),
+}
+impl From<io::Error> for SaveStateError {
+    fn from(value: io::Error) -> Self {
+        SaveStateError::Io(value)
+    }
+}
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Io(e) => write!(f, "{}", e),
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion { major, minor } => {
+                write!(f, "unsupported save state version {}.{}", major, minor)
+            }
+            SaveStateError::Malformed { kind } => {
+                write!(f, "malformed {} chunk", String::from_utf8_lossy(kind))
+            }
+            SaveStateError::ModelMismatch { saved, current } => {
+                write!(
+                    f,
+                    "state of a {} cannot be loaded into a {}",
+                    saved, current
+                )
+            }
+            SaveStateError::UnknownDevice(name) => write!(f, "no device for {}", name),
+            SaveStateError::DeviceRejected(name) => write!(f, "{} rejected its state", name),
+        }
+    }
+}
+
+impl SaveState {
+    /// major and minor version of the format written.
+    pub const VERSION: (u8, u8) = (1, 0);
+
+    /// capture the state of _cpu_ and the devices of its layout.
+    pub fn capture(cpu: &CPU) -> Self {
+        let regs = cpu.registers();
+        let mut data = regs.pc.to_le_bytes().to_vec();
+        data.extend([regs.sp, regs.a, regs.x, regs.y, regs.status]);
+        data.push(model_id(cpu.get_model()));
+        data.extend(cpu.cycles().to_le_bytes());
+        let mut chunks = vec![Chunk::new(*b"CPU ", "", data)];
+
+        if let Some(port) = cpu.io_port() {
+            let data = vec![port.get_direction(), port.get_data(), port.get_input_pins()];
+            chunks.push(Chunk::new(*b"PORT", "", data));
+        }
+
+        for (i, dev) in cpu.layout().devices().iter().enumerate() {
+            if let Some(data) = dev.save_state() {
+                chunks.push(Chunk::new(*b"DEV ", &device_name(i), data));
+            }
+        }
+        Self { chunks }
+    }
+
+    /// load the state into _cpu_, which must be of the same model with devices laid out
+    /// like the one it was captured from. devices the state has no block for are left as they are.
+    pub fn restore(&self, cpu: &mut CPU) -> Result<(), SaveStateError> {
+        for chunk in &self.chunks {
+            let malformed = SaveStateError::Malformed { kind: chunk.kind };
+            match &chunk.kind {
+                b"CPU " => {
+                    let data = chunk.data.get(..16).ok_or(malformed)?;
+                    let saved = model_from_id(data[7])
+                        .ok_or(SaveStateError::Malformed { kind: chunk.kind })?;
+                    if saved != cpu.get_model() {
+                        return Err(SaveStateError::ModelMismatch {
+                            saved,
+                            current: cpu.get_model(),
+                        });
+                    }
+                    cpu.set_registers(Registers {
+                        pc: u16::from_le_bytes([data[0], data[1]]),
+                        sp: data[2],
+                        a: data[3],
+                        x: data[4],
+                        y: data[5],
+                        status: data[6],
+                    });
+                    cpu.set_cycles(u64::from_le_bytes(data[8..16].try_into().unwrap()));
+                }
+                b"PORT" => {
+                    let data = chunk.data.get(..3).ok_or(malformed)?;
+                    if let Some(port) = cpu.io_port_mut() {
+                        port.write(0x0000, data[0]);
+                        port.write(0x0001, data[1]);
+                        port.set_input_pins(data[2]);
+                    }
+                }
+                b"DEV " => {
+                    let dev = chunk
+                        .name
+                        .strip_prefix("dev")
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .and_then(|i| cpu.layout_mut().devices_mut().get_mut(i))
+                        .ok_or_else(|| SaveStateError::UnknownDevice(chunk.name.clone()))?;
+                    dev.restore_state(&chunk.data)
+                        .ok_or_else(|| SaveStateError::DeviceRejected(chunk.name.clone()))?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// write the state to _w_, run-length encoding chunks where it saves space if _compress_ is set.
+    pub fn write_to(&self, mut w: impl Write, compress: bool) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[Self::VERSION.0, Self::VERSION.1, 0, 0])?;
+        for chunk in &self.chunks {
+            let packed = compress.then(|| rle_encode(&chunk.data));
+            let (flags, data) = match &packed {
+                Some(v) if v.len() < chunk.data.len() => (CHUNK_RLE, v),
+                _ => (0, &chunk.data),
+            };
+            w.write_all(&chunk.kind)?;
+            w.write_all(&[flags, chunk.name.len() as u8])?;
+            w.write_all(chunk.name.as_bytes())?;
+            w.write_all(&(data.len() as u32).to_le_bytes())?;
+            w.write_all(data)?;
+        }
+        w.flush()
+    }
+
+    pub fn read_from(mut r: impl Read) -> Result<Self, SaveStateError> {
+        let mut header = [0; 12];
+        r.read_exact(&mut header)
+            .map_err(|_| SaveStateError::NotASaveState)?;
+        if &header[..8] != MAGIC {
+            return Err(SaveStateError::NotASaveState);
+        }
+        let (major, minor) = (header[8], header[9]);
+        if major != Self::VERSION.0 {
+            return Err(SaveStateError::UnsupportedVersion { major, minor });
+        }
+
+        let mut rest = vec![];
+        r.read_to_end(&mut rest)?;
+        let mut chunks = vec![];
+        let mut rest = rest.as_slice();
+        while !rest.is_empty() {
+            let kind: [u8; 4] = rest.get(..4).map_or([0; 4], |v| v.try_into().unwrap());
+            let malformed = || SaveStateError::Malformed { kind };
+            let (&[flags, name_len], tail) = rest
+                .get(4..)
+                .and_then(|v| v.split_first_chunk())
+                .ok_or_else(malformed)?;
+            let name = tail.get(..name_len as usize).ok_or_else(malformed)?;
+            let name = String::from_utf8(name.to_vec()).map_err(|_| malformed())?;
+            let tail = &tail[name_len as usize..];
+            let (len, tail) = tail.split_first_chunk::<4>().ok_or_else(malformed)?;
+            let len = u32::from_le_bytes(*len) as usize;
+            let data = tail.get(..len).ok_or_else(malformed)?;
+            let data = match flags & CHUNK_RLE {
+                0 => data.to_vec(),
+                _ => rle_decode(data).ok_or_else(malformed)?,
+            };
+            chunks.push(Chunk { kind, name, data });
+            rest = &tail[len..];
+        }
+        Ok(Self { chunks })
+    }
+}
+
+impl Chunk {
+    fn new(kind: [u8; 4], name: &str, data: Vec<u8>) -> Self {
+        assert!(name.len() <= u8::MAX as usize, "chunk name is too long");
+        Self {
+            kind,
+            name: name.to_owned(),
+            data,
+        }
+    }
+}
+
+fn device_name(index: usize) -> String {
+    format!("dev{}", index)
+}
+
+fn model_id(model: CpuModel) -> u8 {
+    match model {
+        CpuModel::NMOS6502 => 0,
+        CpuModel::WDC65C02 => 1,
+        CpuModel::Rockwell65C02 => 2,
+    }
+}
+
+fn model_from_id(id: u8) -> Option<CpuModel> {
+    match id {
+        0 => Some(CpuModel::NMOS6502),
+        1 => Some(CpuModel::WDC65C02),
+        2 => Some(CpuModel::Rockwell65C02),
+        _ => None,
+    }
+}
+
+/// PackBits style: a control byte _n_ below $80 is followed by _n_ + 1 literal bytes,
+/// otherwise by one byte repeated 257 - _n_ times.
+fn rle_encode(data: &[u8]) -> Vec<u8> {
+    fn flush(out: &mut Vec<u8>, literals: &mut Vec<u8>) {
+        if !literals.is_empty() {
+            out.push(literals.len() as u8 - 1);
+            out.append(literals);
+        }
+    }
+
+    let mut out = vec![];
+    let mut literals = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..]
+            .iter()
+            .take(129)
+            .take_while(|v| **v == data[i])
+            .count();
+        if run >= 3 {
+            flush(&mut out, &mut literals);
+            out.extend([(257 - run) as u8, data[i]]);
+            i += run;
+        } else {
+            literals.push(data[i]);
+            if literals.len() == 128 {
+                flush(&mut out, &mut literals);
+            }
+            i += 1;
+        }
+    }
+    flush(&mut out, &mut literals);
+    out
+}
+
+fn rle_decode(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    while let Some((&n, rest)) = data.split_first() {
+        if n < 0x80 {
+            let literal = rest.get(..n as usize + 1)?;
+            out.extend(literal);
+            data = &rest[literal.len()..];
+        } else {
+            let (&v, rest) = rest.split_first()?;
+            out.extend(std::iter::repeat_n(v, 257 - n as usize));
+            data = rest;
+        }
+    }
+    Some(out)
+}
@@ -0,0 +1,118 @@
+use std::ops::Range;
+
+use crate::{Device, Layout};
+
+/// how text is encoded in emulated memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    #[default]
+    Ascii,
+    /// ASCII with bit 7 set, as on the Apple II.
+    HighAscii,
+    /// PETSCII in its default uppercase mode, letters match regardless of case.
+    Petscii,
+}
+impl Charset {
+    /// encode _text_, None if it has characters the charset lacks.
+    pub fn encode(self, text: &str) -> Option<Vec<u8>> {
+        text.chars()
+            .map(|c| {
+                if !c.is_ascii() || c.is_ascii_control() {
+                    return None;
+                }
+                let c = c as u8;
+                match self {
+                    Charset::Ascii => Some(c),
+                    Charset::HighAscii => Some(c | 0x80),
+                    Charset::Petscii => match c.to_ascii_uppercase() {
+                        v @ 0x20..=0x5D => Some(v),
+                        _ => None,
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+/// searching, hashing and dumping memory through [`Device::peek`], so no device sees an access.
+/// bytes that cannot be peeked never match.
+impl Layout {
+    /// start addresses within _range_ where _bytes_ are found.
+    pub fn search_bytes(&self, range: Range<usize>, bytes: &[u8]) -> Vec<usize> {
+        self.search(range, bytes)
+    }
+
+    /// start addresses within _range_ where _text_ is found, encoded in _charset_.
+    pub fn search_text(&self, range: Range<usize>, text: &str, charset: Charset) -> Vec<usize> {
+        match charset.encode(text) {
+            Some(bytes) => self.search(range, &bytes),
+            None => vec![],
+        }
+    }
+
+    /// addresses within _range_ holding _value_ as a little endian word, e.g. a pointer.
+    pub fn search_word(&self, range: Range<usize>, value: u16) -> Vec<usize> {
+        self.search(range, &value.to_le_bytes())
+    }
+
+    /// CRC-32 of the bytes within _range_, for cheaply comparing a region against a known
+    /// good state or an earlier hash. bytes that cannot be peeked count as $FF, like an open bus.
+    pub fn hash_range(&self, range: Range<usize>) -> u32 {
+        let end = range.end.min(self.get_byte_count());
+        let crc = (range.start..end).fold(!0u32, |crc, addr| {
+            let mut crc = crc ^ self.peek(addr).unwrap_or(0xFF) as u32;
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+            }
+            crc
+        });
+        !crc
+    }
+
+    /// the bytes within _range_ as rows of 16, each the address, the bytes in hex and as
+    /// ASCII, e.g. `0400  48 49 00 ..  HI..`. bytes that cannot be peeked show as `??`.
+    pub fn hexdump(&self, range: Range<usize>) -> String {
+        let width = if self.get_byte_count() > 0x10000 {
+            6
+        } else {
+            4
+        };
+        let end = range.end.min(self.get_byte_count());
+        let mut out = String::new();
+        for row in (range.start..end).step_by(16) {
+            let bytes: Vec<_> = (row..(row + 16).min(end)).map(|v| self.peek(v)).collect();
+            let hex: Vec<_> = bytes
+                .iter()
+                .map(|v| v.map_or("??".to_owned(), |v| format!("{:02X}", v)))
+                .collect();
+            let text: String = bytes
+                .iter()
+                .map(|v| match v {
+                    Some(v @ 0x20..=0x7E) => *v as char,
+                    _ => '.',
+                })
+                .collect();
+            out.push_str(&format!(
+                "{:0w$X}  {: <47}  {}\n",
+                row,
+                hex.join(" "),
+                text,
+                w = width
+            ));
+        }
+        out
+    }
+
+    fn search(&self, range: Range<usize>, pattern: &[u8]) -> Vec<usize> {
+        if pattern.is_empty() {
+            return vec![];
+        }
+        let end = range.end.min(self.get_byte_count());
+        let mem: Vec<Option<u8>> = (range.start..end).map(|addr| self.peek(addr)).collect();
+        mem.windows(pattern.len())
+            .enumerate()
+            .filter(|(_, window)| window.iter().zip(pattern).all(|(v, p)| *v == Some(*p)))
+            .map(|(i, _)| range.start + i)
+            .collect()
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+
+use crate::DebugInfo;
+
+/// labels for addresses, for showing `JSR CHROUT` instead of `JSR $FFD2` in traces and
+/// disassembly, see [`CPU::set_symbols`](crate::CPU::set_symbols).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SymbolTable {
+    names: BTreeMap<u16, String>,
+}
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// parse a VICE label file as written by `ld65 -Ln`, with lines like `al 00FFD2 .CHROUT`.
+    /// other lines are skipped.
+    pub fn parse_vice(text: &str) -> Self {
+        let mut table = Self::new();
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some("al"), Some(addr), Some(name)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if let Ok(addr) = u32::from_str_radix(addr.trim_start_matches("C:"), 16) {
+                table.insert(addr as u16, name.trim_start_matches('.'));
+            }
+        }
+        table
+    }
+
+    /// label _addr_ as _name_, replacing any label it had.
+    pub fn insert(&mut self, addr: u16, name: impl Into<String>) {
+        self.names.insert(addr, name.into());
+    }
+
+    pub fn remove(&mut self, addr: u16) -> Option<String> {
+        self.names.remove(&addr)
+    }
+
+    pub fn name(&self, addr: u16) -> Option<&str> {
+        self.names.get(&addr).map(|v| v.as_str())
+    }
+
+    pub fn addr(&self, name: &str) -> Option<u16> {
+        self.names
+            .iter()
+            .find(|(_, v)| *v == name)
+            .map(|(addr, _)| *addr)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> + '_ {
+        self.names.iter().map(|(addr, name)| (*addr, name.as_str()))
+    }
+}
+/// the first symbol of every address with symbols.
+impl From<&DebugInfo> for SymbolTable {
+    fn from(info: &DebugInfo) -> Self {
+        let mut table = Self::new();
+        for (addr, name) in info.symbols() {
+            table.names.entry(addr).or_insert_with(|| name.to_owned());
+        }
+        table
+    }
+}
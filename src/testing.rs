@@ -0,0 +1,214 @@
+use std::ops::Range;
+
+use crate::{ExecutionError, RunState, CPU};
+
+/// result code a routine writes to its done address when it succeeded.
+/// any other value written there is treated as a failure code.
+pub const PASS: u8 = 0x00;
+
+#[derive(Debug)]
+pub enum TestError {
+    Execution(ExecutionError),
+    /// the program counter got stuck at this address (e.g. `JMP *` or `BNE *`).
+    Trapped(u16),
+    /// the CPU stopped on STP or jammed on an NMOS JAM opcode at this address,
+    /// it runs nothing more until reset.
+    Halted(u16),
+    /// the step budget ran out before the condition was met.
+    StepLimit(usize),
+    /// the routine reported this failure code.
+    Failed(u8),
+}
+impl From<ExecutionError> for TestError {
+    fn from(value: ExecutionError) -> Self {
+        Self::Execution(value)
+    }
+}
+
+/// step _cpu_ until it writes to _addr_ and return the written value.
+pub fn run_until_write(cpu: &mut CPU, addr: u16, max_steps: usize) -> Result<u8, TestError> {
+    cpu.watch_write(Some(addr));
+    let result = run_steps(cpu, max_steps, |cpu| cpu.take_write_watch_hit());
+    cpu.watch_write(None);
+    result
+}
+
+/// step _cpu_ until it writes a result code to _done_addr_, following the [`PASS`] convention.
+pub fn run_until_done(cpu: &mut CPU, done_addr: u16, max_steps: usize) -> Result<(), TestError> {
+    match run_until_write(cpu, done_addr, max_steps)? {
+        PASS => Ok(()),
+        code => Err(TestError::Failed(code)),
+    }
+}
+
+/// step _cpu_ until it traps itself in a jump or branch to the same instruction and return the trap address.
+pub fn run_until_trap(cpu: &mut CPU, max_steps: usize) -> Result<u16, TestError> {
+    match run_steps(cpu, max_steps, |_| None::<()>) {
+        Err(TestError::Trapped(pc)) => Ok(pc),
+        Err(e) => Err(e),
+        Ok(()) => unreachable!(),
+    }
+}
+
+/// assert that memory in _range_ holds exactly _expected_, peeking so that no device sees a read.
+/// bytes that cannot be peeked never match.
+#[track_caller]
+pub fn assert_memory_eq(cpu: &CPU, range: Range<u16>, expected: &[u8]) {
+    assert_eq!(
+        range.len(),
+        expected.len(),
+        "range {:#06x}..{:#06x} does not match the length of expected bytes ({})",
+        range.start,
+        range.end,
+        expected.len()
+    );
+    for (addr, want) in range.zip(expected) {
+        match cpu.peek_byte(addr) {
+            Some(got) => assert_eq!(
+                got, *want,
+                "memory at {:#06x} is {:#04x}, expected {:#04x}",
+                addr, got, want
+            ),
+            None => panic!(
+                "memory at {:#06x} cannot be peeked, expected {:#04x}",
+                addr, want
+            ),
+        }
+    }
+}
+
+fn run_steps<T>(
+    cpu: &mut CPU,
+    max_steps: usize,
+    mut done: impl FnMut(&mut CPU) -> Option<T>,
+) -> Result<T, TestError> {
+    if let RunState::Stopped | RunState::Jammed = cpu.run_state() {
+        return Err(TestError::Halted(cpu.get_pc()));
+    }
+    for _ in 0..max_steps {
+        let pc = cpu.get_pc();
+        // steps waiting on WAI or stalled by RDY run nothing, so PC staying put is no trap
+        let idle = cpu.run_state() == RunState::Waiting || !cpu.rdy_line().is_ready();
+        cpu.step()?;
+        if let Some(v) = done(cpu) {
+            return Ok(v);
+        }
+        if let RunState::Stopped | RunState::Jammed = cpu.run_state() {
+            return Err(TestError::Halted(pc));
+        }
+        if cpu.get_pc() == pc && !idle {
+            return Err(TestError::Trapped(pc));
+        }
+    }
+    Err(TestError::StepLimit(max_steps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm, devices::Interrupt, CpuModel, LayoutBuilder, Machine, RAM};
+
+    const ORIGIN: u16 = 0x0400;
+
+    fn machine(source: &str) -> Machine {
+        Machine::with_program(ORIGIN, &asm!(ORIGIN, source))
+    }
+
+    #[test]
+    fn done_passes_on_pass() {
+        let mut machine = machine("LDA #$00; STA $0200; end: JMP end");
+        assert!(run_until_done(machine.cpu_mut(), 0x0200, 10).is_ok());
+    }
+
+    #[test]
+    fn done_fails_with_the_code() {
+        let mut machine = machine("LDA #$2A; STA $0200; end: JMP end");
+        let result = run_until_done(machine.cpu_mut(), 0x0200, 10);
+        assert!(matches!(result, Err(TestError::Failed(0x2A))));
+    }
+
+    #[test]
+    fn done_reports_a_trap_before_the_write() {
+        let mut machine = machine("NOP; loop: BNE loop");
+        // Z is clear after reset with zeroed memory, so BNE is taken
+        let result = run_until_done(machine.cpu_mut(), 0x0200, 10);
+        assert!(matches!(result, Err(TestError::Trapped(0x0401))));
+    }
+
+    #[test]
+    fn done_runs_out_of_steps() {
+        let mut machine = machine("loop: NOP; JMP loop");
+        let result = run_until_done(machine.cpu_mut(), 0x0200, 10);
+        assert!(matches!(result, Err(TestError::StepLimit(10))));
+    }
+
+    #[test]
+    fn trap_returns_its_address() {
+        let mut machine = machine("LDX #$03; loop: DEX; BNE loop; end: JMP end");
+        assert_eq!(run_until_trap(machine.cpu_mut(), 20).unwrap(), 0x0405);
+    }
+
+    #[test]
+    fn trap_waits_out_wai() {
+        let mut machine = machine("CLI; WAI; end: JMP end");
+        let cpu = machine.cpu_mut();
+        // RTI
+        cpu.poke_byte(0x0410, 0x40).unwrap();
+        cpu.poke_byte(0xFFFE, 0x10).unwrap();
+        cpu.poke_byte(0xFFFF, 0x04).unwrap();
+        // PC stays at the WAI while waiting, which is no trap
+        let result = run_until_trap(cpu, 10);
+        assert!(matches!(result, Err(TestError::StepLimit(10))));
+        cpu.set_pending(Interrupt::Irq, true);
+        assert_eq!(run_until_trap(cpu, 10).unwrap(), 0x0402);
+    }
+
+    #[test]
+    fn trap_fails_at_once_on_stp() {
+        let mut machine = machine("NOP; STP");
+        let result = run_until_trap(machine.cpu_mut(), 1000);
+        assert!(matches!(result, Err(TestError::Halted(0x0401))));
+    }
+
+    #[test]
+    fn trap_fails_at_once_on_jam() {
+        let mut builder = LayoutBuilder::new(0x10000);
+        let ram = builder.add_device(RAM::<0x10000>::default());
+        builder.assign_range(0, 0x10000, ram);
+        let mut cpu = CPU::with_model(builder.build().unwrap(), CpuModel::NMOS6502).unwrap();
+        // NOP; JAM
+        for (addr, byte) in [
+            (0x0400, 0xEA),
+            (0x0401, 0x02),
+            (0xFFFC, 0x00),
+            (0xFFFD, 0x04),
+        ] {
+            cpu.poke_byte(addr, byte).unwrap();
+        }
+        cpu.reset();
+        let result = run_until_trap(&mut cpu, 1000);
+        assert!(matches!(result, Err(TestError::Halted(0x0401))));
+    }
+
+    #[test]
+    fn memory_eq_matches() {
+        let mut machine = machine("LDA #$12; STA $10; LDA #$34; STA $11; end: JMP end");
+        run_until_trap(machine.cpu_mut(), 10).unwrap();
+        assert_memory_eq(machine.cpu(), 0x10..0x12, &[0x12, 0x34]);
+    }
+
+    #[test]
+    #[should_panic(expected = "memory at 0x0011 is 0x34, expected 0x00")]
+    fn memory_eq_reports_the_first_mismatch() {
+        let mut machine = machine("LDA #$12; STA $10; LDA #$34; STA $11; end: JMP end");
+        run_until_trap(machine.cpu_mut(), 10).unwrap();
+        assert_memory_eq(machine.cpu(), 0x10..0x12, &[0x12, 0x00]);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not match the length")]
+    fn memory_eq_checks_the_length() {
+        let machine = machine("end: JMP end");
+        assert_memory_eq(machine.cpu(), 0x10..0x12, &[0x00]);
+    }
+}
@@ -0,0 +1,213 @@
+use core::fmt;
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{devices::Interrupt, AddressingMode, Cpu, Registers, CPU};
+
+/// receives every instruction the CPU executes, see [`CPU::set_trace_sink`].
+pub trait TraceSink {
+    /// record the instruction _cpu_ just executed, its registers already hold the results.
+    fn record(&mut self, cpu: &CPU) -> io::Result<()>;
+}
+
+/// an executed instruction as data, see [`CPU::trace_event`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub pc: u16,
+    /// as written in assembly, e.g. `LDA` or `BBS3`.
+    pub mnemonic: String,
+    pub addr_mode: AddressingMode,
+    /// the memory operand read or written, None for implied and immediate operands, jumps,
+    /// branches and stack accesses.
+    pub effective_addr: Option<u16>,
+    /// the byte read from or written to _effective_addr_, read-modify-write instructions
+    /// give the byte before modifying it.
+    pub value: Option<u8>,
+    /// clock cycles the instruction took, without interrupts taken before it.
+    pub cycles: u64,
+    pub status_before: u8,
+    pub status_after: u8,
+}
+
+/// how a [`CallFrame`] was entered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Jsr,
+    Brk,
+    Interrupt(Interrupt),
+}
+
+/// a subroutine or interrupt handler being run, see [`CPU::call_stack`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub kind: CallKind,
+    /// the JSR or BRK, or the instruction the interrupt was taken before.
+    pub caller: u16,
+    /// the subroutine or handler entered.
+    pub target: u16,
+    /// where execution continues once the frame returns.
+    pub return_addr: u16,
+    /// SP after the return address was pushed.
+    pub sp: u8,
+}
+impl fmt::Display for CallFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            CallKind::Jsr => "JSR",
+            CallKind::Brk => "BRK",
+            CallKind::Interrupt(Interrupt::Irq) => "IRQ",
+            CallKind::Interrupt(Interrupt::Nmi) => "NMI",
+        };
+        write!(
+            f,
+            "${:04X} from ${:04X} ({}), returns to ${:04X}",
+            self.target, self.caller, kind, self.return_addr
+        )
+    }
+}
+
+/// an executed instruction kept in the history, see [`CPU::set_history_len`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub opcode: u8,
+    /// the bytes following the opcode, the first _operand_len_ of them are valid.
+    pub operand: [u8; 2],
+    pub operand_len: u8,
+    /// registers after the instruction executed.
+    pub regs: Registers,
+    /// the cycle count after the instruction executed.
+    pub cycles: u64,
+}
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<_> = std::iter::once(self.opcode)
+            .chain(self.operand.into_iter().take(self.operand_len as usize))
+            .map(|v| format!("{:02X}", v))
+            .collect();
+        write!(
+            f,
+            "{:04X}  {: <8} A={:02X} X={:02X} Y={:02X} SP={:02X} P={:02X} CYC={}",
+            self.pc,
+            bytes.join(" "),
+            self.regs.a,
+            self.regs.x,
+            self.regs.y,
+            self.regs.sp,
+            self.regs.status,
+            self.cycles
+        )
+    }
+}
+
+/// the human-readable trace format, one instruction per line.
+pub struct TextTrace<W> {
+    out: W,
+}
+impl<W: Write> TextTrace<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+impl<W: Write> TraceSink for TextTrace<W> {
+    fn record(&mut self, cpu: &CPU) -> io::Result<()> {
+        let line = format!("{}\n", cpu.trace_exec().trim_end());
+        self.out.write_all(line.as_bytes())
+    }
+}
+
+/// one JSON object per line and instruction, for processing traces with scripts, e.g.
+/// `{"pc":1024,"inst":"LDA","operand":"#$80","a":128,"x":0,"y":0,"sp":253,"p":164,"cycles":9}`.
+/// registers are the ones after the instruction executed.
+pub struct JsonTrace<W> {
+    out: W,
+}
+impl<W: Write> JsonTrace<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+impl<W: Write> TraceSink for JsonTrace<W> {
+    fn record(&mut self, cpu: &CPU) -> io::Result<()> {
+        let (pc, inst) = cpu.trace_inst();
+        let regs = cpu.registers();
+        let line = format!(
+            "{{\"pc\":{},\"inst\":\"{}\",\"operand\":\"{}\",\"a\":{},\"x\":{},\"y\":{},\"sp\":{},\"p\":{},\"cycles\":{}}}\n",
+            pc,
+            inst,
+            cpu.trace_operand(),
+            regs.a,
+            regs.x,
+            regs.y,
+            regs.sp,
+            regs.status,
+            cpu.cycles()
+        );
+        self.out.write_all(line.as_bytes())
+    }
+}
+
+/// a file that is rotated once it would grow past _max_bytes_, keeping the _keep_ most recent
+/// old files as `path.1` (newest) to `path.keep`. rotation only happens between writes,
+/// so a record written at once never gets split across files.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: File,
+    written: u64,
+}
+impl RotatingFile {
+    pub fn create(path: impl AsRef<Path>, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        assert!(max_bytes > 0, "rotation size must be non-zero");
+        let path = path.as_ref().to_owned();
+        Ok(Self {
+            file: File::create(&path)?,
+            path,
+            max_bytes,
+            keep,
+            written: 0,
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = File::create(&self.path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+}
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -0,0 +1,1614 @@
+use core::fmt;
+
+use crate::{devices::Interrupt, Cpu, Device, ExecutionError, Layout};
+
+const FLAG_C: u8 = 0x01;
+const FLAG_Z: u8 = 0x02;
+const FLAG_I: u8 = 0x04;
+const FLAG_D: u8 = 0x08;
+/// index registers are 8 bits wide, B (break) in emulation mode.
+const FLAG_X: u8 = 0x10;
+/// accumulator and memory are 8 bits wide.
+const FLAG_M: u8 = 0x20;
+const FLAG_V: u8 = 0x40;
+const FLAG_N: u8 = 0x80;
+
+/// a WDC 65C816 microprocessor emulator, in emulation mode after reset and switched to
+/// native mode by `CLC; XCE` like the real part.
+///
+/// addresses are 24 bits wide, bank _n_ is at _n_ * $10000 in the layout. accesses past the
+/// end of the layout read 0 and are not written, so a layout of 64K is enough for emulation mode.
+/// interrupt vectors are always fetched from bank 0.
+pub struct W65C816 {
+    pc: u16,
+    /// program bank, K.
+    pbr: u8,
+    /// data bank, B.
+    dbr: u8,
+    /// direct page.
+    d: u16,
+    sp: u16,
+    /// the whole 16-bit accumulator C, A is its low byte.
+    a: u16,
+    x: u16,
+    y: u16,
+    p: u8,
+    emulation: bool,
+    layout: Layout,
+    cycles: u64,
+    /// cycles the current instruction takes on top of its base cycles.
+    extra_cycles: u64,
+    irq_pending: bool,
+    nmi_pending: bool,
+    nmi_line: bool,
+    waiting: bool,
+    stopped: bool,
+}
+impl fmt::Debug for W65C816 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("W65C816")
+            .field("registers", &self.registers())
+            .finish()
+    }
+}
+impl fmt::Display for W65C816 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let letters: String = "NVMXDIZC"
+            .chars()
+            .enumerate()
+            .map(|(i, c)| match self.p & (0x80 >> i) {
+                0 => c.to_ascii_lowercase(),
+                _ => c,
+            })
+            .collect();
+        write!(
+            f,
+            "PC={:02X}:{:04X} C={:04X} X={:04X} Y={:04X} S={:04X} D={:04X} DB={:02X} P={:02X} {} {}",
+            self.pbr,
+            self.pc,
+            self.a,
+            self.x,
+            self.y,
+            self.sp,
+            self.d,
+            self.dbr,
+            self.p,
+            letters,
+            if self.emulation { 'E' } else { 'e' }
+        )
+    }
+}
+impl Drop for W65C816 {
+    fn drop(&mut self) {
+        self.layout.detach();
+    }
+}
+
+/// the programmer-visible registers of a [`W65C816`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Registers816 {
+    pub pc: u16,
+    pub pbr: u8,
+    pub dbr: u8,
+    pub d: u16,
+    pub sp: u16,
+    /// the 16-bit accumulator, C.
+    pub a: u16,
+    pub x: u16,
+    pub y: u16,
+    pub status: u8,
+    pub emulation: bool,
+}
+
+impl W65C816 {
+    /// create a 65C816 emulator, _layout_ must have at least 65536 possible addresses
+    /// to hold bank 0.
+    pub fn new(mut layout: Layout) -> Option<Self> {
+        if layout.get_byte_count() < 0x10000 {
+            return None;
+        }
+        layout.attach();
+
+        Some(Self {
+            pc: 0,
+            pbr: 0,
+            dbr: 0,
+            d: 0,
+            sp: 0x01FF,
+            a: 0,
+            x: 0,
+            y: 0,
+            p: FLAG_M | FLAG_X | FLAG_I,
+            emulation: true,
+            layout,
+            cycles: 0,
+            extra_cycles: 0,
+            irq_pending: false,
+            nmi_pending: false,
+            nmi_line: false,
+            waiting: false,
+            stopped: false,
+        })
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    pub fn layout_mut(&mut self) -> &mut Layout {
+        &mut self.layout
+    }
+
+    pub fn is_emulation(&self) -> bool {
+        self.emulation
+    }
+
+    /// the program bank and PC as one 24-bit address.
+    pub fn get_full_pc(&self) -> u32 {
+        (self.pbr as u32) << 16 | self.pc as u32
+    }
+
+    /// reset like pulling the RES line: emulation mode, 8-bit registers, direct page and banks
+    /// at 0 and PC loaded from the reset vector.
+    pub fn reset(&mut self) {
+        self.layout.reset();
+        self.irq_pending = false;
+        self.nmi_pending = false;
+        self.waiting = false;
+        self.stopped = false;
+
+        self.emulation = true;
+        self.pbr = 0;
+        self.dbr = 0;
+        self.d = 0;
+        self.sp = 0x0100 | (self.sp.wrapping_sub(3) & 0xFF);
+        self.x &= 0xFF;
+        self.y &= 0xFF;
+        self.p = (self.p | FLAG_M | FLAG_X | FLAG_I) & !FLAG_D;
+        self.pc = self.read_bank0_word(0xFFFC);
+        self.spend_cycles(7);
+    }
+
+    pub fn irq(&mut self) {
+        if self.stopped {
+            return;
+        }
+        self.waiting = false;
+        if self.p & FLAG_I != 0 {
+            return;
+        }
+        let vector = if self.emulation { 0xFFFE } else { 0xFFEE };
+        self.interrupt(vector, Some(Interrupt::Irq), false);
+    }
+
+    pub fn nmi(&mut self) {
+        if self.stopped {
+            return;
+        }
+        self.waiting = false;
+        let vector = if self.emulation { 0xFFFA } else { 0xFFEA };
+        self.interrupt(vector, Some(Interrupt::Nmi), false);
+    }
+
+    /// hold or release the edge-triggered NMI input, see [`Cpu::set_nmi_line`].
+    pub fn set_nmi_line(&mut self, asserted: bool) {
+        if asserted && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = asserted;
+    }
+
+    /// latch _interrupt_ to be taken before the next instruction.
+    pub fn set_pending(&mut self, interrupt: Interrupt, pending: bool) {
+        match interrupt {
+            Interrupt::Irq => self.irq_pending = pending,
+            Interrupt::Nmi => self.nmi_pending = pending,
+        }
+    }
+
+    /// execute one instruction, after taking any pending interrupt, and return the clock cycles
+    /// both took. MVN and MVP move one byte per step.
+    pub fn step(&mut self) -> Result<u64, ExecutionError> {
+        let start = self.cycles;
+        if self.irq_pending || self.nmi_pending {
+            self.waiting = false;
+        }
+        if self.waiting || self.stopped {
+            self.spend_cycles(1);
+            return Ok(self.cycles - start);
+        }
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.nmi();
+        }
+        if self.irq_pending && self.p & FLAG_I == 0 {
+            self.irq_pending = false;
+            self.irq();
+        }
+
+        let opcode = self.fetch_byte();
+        let (op, mode, base_cycles) = OPCODES[opcode as usize];
+        let (m8, x8) = (self.is_m8(), self.is_x8());
+        self.extra_cycles = 0;
+        self.execute(op, mode);
+
+        let mut cycles = base_cycles as u64 + self.extra_cycles;
+        cycles += match op {
+            Op::ASL | Op::LSR | Op::ROL | Op::ROR | Op::DEC | Op::INC | Op::TRB | Op::TSB
+                if mode != Mode::Acc && !m8 =>
+            {
+                2
+            }
+            Op::ADC
+            | Op::AND
+            | Op::BIT
+            | Op::CMP
+            | Op::EOR
+            | Op::LDA
+            | Op::ORA
+            | Op::SBC
+            | Op::STA
+            | Op::STZ
+            | Op::PHA
+            | Op::PLA
+                if !m8 =>
+            {
+                1
+            }
+            Op::CPX
+            | Op::CPY
+            | Op::LDX
+            | Op::LDY
+            | Op::STX
+            | Op::STY
+            | Op::PHX
+            | Op::PHY
+            | Op::PLX
+            | Op::PLY
+                if !x8 =>
+            {
+                1
+            }
+            Op::BRK | Op::COP | Op::RTI if !self.emulation => 1,
+            _ => 0,
+        };
+        if mode.is_direct() && self.d & 0xFF != 0 {
+            // the direct page not starting at a page boundary takes an extra addition
+            cycles += 1;
+        }
+        self.spend_cycles(cycles);
+        Ok(self.cycles - start)
+    }
+
+    fn spend_cycles(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        self.layout.tick(cycles);
+    }
+
+    fn interrupt(&mut self, vector: u16, interrupt: Option<Interrupt>, is_brk: bool) {
+        if !self.emulation {
+            self.push_byte(self.pbr);
+        }
+        self.push_word(self.pc);
+        let mut p = self.p;
+        if self.emulation {
+            // bit 4 is the B flag pushed in emulation mode
+            p = if is_brk { p | FLAG_X } else { p & !FLAG_X };
+        }
+        self.push_byte(p);
+        self.p = (self.p | FLAG_I) & !FLAG_D;
+        self.pbr = 0;
+        self.pc = match interrupt.and_then(|v| self.layout.acknowledge(v)) {
+            Some(vector) => vector,
+            None => self.read_bank0_word(vector),
+        };
+        if interrupt.is_some() {
+            self.spend_cycles(if self.emulation { 7 } else { 8 });
+        }
+    }
+
+    fn is_m8(&self) -> bool {
+        self.emulation || self.p & FLAG_M != 0
+    }
+
+    fn is_x8(&self) -> bool {
+        self.emulation || self.p & FLAG_X != 0
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        self.p & flag != 0
+    }
+
+    fn set_flag(&mut self, flag: u8, set: bool) {
+        if set {
+            self.p |= flag;
+        } else {
+            self.p &= !flag;
+        }
+    }
+
+    fn set_p(&mut self, p: u8) {
+        self.p = p;
+        if self.emulation {
+            self.p |= FLAG_M | FLAG_X;
+        }
+        if self.p & FLAG_X != 0 {
+            self.x &= 0xFF;
+            self.y &= 0xFF;
+        }
+    }
+
+    fn set_nz(&mut self, value: u16, wide: bool) {
+        let (value, sign) = if wide {
+            (value, 0x8000)
+        } else {
+            (value & 0xFF, 0x80)
+        };
+        self.set_flag(FLAG_Z, value == 0);
+        self.set_flag(FLAG_N, value & sign != 0);
+    }
+
+    fn read(&mut self, addr: u32) -> u8 {
+        self.layout.read(addr as usize & 0xFF_FFFF).unwrap_or(0)
+    }
+
+    fn write(&mut self, addr: u32, data: u8) {
+        let _ = self.layout.write(addr as usize & 0xFF_FFFF, data);
+    }
+
+    fn read_value(&mut self, addr: u32, wide: bool) -> u16 {
+        let lo = self.read(addr) as u16;
+        if !wide {
+            return lo;
+        }
+        lo | (self.read(addr.wrapping_add(1)) as u16) << 8
+    }
+
+    fn write_value(&mut self, addr: u32, value: u16, wide: bool) {
+        self.write(addr, value as u8);
+        if wide {
+            self.write(addr.wrapping_add(1), (value >> 8) as u8);
+        }
+    }
+
+    fn read_bank0_word(&mut self, addr: u16) -> u16 {
+        u16::from_le_bytes([
+            self.read(addr as u32),
+            self.read(addr.wrapping_add(1) as u32),
+        ])
+    }
+
+    fn fetch_byte(&mut self) -> u8 {
+        let byte = self.read(self.get_full_pc());
+        self.pc = self.pc.wrapping_add(1);
+        byte
+    }
+
+    fn fetch_word(&mut self) -> u16 {
+        u16::from_le_bytes([self.fetch_byte(), self.fetch_byte()])
+    }
+
+    fn fetch_long(&mut self) -> u32 {
+        u32::from_le_bytes([self.fetch_byte(), self.fetch_byte(), self.fetch_byte(), 0])
+    }
+
+    fn push_byte(&mut self, data: u8) {
+        self.write(self.sp as u32, data);
+        self.sp = self.sp.wrapping_sub(1);
+        if self.emulation {
+            self.sp = 0x0100 | (self.sp & 0xFF);
+        }
+    }
+
+    fn pull_byte(&mut self) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        if self.emulation {
+            self.sp = 0x0100 | (self.sp & 0xFF);
+        }
+        self.read(self.sp as u32)
+    }
+
+    fn push_word(&mut self, data: u16) {
+        self.push_byte((data >> 8) as u8);
+        self.push_byte(data as u8);
+    }
+
+    fn pull_word(&mut self) -> u16 {
+        u16::from_le_bytes([self.pull_byte(), self.pull_byte()])
+    }
+
+    fn push_value(&mut self, value: u16, wide: bool) {
+        if wide {
+            self.push_word(value);
+        } else {
+            self.push_byte(value as u8);
+        }
+    }
+
+    fn pull_value(&mut self, wide: bool) -> u16 {
+        if wide {
+            self.pull_word()
+        } else {
+            self.pull_byte() as u16
+        }
+    }
+
+    /// address of direct page offset _offset_ plus _index_. in emulation mode with the direct page
+    /// on a page boundary, indexing wraps around within the page like on the 6502.
+    fn direct_addr(&self, offset: u8, index: u16) -> u32 {
+        if self.emulation && self.d & 0xFF == 0 {
+            (self.d | (offset as u16 + index) & 0xFF) as u32
+        } else {
+            self.d.wrapping_add(offset as u16).wrapping_add(index) as u32
+        }
+    }
+
+    fn data_addr(&self, addr: u16) -> u32 {
+        (self.dbr as u32) << 16 | addr as u32
+    }
+
+    /// index a base address by _index_, accounting for the extra cycle reads take when the index
+    /// crosses a page or is 16 bits wide.
+    fn indexed(&mut self, base: u32, index: u16) -> u32 {
+        let addr = base.wrapping_add(index as u32) & 0xFF_FFFF;
+        if !self.is_x8() || (base & 0xFFFF00) != (addr & 0xFFFF00) {
+            self.extra_cycles += 1;
+        }
+        addr
+    }
+
+    /// fetch the operand of _mode_ and work out the address it refers to.
+    fn operand_addr(&mut self, mode: Mode) -> u32 {
+        match mode {
+            Mode::Dp => {
+                let offset = self.fetch_byte();
+                self.direct_addr(offset, 0)
+            }
+            Mode::DpX => {
+                let offset = self.fetch_byte();
+                self.direct_addr(offset, self.x)
+            }
+            Mode::DpY => {
+                let offset = self.fetch_byte();
+                self.direct_addr(offset, self.y)
+            }
+            Mode::DpInd => {
+                let offset = self.fetch_byte();
+                let ptr = self.direct_addr(offset, 0) as u16;
+                let addr = self.read_bank0_word(ptr);
+                self.data_addr(addr)
+            }
+            Mode::DpIndX => {
+                let offset = self.fetch_byte();
+                let ptr = self.direct_addr(offset, self.x) as u16;
+                let addr = self.read_bank0_word(ptr);
+                self.data_addr(addr)
+            }
+            Mode::DpIndY => {
+                let offset = self.fetch_byte();
+                let ptr = self.direct_addr(offset, 0) as u16;
+                let base = self.read_bank0_word(ptr);
+                let base = self.data_addr(base);
+                self.indexed(base, self.y)
+            }
+            Mode::DpIndLong | Mode::DpIndLongY => {
+                let offset = self.fetch_byte();
+                let ptr = self.direct_addr(offset, 0) as u16;
+                let addr = u32::from_le_bytes([
+                    self.read(ptr as u32),
+                    self.read(ptr.wrapping_add(1) as u32),
+                    self.read(ptr.wrapping_add(2) as u32),
+                    0,
+                ]);
+                match mode {
+                    Mode::DpIndLongY => addr.wrapping_add(self.y as u32) & 0xFF_FFFF,
+                    _ => addr,
+                }
+            }
+            Mode::Abs => {
+                let addr = self.fetch_word();
+                self.data_addr(addr)
+            }
+            Mode::AbsX => {
+                let base = self.fetch_word();
+                let base = self.data_addr(base);
+                self.indexed(base, self.x)
+            }
+            Mode::AbsY => {
+                let base = self.fetch_word();
+                let base = self.data_addr(base);
+                self.indexed(base, self.y)
+            }
+            Mode::Long => self.fetch_long(),
+            Mode::LongX => self.fetch_long().wrapping_add(self.x as u32) & 0xFF_FFFF,
+            Mode::Sr => {
+                let offset = self.fetch_byte();
+                self.sp.wrapping_add(offset as u16) as u32
+            }
+            Mode::SrIndY => {
+                let offset = self.fetch_byte();
+                let ptr = self.sp.wrapping_add(offset as u16);
+                let base = self.read_bank0_word(ptr);
+                self.data_addr(base).wrapping_add(self.y as u32) & 0xFF_FFFF
+            }
+            _ => unreachable!("{:?} has no operand address", mode),
+        }
+    }
+
+    /// read the operand of _mode_, _wide_ telling whether it is 16 bits.
+    fn load(&mut self, mode: Mode, wide: bool) -> u16 {
+        match mode {
+            Mode::Imm if wide => self.fetch_word(),
+            Mode::Imm => self.fetch_byte() as u16,
+            Mode::Acc => self.a,
+            _ => {
+                let addr = self.operand_addr(mode);
+                self.read_value(addr, wide)
+            }
+        }
+    }
+
+    /// read, modify and write back the operand of _mode_, which may be the accumulator.
+    fn modify(&mut self, mode: Mode, f: impl FnOnce(&mut Self, u16) -> u16) {
+        let wide = !self.is_m8();
+        if mode == Mode::Acc {
+            let value = f(self, self.a);
+            self.set_a(value);
+            return;
+        }
+        let addr = self.operand_addr(mode);
+        // the writes take the same time whether or not the index crosses a page
+        self.extra_cycles = 0;
+        let value = self.read_value(addr, wide);
+        let value = f(self, value);
+        self.write_value(addr, value, wide);
+    }
+
+    fn store(&mut self, mode: Mode, value: u16, wide: bool) {
+        let addr = self.operand_addr(mode);
+        self.extra_cycles = 0;
+        self.write_value(addr, value, wide);
+    }
+
+    /// set A, or all of C with a 16-bit accumulator.
+    fn set_a(&mut self, value: u16) {
+        if self.is_m8() {
+            self.a = (self.a & 0xFF00) | (value & 0xFF);
+        } else {
+            self.a = value;
+        }
+    }
+
+    fn set_index(&mut self, value: u16) -> u16 {
+        if self.is_x8() {
+            value & 0xFF
+        } else {
+            value
+        }
+    }
+
+    fn branch(&mut self, offset: u16) {
+        let target = self.pc.wrapping_add(offset);
+        self.extra_cycles += 1;
+        if self.emulation && (target & 0xFF00) != (self.pc & 0xFF00) {
+            self.extra_cycles += 1;
+        }
+        self.pc = target;
+    }
+
+    fn branch_if(&mut self, cond: bool) {
+        let offset = self.fetch_byte() as i8 as u16;
+        if cond {
+            self.branch(offset);
+        }
+    }
+
+    fn compare(&mut self, reg: u16, operand: u16, wide: bool) {
+        let mask = if wide { 0xFFFF } else { 0xFF };
+        let (reg, operand) = (reg & mask, operand & mask);
+        self.set_flag(FLAG_C, reg >= operand);
+        self.set_nz(reg.wrapping_sub(operand), wide);
+    }
+
+    fn adc(&mut self, operand: u16) {
+        let wide = !self.is_m8();
+        let (mask, sign, digits) = if wide {
+            (0xFFFFu32, 0x8000u32, 4)
+        } else {
+            (0xFF, 0x80, 2)
+        };
+        let a = self.a as u32 & mask;
+        let b = operand as u32 & mask;
+        let carry = self.flag(FLAG_C) as u32;
+
+        let result = if self.flag(FLAG_D) {
+            let mut result = 0;
+            let mut carry = carry;
+            for digit in 0..digits {
+                let shift = digit * 4;
+                let mut sum = ((a >> shift) & 0xF) + ((b >> shift) & 0xF) + carry;
+                carry = (sum > 9) as u32;
+                if carry != 0 {
+                    sum += 6;
+                }
+                result |= (sum & 0xF) << shift;
+            }
+            // V follows the signed binary sum of the operands
+            let binary = a + b + self.flag(FLAG_C) as u32;
+            self.set_flag(FLAG_V, (a ^ binary) & (b ^ binary) & sign != 0);
+            self.set_flag(FLAG_C, carry != 0);
+            result
+        } else {
+            let result = a + b + carry;
+            self.set_flag(FLAG_V, (a ^ result) & (b ^ result) & sign != 0);
+            self.set_flag(FLAG_C, result > mask);
+            result & mask
+        };
+        self.set_a(result as u16);
+        self.set_nz(result as u16, wide);
+    }
+
+    fn sbc(&mut self, operand: u16) {
+        let wide = !self.is_m8();
+        let (mask, sign, digits) = if wide {
+            (0xFFFFu32, 0x8000u32, 4)
+        } else {
+            (0xFF, 0x80, 2)
+        };
+        let a = self.a as u32 & mask;
+        let b = operand as u32 & mask;
+        let borrow = !self.flag(FLAG_C) as u32;
+
+        let binary = a.wrapping_sub(b).wrapping_sub(borrow);
+        self.set_flag(FLAG_V, (a ^ b) & (a ^ binary) & sign != 0);
+        self.set_flag(FLAG_C, binary <= mask);
+        let result = if self.flag(FLAG_D) {
+            let mut result = 0;
+            let mut borrow = borrow as i32;
+            for digit in 0..digits {
+                let shift = digit * 4;
+                let mut diff = ((a >> shift) & 0xF) as i32 - ((b >> shift) & 0xF) as i32 - borrow;
+                borrow = (diff < 0) as i32;
+                if borrow != 0 {
+                    diff += 10;
+                }
+                result |= ((diff as u32) & 0xF) << shift;
+            }
+            result
+        } else {
+            binary & mask
+        };
+        self.set_a(result as u16);
+        self.set_nz(result as u16, wide);
+    }
+
+    fn execute(&mut self, op: Op, mode: Mode) {
+        let m_wide = !self.is_m8();
+        let x_wide = !self.is_x8();
+        match op {
+            Op::LDA => {
+                let value = self.load(mode, m_wide);
+                self.set_a(value);
+                self.set_nz(value, m_wide);
+            }
+            Op::LDX => {
+                let value = self.load(mode, x_wide);
+                self.x = value;
+                self.set_nz(value, x_wide);
+            }
+            Op::LDY => {
+                let value = self.load(mode, x_wide);
+                self.y = value;
+                self.set_nz(value, x_wide);
+            }
+            Op::STA => self.store(mode, self.a, m_wide),
+            Op::STX => self.store(mode, self.x, x_wide),
+            Op::STY => self.store(mode, self.y, x_wide),
+            Op::STZ => self.store(mode, 0, m_wide),
+
+            Op::ADC => {
+                let value = self.load(mode, m_wide);
+                self.adc(value);
+            }
+            Op::SBC => {
+                let value = self.load(mode, m_wide);
+                self.sbc(value);
+            }
+            Op::AND => {
+                let value = self.load(mode, m_wide) & self.a;
+                self.set_a(value);
+                self.set_nz(value, m_wide);
+            }
+            Op::ORA => {
+                let value = self.load(mode, m_wide) | self.a;
+                self.set_a(value);
+                self.set_nz(value, m_wide);
+            }
+            Op::EOR => {
+                let value = self.load(mode, m_wide) ^ self.a;
+                self.set_a(value);
+                self.set_nz(value, m_wide);
+            }
+            Op::CMP => {
+                let value = self.load(mode, m_wide);
+                self.compare(self.a, value, m_wide);
+            }
+            Op::CPX => {
+                let value = self.load(mode, x_wide);
+                self.compare(self.x, value, x_wide);
+            }
+            Op::CPY => {
+                let value = self.load(mode, x_wide);
+                self.compare(self.y, value, x_wide);
+            }
+            Op::BIT => {
+                let value = self.load(mode, m_wide);
+                self.set_flag(
+                    FLAG_Z,
+                    value & self.a & if m_wide { 0xFFFF } else { 0xFF } == 0,
+                );
+                // the immediate form has no memory operand to take N and V from
+                if mode != Mode::Imm {
+                    let sign = if m_wide { 0x8000 } else { 0x80 };
+                    self.set_flag(FLAG_N, value & sign != 0);
+                    self.set_flag(FLAG_V, value & (sign >> 1) != 0);
+                }
+            }
+            Op::TRB | Op::TSB => self.modify(mode, |cpu, value| {
+                let mask = if m_wide { 0xFFFF } else { 0xFF };
+                cpu.set_flag(FLAG_Z, value & cpu.a & mask == 0);
+                match op {
+                    Op::TRB => value & !cpu.a,
+                    _ => value | cpu.a,
+                }
+            }),
+
+            Op::ASL => self.modify(mode, |cpu, value| {
+                let sign = if m_wide { 0x8000 } else { 0x80 };
+                cpu.set_flag(FLAG_C, value & sign != 0);
+                let value = value << 1;
+                cpu.set_nz(value, m_wide);
+                value
+            }),
+            Op::LSR => self.modify(mode, |cpu, value| {
+                let value = if m_wide { value } else { value & 0xFF };
+                cpu.set_flag(FLAG_C, value & 1 != 0);
+                let value = value >> 1;
+                cpu.set_nz(value, m_wide);
+                value
+            }),
+            Op::ROL => self.modify(mode, |cpu, value| {
+                let sign = if m_wide { 0x8000 } else { 0x80 };
+                let carry = cpu.flag(FLAG_C) as u16;
+                cpu.set_flag(FLAG_C, value & sign != 0);
+                let value = value << 1 | carry;
+                cpu.set_nz(value, m_wide);
+                value
+            }),
+            Op::ROR => self.modify(mode, |cpu, value| {
+                let (value, sign) = if m_wide {
+                    (value, 0x8000)
+                } else {
+                    (value & 0xFF, 0x80)
+                };
+                let carry = cpu.flag(FLAG_C);
+                cpu.set_flag(FLAG_C, value & 1 != 0);
+                let value = value >> 1 | if carry { sign } else { 0 };
+                cpu.set_nz(value, m_wide);
+                value
+            }),
+            Op::INC => self.modify(mode, |cpu, value| {
+                let value = value.wrapping_add(1);
+                cpu.set_nz(value, m_wide);
+                value
+            }),
+            Op::DEC => self.modify(mode, |cpu, value| {
+                let value = value.wrapping_sub(1);
+                cpu.set_nz(value, m_wide);
+                value
+            }),
+            Op::INX => {
+                self.x = self.set_index(self.x.wrapping_add(1));
+                self.set_nz(self.x, x_wide);
+            }
+            Op::INY => {
+                self.y = self.set_index(self.y.wrapping_add(1));
+                self.set_nz(self.y, x_wide);
+            }
+            Op::DEX => {
+                self.x = self.set_index(self.x.wrapping_sub(1));
+                self.set_nz(self.x, x_wide);
+            }
+            Op::DEY => {
+                self.y = self.set_index(self.y.wrapping_sub(1));
+                self.set_nz(self.y, x_wide);
+            }
+
+            Op::TAX => {
+                self.x = self.set_index(self.a);
+                self.set_nz(self.x, x_wide);
+            }
+            Op::TAY => {
+                self.y = self.set_index(self.a);
+                self.set_nz(self.y, x_wide);
+            }
+            Op::TXA => {
+                self.set_a(self.x);
+                self.set_nz(self.x, m_wide);
+            }
+            Op::TYA => {
+                self.set_a(self.y);
+                self.set_nz(self.y, m_wide);
+            }
+            Op::TXY => {
+                self.y = self.x;
+                self.set_nz(self.y, x_wide);
+            }
+            Op::TYX => {
+                self.x = self.y;
+                self.set_nz(self.x, x_wide);
+            }
+            Op::TSX => {
+                self.x = self.set_index(self.sp);
+                self.set_nz(self.x, x_wide);
+            }
+            Op::TXS => {
+                self.sp = if self.emulation {
+                    0x0100 | (self.x & 0xFF)
+                } else {
+                    self.x
+                };
+            }
+            Op::TCD => {
+                self.d = self.a;
+                self.set_nz(self.d, true);
+            }
+            Op::TDC => {
+                self.a = self.d;
+                self.set_nz(self.a, true);
+            }
+            Op::TCS => {
+                self.sp = if self.emulation {
+                    0x0100 | (self.a & 0xFF)
+                } else {
+                    self.a
+                };
+            }
+            Op::TSC => {
+                self.a = self.sp;
+                self.set_nz(self.a, true);
+            }
+            Op::XBA => {
+                self.a = self.a.swap_bytes();
+                self.set_nz(self.a, false);
+            }
+            Op::XCE => {
+                let carry = self.flag(FLAG_C);
+                self.set_flag(FLAG_C, self.emulation);
+                self.emulation = carry;
+                if self.emulation {
+                    self.sp = 0x0100 | (self.sp & 0xFF);
+                }
+                self.set_p(self.p | if self.emulation { FLAG_M | FLAG_X } else { 0 });
+            }
+
+            Op::PHA => self.push_value(self.a, m_wide),
+            Op::PHX => self.push_value(self.x, x_wide),
+            Op::PHY => self.push_value(self.y, x_wide),
+            Op::PHP => {
+                // B always reads as set in emulation mode pushes
+                let p = if self.emulation {
+                    self.p | FLAG_X
+                } else {
+                    self.p
+                };
+                self.push_byte(p);
+            }
+            Op::PHB => self.push_byte(self.dbr),
+            Op::PHD => self.push_word(self.d),
+            Op::PHK => self.push_byte(self.pbr),
+            Op::PLA => {
+                let value = self.pull_value(m_wide);
+                self.set_a(value);
+                self.set_nz(value, m_wide);
+            }
+            Op::PLX => {
+                self.x = self.pull_value(x_wide);
+                self.set_nz(self.x, x_wide);
+            }
+            Op::PLY => {
+                self.y = self.pull_value(x_wide);
+                self.set_nz(self.y, x_wide);
+            }
+            Op::PLP => {
+                let p = self.pull_byte();
+                self.set_p(p);
+            }
+            Op::PLB => {
+                self.dbr = self.pull_byte();
+                self.set_nz(self.dbr as u16, false);
+            }
+            Op::PLD => {
+                self.d = self.pull_word();
+                self.set_nz(self.d, true);
+            }
+            Op::PEA => {
+                let value = self.fetch_word();
+                self.push_word(value);
+            }
+            Op::PEI => {
+                let offset = self.fetch_byte();
+                let ptr = self.direct_addr(offset, 0) as u16;
+                let value = self.read_bank0_word(ptr);
+                self.push_word(value);
+            }
+            Op::PER => {
+                let offset = self.fetch_word();
+                self.push_word(self.pc.wrapping_add(offset));
+            }
+
+            Op::CLC => self.set_flag(FLAG_C, false),
+            Op::CLD => self.set_flag(FLAG_D, false),
+            Op::CLI => self.set_flag(FLAG_I, false),
+            Op::CLV => self.set_flag(FLAG_V, false),
+            Op::SEC => self.set_flag(FLAG_C, true),
+            Op::SED => self.set_flag(FLAG_D, true),
+            Op::SEI => self.set_flag(FLAG_I, true),
+            Op::REP => {
+                let mask = self.fetch_byte();
+                self.set_p(self.p & !mask);
+            }
+            Op::SEP => {
+                let mask = self.fetch_byte();
+                self.set_p(self.p | mask);
+            }
+
+            Op::BRA => self.branch_if(true),
+            Op::BCC => self.branch_if(!self.flag(FLAG_C)),
+            Op::BCS => self.branch_if(self.flag(FLAG_C)),
+            Op::BNE => self.branch_if(!self.flag(FLAG_Z)),
+            Op::BEQ => self.branch_if(self.flag(FLAG_Z)),
+            Op::BPL => self.branch_if(!self.flag(FLAG_N)),
+            Op::BMI => self.branch_if(self.flag(FLAG_N)),
+            Op::BVC => self.branch_if(!self.flag(FLAG_V)),
+            Op::BVS => self.branch_if(self.flag(FLAG_V)),
+            Op::BRL => {
+                let offset = self.fetch_word();
+                self.pc = self.pc.wrapping_add(offset);
+            }
+
+            Op::JMP => {
+                self.pc = match mode {
+                    Mode::Abs => self.fetch_word(),
+                    Mode::AbsInd => {
+                        let ptr = self.fetch_word();
+                        self.read_bank0_word(ptr)
+                    }
+                    Mode::AbsIndX => {
+                        let ptr = self.fetch_word().wrapping_add(self.x);
+                        let bank = (self.pbr as u32) << 16;
+                        self.read_value(bank | ptr as u32, true)
+                    }
+                    _ => unreachable!("JMP {:?}", mode),
+                }
+            }
+            Op::JML => {
+                let target = match mode {
+                    Mode::Long => self.fetch_long(),
+                    _ => {
+                        let ptr = self.fetch_word();
+                        u32::from_le_bytes([
+                            self.read(ptr as u32),
+                            self.read(ptr.wrapping_add(1) as u32),
+                            self.read(ptr.wrapping_add(2) as u32),
+                            0,
+                        ])
+                    }
+                };
+                self.pbr = (target >> 16) as u8;
+                self.pc = target as u16;
+            }
+            Op::JSR => {
+                let target = match mode {
+                    Mode::Abs => self.fetch_word(),
+                    _ => {
+                        let ptr = self.fetch_word().wrapping_add(self.x);
+                        let bank = (self.pbr as u32) << 16;
+                        self.read_value(bank | ptr as u32, true)
+                    }
+                };
+                self.push_word(self.pc.wrapping_sub(1));
+                self.pc = target;
+            }
+            Op::JSL => {
+                let target = self.fetch_long();
+                self.push_byte(self.pbr);
+                self.push_word(self.pc.wrapping_sub(1));
+                self.pbr = (target >> 16) as u8;
+                self.pc = target as u16;
+            }
+            Op::RTS => self.pc = self.pull_word().wrapping_add(1),
+            Op::RTL => {
+                self.pc = self.pull_word().wrapping_add(1);
+                self.pbr = self.pull_byte();
+            }
+            Op::RTI => {
+                let p = self.pull_byte();
+                self.set_p(p);
+                self.pc = self.pull_word();
+                if !self.emulation {
+                    self.pbr = self.pull_byte();
+                }
+            }
+            Op::BRK | Op::COP => {
+                // the signature byte is skipped
+                self.pc = self.pc.wrapping_add(1);
+                let vector = match (op, self.emulation) {
+                    (Op::BRK, true) => 0xFFFE,
+                    (Op::BRK, false) => 0xFFE6,
+                    (_, true) => 0xFFF4,
+                    (_, false) => 0xFFE4,
+                };
+                self.interrupt(vector, None, op == Op::BRK);
+            }
+
+            Op::MVN | Op::MVP => {
+                let dest_bank = self.fetch_byte();
+                let src_bank = self.fetch_byte();
+                self.dbr = dest_bank;
+                let data = self.read((src_bank as u32) << 16 | self.x as u32);
+                self.write((dest_bank as u32) << 16 | self.y as u32, data);
+                let step = if op == Op::MVN { 1 } else { 0xFFFF };
+                self.x = self.set_index(self.x.wrapping_add(step));
+                self.y = self.set_index(self.y.wrapping_add(step));
+                self.a = self.a.wrapping_sub(1);
+                if self.a != 0xFFFF {
+                    // repeat until the count in C runs out
+                    self.pc = self.pc.wrapping_sub(3);
+                }
+            }
+
+            Op::WAI => self.waiting = true,
+            Op::STP => self.stopped = true,
+            Op::WDM => {
+                self.fetch_byte();
+            }
+            Op::NOP => {}
+        }
+    }
+}
+impl Cpu for W65C816 {
+    type Registers = Registers816;
+
+    fn reset(&mut self) {
+        W65C816::reset(self)
+    }
+
+    fn step(&mut self) -> Result<u64, ExecutionError> {
+        W65C816::step(self)
+    }
+
+    fn irq(&mut self) {
+        W65C816::irq(self)
+    }
+
+    fn nmi(&mut self) {
+        W65C816::nmi(self)
+    }
+
+    fn set_nmi_line(&mut self, asserted: bool) {
+        W65C816::set_nmi_line(self, asserted)
+    }
+
+    fn get_pc(&self) -> u16 {
+        self.pc
+    }
+
+    fn set_pc(&mut self, addr: u16) {
+        self.pc = addr;
+    }
+
+    fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    fn reset_cycles(&mut self) {
+        self.cycles = 0;
+    }
+
+    fn registers(&self) -> Registers816 {
+        Registers816 {
+            pc: self.pc,
+            pbr: self.pbr,
+            dbr: self.dbr,
+            d: self.d,
+            sp: self.sp,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            status: self.p,
+            emulation: self.emulation,
+        }
+    }
+
+    fn set_registers(&mut self, regs: Registers816) {
+        self.pc = regs.pc;
+        self.pbr = regs.pbr;
+        self.dbr = regs.dbr;
+        self.d = regs.d;
+        self.a = regs.a;
+        self.x = regs.x;
+        self.y = regs.y;
+        self.emulation = regs.emulation;
+        self.sp = if regs.emulation {
+            0x0100 | (regs.sp & 0xFF)
+        } else {
+            regs.sp
+        };
+        self.set_p(regs.status);
+    }
+
+    fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    fn layout_mut(&mut self) -> &mut Layout {
+        &mut self.layout
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    ADC,
+    AND,
+    ASL,
+    BCC,
+    BCS,
+    BEQ,
+    BIT,
+    BMI,
+    BNE,
+    BPL,
+    BRA,
+    BRK,
+    BRL,
+    BVC,
+    BVS,
+    CLC,
+    CLD,
+    CLI,
+    CLV,
+    CMP,
+    COP,
+    CPX,
+    CPY,
+    DEC,
+    DEX,
+    DEY,
+    EOR,
+    INC,
+    INX,
+    INY,
+    JML,
+    JMP,
+    JSL,
+    JSR,
+    LDA,
+    LDX,
+    LDY,
+    LSR,
+    MVN,
+    MVP,
+    NOP,
+    ORA,
+    PEA,
+    PEI,
+    PER,
+    PHA,
+    PHB,
+    PHD,
+    PHK,
+    PHP,
+    PHX,
+    PHY,
+    PLA,
+    PLB,
+    PLD,
+    PLP,
+    PLX,
+    PLY,
+    REP,
+    ROL,
+    ROR,
+    RTI,
+    RTL,
+    RTS,
+    SBC,
+    SEC,
+    SED,
+    SEI,
+    SEP,
+    STA,
+    STP,
+    STX,
+    STY,
+    STZ,
+    TAX,
+    TAY,
+    TCD,
+    TCS,
+    TDC,
+    TRB,
+    TSB,
+    TSC,
+    TSX,
+    TXA,
+    TXS,
+    TXY,
+    TYA,
+    TYX,
+    WAI,
+    WDM,
+    XBA,
+    XCE,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// no operand, or one the instruction fetches on its own.
+    Imp,
+    Acc,
+    /// 8 or 16 bits depending on the register the instruction works on.
+    Imm,
+    Dp,
+    DpX,
+    DpY,
+    DpInd,
+    DpIndX,
+    DpIndY,
+    DpIndLong,
+    DpIndLongY,
+    Abs,
+    AbsX,
+    AbsY,
+    Long,
+    LongX,
+    Sr,
+    SrIndY,
+    AbsInd,
+    AbsIndX,
+    AbsIndLong,
+}
+impl Mode {
+    fn is_direct(self) -> bool {
+        matches!(
+            self,
+            Mode::Dp
+                | Mode::DpX
+                | Mode::DpY
+                | Mode::DpInd
+                | Mode::DpIndX
+                | Mode::DpIndY
+                | Mode::DpIndLong
+                | Mode::DpIndLongY
+        )
+    }
+}
+
+/// instruction, addressing mode and cycles with 8-bit registers of every opcode,
+/// before width, direct page, indexing and branch penalties.
+#[rustfmt::skip]
+const OPCODES: [(Op, Mode, u8); 256] = {
+    use Mode::*;
+    use Op::*;
+    [
+        (BRK, Imp, 7), (ORA, DpIndX, 6), (COP, Imp, 7), (ORA, Sr, 4), (TSB, Dp, 5), (ORA, Dp, 3), (ASL, Dp, 5), (ORA, DpIndLong, 6),
+        (PHP, Imp, 3), (ORA, Imm, 2), (ASL, Acc, 2), (PHD, Imp, 4), (TSB, Abs, 6), (ORA, Abs, 4), (ASL, Abs, 6), (ORA, Long, 5),
+        (BPL, Imp, 2), (ORA, DpIndY, 5), (ORA, DpInd, 5), (ORA, SrIndY, 7), (TRB, Dp, 5), (ORA, DpX, 4), (ASL, DpX, 6), (ORA, DpIndLongY, 6),
+        (CLC, Imp, 2), (ORA, AbsY, 4), (INC, Acc, 2), (TCS, Imp, 2), (TRB, Abs, 6), (ORA, AbsX, 4), (ASL, AbsX, 7), (ORA, LongX, 5),
+        (JSR, Abs, 6), (AND, DpIndX, 6), (JSL, Long, 8), (AND, Sr, 4), (BIT, Dp, 3), (AND, Dp, 3), (ROL, Dp, 5), (AND, DpIndLong, 6),
+        (PLP, Imp, 4), (AND, Imm, 2), (ROL, Acc, 2), (PLD, Imp, 5), (BIT, Abs, 4), (AND, Abs, 4), (ROL, Abs, 6), (AND, Long, 5),
+        (BMI, Imp, 2), (AND, DpIndY, 5), (AND, DpInd, 5), (AND, SrIndY, 7), (BIT, DpX, 4), (AND, DpX, 4), (ROL, DpX, 6), (AND, DpIndLongY, 6),
+        (SEC, Imp, 2), (AND, AbsY, 4), (DEC, Acc, 2), (TSC, Imp, 2), (BIT, AbsX, 4), (AND, AbsX, 4), (ROL, AbsX, 7), (AND, LongX, 5),
+        (RTI, Imp, 6), (EOR, DpIndX, 6), (WDM, Imp, 2), (EOR, Sr, 4), (MVP, Imp, 7), (EOR, Dp, 3), (LSR, Dp, 5), (EOR, DpIndLong, 6),
+        (PHA, Imp, 3), (EOR, Imm, 2), (LSR, Acc, 2), (PHK, Imp, 3), (JMP, Abs, 3), (EOR, Abs, 4), (LSR, Abs, 6), (EOR, Long, 5),
+        (BVC, Imp, 2), (EOR, DpIndY, 5), (EOR, DpInd, 5), (EOR, SrIndY, 7), (MVN, Imp, 7), (EOR, DpX, 4), (LSR, DpX, 6), (EOR, DpIndLongY, 6),
+        (CLI, Imp, 2), (EOR, AbsY, 4), (PHY, Imp, 3), (TCD, Imp, 2), (JML, Long, 4), (EOR, AbsX, 4), (LSR, AbsX, 7), (EOR, LongX, 5),
+        (RTS, Imp, 6), (ADC, DpIndX, 6), (PER, Imp, 6), (ADC, Sr, 4), (STZ, Dp, 3), (ADC, Dp, 3), (ROR, Dp, 5), (ADC, DpIndLong, 6),
+        (PLA, Imp, 4), (ADC, Imm, 2), (ROR, Acc, 2), (RTL, Imp, 6), (JMP, AbsInd, 5), (ADC, Abs, 4), (ROR, Abs, 6), (ADC, Long, 5),
+        (BVS, Imp, 2), (ADC, DpIndY, 5), (ADC, DpInd, 5), (ADC, SrIndY, 7), (STZ, DpX, 4), (ADC, DpX, 4), (ROR, DpX, 6), (ADC, DpIndLongY, 6),
+        (SEI, Imp, 2), (ADC, AbsY, 4), (PLY, Imp, 4), (TDC, Imp, 2), (JMP, AbsIndX, 6), (ADC, AbsX, 4), (ROR, AbsX, 7), (ADC, LongX, 5),
+        (BRA, Imp, 2), (STA, DpIndX, 6), (BRL, Imp, 4), (STA, Sr, 4), (STY, Dp, 3), (STA, Dp, 3), (STX, Dp, 3), (STA, DpIndLong, 6),
+        (DEY, Imp, 2), (BIT, Imm, 2), (TXA, Imp, 2), (PHB, Imp, 3), (STY, Abs, 4), (STA, Abs, 4), (STX, Abs, 4), (STA, Long, 5),
+        (BCC, Imp, 2), (STA, DpIndY, 6), (STA, DpInd, 5), (STA, SrIndY, 7), (STY, DpX, 4), (STA, DpX, 4), (STX, DpY, 4), (STA, DpIndLongY, 6),
+        (TYA, Imp, 2), (STA, AbsY, 5), (TXS, Imp, 2), (TXY, Imp, 2), (STZ, Abs, 4), (STA, AbsX, 5), (STZ, AbsX, 5), (STA, LongX, 5),
+        (LDY, Imm, 2), (LDA, DpIndX, 6), (LDX, Imm, 2), (LDA, Sr, 4), (LDY, Dp, 3), (LDA, Dp, 3), (LDX, Dp, 3), (LDA, DpIndLong, 6),
+        (TAY, Imp, 2), (LDA, Imm, 2), (TAX, Imp, 2), (PLB, Imp, 4), (LDY, Abs, 4), (LDA, Abs, 4), (LDX, Abs, 4), (LDA, Long, 5),
+        (BCS, Imp, 2), (LDA, DpIndY, 5), (LDA, DpInd, 5), (LDA, SrIndY, 7), (LDY, DpX, 4), (LDA, DpX, 4), (LDX, DpY, 4), (LDA, DpIndLongY, 6),
+        (CLV, Imp, 2), (LDA, AbsY, 4), (TSX, Imp, 2), (TYX, Imp, 2), (LDY, AbsX, 4), (LDA, AbsX, 4), (LDX, AbsY, 4), (LDA, LongX, 5),
+        (CPY, Imm, 2), (CMP, DpIndX, 6), (REP, Imp, 3), (CMP, Sr, 4), (CPY, Dp, 3), (CMP, Dp, 3), (DEC, Dp, 5), (CMP, DpIndLong, 6),
+        (INY, Imp, 2), (CMP, Imm, 2), (DEX, Imp, 2), (WAI, Imp, 3), (CPY, Abs, 4), (CMP, Abs, 4), (DEC, Abs, 6), (CMP, Long, 5),
+        (BNE, Imp, 2), (CMP, DpIndY, 5), (CMP, DpInd, 5), (CMP, SrIndY, 7), (PEI, Dp, 6), (CMP, DpX, 4), (DEC, DpX, 6), (CMP, DpIndLongY, 6),
+        (CLD, Imp, 2), (CMP, AbsY, 4), (PHX, Imp, 3), (STP, Imp, 3), (JML, AbsIndLong, 6), (CMP, AbsX, 4), (DEC, AbsX, 7), (CMP, LongX, 5),
+        (CPX, Imm, 2), (SBC, DpIndX, 6), (SEP, Imp, 3), (SBC, Sr, 4), (CPX, Dp, 3), (SBC, Dp, 3), (INC, Dp, 5), (SBC, DpIndLong, 6),
+        (INX, Imp, 2), (SBC, Imm, 2), (NOP, Imp, 2), (XBA, Imp, 3), (CPX, Abs, 4), (SBC, Abs, 4), (INC, Abs, 6), (SBC, Long, 5),
+        (BEQ, Imp, 2), (SBC, DpIndY, 5), (SBC, DpInd, 5), (SBC, SrIndY, 7), (PEA, Imp, 5), (SBC, DpX, 4), (INC, DpX, 6), (SBC, DpIndLongY, 6),
+        (SED, Imp, 2), (SBC, AbsY, 4), (PLX, Imp, 4), (XCE, Imp, 2), (JSR, AbsIndX, 8), (SBC, AbsX, 4), (INC, AbsX, 7), (SBC, LongX, 5),
+    ]
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LayoutBuilder, RAM};
+
+    /// where the programs start, in bank 0.
+    const ORIGIN: u16 = 0x8000;
+
+    /// a 65C816 with three banks of RAM, reset into _program_ at [`ORIGIN`].
+    fn cpu(program: &[u8]) -> W65C816 {
+        let mut builder = LayoutBuilder::new(0x30000);
+        let ram = builder.add_device(RAM::<0x30000>::default());
+        builder.assign_range(0, 0x30000, ram);
+        let mut cpu = W65C816::new(builder.build().unwrap()).unwrap();
+        load(&mut cpu, ORIGIN as u32, program);
+        load(&mut cpu, 0xFFFC, &ORIGIN.to_le_bytes());
+        cpu.reset();
+        cpu
+    }
+
+    fn load(cpu: &mut W65C816, addr: u32, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            cpu.layout_mut().poke(addr as usize + i, *byte).unwrap();
+        }
+    }
+
+    fn peek(cpu: &W65C816, addr: u32) -> u8 {
+        cpu.layout().peek(addr as usize).unwrap()
+    }
+
+    fn run(cpu: &mut W65C816, steps: usize) {
+        for _ in 0..steps {
+            cpu.step().unwrap();
+        }
+    }
+
+    /// CLC; XCE; REP #$30
+    const NATIVE16: [u8; 4] = [0x18, 0xFB, 0xC2, 0x30];
+
+    #[test]
+    fn xce_switches_modes() {
+        // CLC; XCE; SEC; XCE
+        let mut cpu = cpu(&[0x18, 0xFB, 0x38, 0xFB]);
+        run(&mut cpu, 2);
+        assert!(!cpu.is_emulation());
+        // the old E lands in C
+        assert!(cpu.flag(FLAG_C));
+
+        cpu.sp = 0x1234;
+        run(&mut cpu, 2);
+        assert!(cpu.is_emulation());
+        assert!(!cpu.flag(FLAG_C));
+        assert_eq!(cpu.sp, 0x0134);
+        assert_eq!(cpu.p & (FLAG_M | FLAG_X), FLAG_M | FLAG_X);
+    }
+
+    #[test]
+    fn x_flag_truncates_index_registers() {
+        let mut program = NATIVE16.to_vec();
+        // LDX #$1234; LDY #$5678; SEP #$10; REP #$10
+        program.extend([0xA2, 0x34, 0x12, 0xA0, 0x78, 0x56, 0xE2, 0x10, 0xC2, 0x10]);
+        let mut cpu = cpu(&program);
+        run(&mut cpu, 5);
+        assert_eq!((cpu.x, cpu.y), (0x1234, 0x5678));
+        run(&mut cpu, 1);
+        assert_eq!((cpu.x, cpu.y), (0x34, 0x78));
+        // widening again does not bring the high bytes back
+        run(&mut cpu, 1);
+        assert_eq!((cpu.x, cpu.y), (0x34, 0x78));
+    }
+
+    #[test]
+    fn m_flag_keeps_the_high_byte_of_c() {
+        let mut program = NATIVE16.to_vec();
+        // LDA #$1234; SEP #$20; LDA #$FF
+        program.extend([0xA9, 0x34, 0x12, 0xE2, 0x20, 0xA9, 0xFF]);
+        let mut cpu = cpu(&program);
+        run(&mut cpu, 6);
+        assert_eq!(cpu.a, 0x12FF);
+    }
+
+    #[test]
+    fn binary_adc_sbc_8_bit() {
+        // CLC; LDA #$7F; ADC #$01; SEC; SBC #$01; CLC; LDA #$FF; ADC #$01
+        let mut cpu = cpu(&[
+            0x18, 0xA9, 0x7F, 0x69, 0x01, 0x38, 0xE9, 0x01, 0x18, 0xA9, 0xFF, 0x69, 0x01,
+        ]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.a & 0xFF, 0x80);
+        assert!(cpu.flag(FLAG_V) && cpu.flag(FLAG_N) && !cpu.flag(FLAG_C));
+        run(&mut cpu, 2);
+        assert_eq!(cpu.a & 0xFF, 0x7F);
+        assert!(cpu.flag(FLAG_V) && cpu.flag(FLAG_C));
+        run(&mut cpu, 3);
+        assert_eq!(cpu.a & 0xFF, 0x00);
+        assert!(cpu.flag(FLAG_Z) && cpu.flag(FLAG_C) && !cpu.flag(FLAG_V));
+    }
+
+    #[test]
+    fn binary_adc_sbc_16_bit() {
+        let mut program = NATIVE16.to_vec();
+        // CLC; LDA #$7FFF; ADC #$0001; CLC; LDA #$FFFF; ADC #$0001; SBC #$0001
+        program.extend([
+            0x18, 0xA9, 0xFF, 0x7F, 0x69, 0x01, 0x00, 0x18, 0xA9, 0xFF, 0xFF, 0x69, 0x01, 0x00,
+            0xE9, 0x01, 0x00,
+        ]);
+        let mut cpu = cpu(&program);
+        run(&mut cpu, 6);
+        assert_eq!(cpu.a, 0x8000);
+        assert!(cpu.flag(FLAG_V) && cpu.flag(FLAG_N) && !cpu.flag(FLAG_C));
+        run(&mut cpu, 3);
+        assert_eq!(cpu.a, 0x0000);
+        assert!(cpu.flag(FLAG_Z) && cpu.flag(FLAG_C));
+        // with C set there is no borrow
+        run(&mut cpu, 1);
+        assert_eq!(cpu.a, 0xFFFF);
+        assert!(cpu.flag(FLAG_N) && !cpu.flag(FLAG_C) && !cpu.flag(FLAG_V));
+    }
+
+    #[test]
+    fn decimal_adc_sbc_8_bit() {
+        // SED; CLC; LDA #$19; ADC #$28; ADC #$53; SEC; LDA #$10; SBC #$01; SBC #$20
+        let mut cpu = cpu(&[
+            0xF8, 0x18, 0xA9, 0x19, 0x69, 0x28, 0x69, 0x53, 0x38, 0xA9, 0x10, 0xE9, 0x01, 0xE9,
+            0x20,
+        ]);
+        run(&mut cpu, 4);
+        assert_eq!(cpu.a & 0xFF, 0x47);
+        assert!(!cpu.flag(FLAG_C));
+        run(&mut cpu, 1);
+        assert_eq!(cpu.a & 0xFF, 0x00);
+        assert!(cpu.flag(FLAG_C));
+        run(&mut cpu, 3);
+        assert_eq!(cpu.a & 0xFF, 0x09);
+        assert!(cpu.flag(FLAG_C));
+        run(&mut cpu, 1);
+        assert_eq!(cpu.a & 0xFF, 0x89);
+        assert!(!cpu.flag(FLAG_C));
+    }
+
+    #[test]
+    fn decimal_adc_sbc_16_bit() {
+        let mut program = NATIVE16.to_vec();
+        // SED; CLC; LDA #$1999; ADC #$0001; SEC; SBC #$0001; ADC #$8001
+        program.extend([
+            0xF8, 0x18, 0xA9, 0x99, 0x19, 0x69, 0x01, 0x00, 0x38, 0xE9, 0x01, 0x00, 0x69, 0x01,
+            0x80,
+        ]);
+        let mut cpu = cpu(&program);
+        run(&mut cpu, 7);
+        assert_eq!(cpu.a, 0x2000);
+        assert!(!cpu.flag(FLAG_C));
+        run(&mut cpu, 2);
+        assert_eq!(cpu.a, 0x1999);
+        assert!(cpu.flag(FLAG_C));
+        run(&mut cpu, 1);
+        assert_eq!(cpu.a, 0x0001);
+        assert!(cpu.flag(FLAG_C));
+    }
+
+    #[test]
+    fn mvn_moves_up_one_byte_per_step() {
+        let mut program = NATIVE16.to_vec();
+        // LDA #$0002; LDX #$1000; LDY #$2000; MVN $02,$01; NOP
+        program.extend([
+            0xA9, 0x02, 0x00, 0xA2, 0x00, 0x10, 0xA0, 0x00, 0x20, 0x54, 0x02, 0x01, 0xEA,
+        ]);
+        let mut cpu = cpu(&program);
+        load(&mut cpu, 0x01_1000, &[0xAA, 0xBB, 0xCC, 0xDD]);
+        run(&mut cpu, 6);
+
+        let mvn = cpu.pc;
+        run(&mut cpu, 1);
+        // repeated until the count runs out
+        assert_eq!(cpu.pc, mvn);
+        assert_eq!(cpu.dbr, 0x02);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.pc, mvn + 3);
+        assert_eq!((cpu.a, cpu.x, cpu.y), (0xFFFF, 0x1003, 0x2003));
+        let moved: Vec<_> = (0..4).map(|i| peek(&cpu, 0x02_2000 + i)).collect();
+        assert_eq!(moved, [0xAA, 0xBB, 0xCC, 0x00]);
+    }
+
+    #[test]
+    fn mvp_moves_down() {
+        let mut program = NATIVE16.to_vec();
+        // LDA #$0001; LDX #$1001; LDY #$2001; MVP $00,$01
+        program.extend([
+            0xA9, 0x01, 0x00, 0xA2, 0x01, 0x10, 0xA0, 0x01, 0x20, 0x44, 0x00, 0x01,
+        ]);
+        let mut cpu = cpu(&program);
+        load(&mut cpu, 0x01_1000, &[0xAA, 0xBB]);
+        run(&mut cpu, 8);
+        assert_eq!((cpu.a, cpu.x, cpu.y), (0xFFFF, 0x0FFF, 0x1FFF));
+        assert_eq!(cpu.dbr, 0x00);
+        assert_eq!((peek(&cpu, 0x2000), peek(&cpu, 0x2001)), (0xAA, 0xBB));
+    }
+
+    #[test]
+    fn mvn_index_registers_wrap_at_8_bits() {
+        // CLC; XCE; REP #$20; LDA #$0001; LDX #$FF; LDY #$10; MVN $00,$00
+        let mut cpu = cpu(&[
+            0x18, 0xFB, 0xC2, 0x20, 0xA9, 0x01, 0x00, 0xA2, 0xFF, 0xA0, 0x10, 0x54, 0x00, 0x00,
+        ]);
+        load(&mut cpu, 0x00FF, &[0x11]);
+        load(&mut cpu, 0x0000, &[0x22]);
+        run(&mut cpu, 8);
+        assert_eq!((cpu.x, cpu.y), (0x01, 0x12));
+        assert_eq!((peek(&cpu, 0x10), peek(&cpu, 0x11)), (0x11, 0x22));
+    }
+
+    #[test]
+    fn native_brk_pushes_the_program_bank() {
+        // CLC; XCE; JML $01:8000
+        let mut cpu = cpu(&[0x18, 0xFB, 0x5C, 0x00, 0x80, 0x01]);
+        // BRK $EA
+        load(&mut cpu, 0x01_8000, &[0x00, 0xEA]);
+        load(&mut cpu, 0xFFE6, &[0x00, 0x90]);
+        // RTI
+        load(&mut cpu, 0x9000, &[0x40]);
+        run(&mut cpu, 3);
+        let (sp, p) = (cpu.sp, cpu.p);
+
+        run(&mut cpu, 1);
+        assert_eq!(cpu.get_full_pc(), 0x00_9000);
+        assert_eq!(cpu.sp, sp - 4);
+        let frame: Vec<_> = (1..=4).map(|i| peek(&cpu, (cpu.sp + i) as u32)).collect();
+        // P, then PC past the signature byte, then the bank
+        assert_eq!(frame, [p, 0x02, 0x80, 0x01]);
+        assert!(cpu.flag(FLAG_I) && !cpu.flag(FLAG_D));
+
+        run(&mut cpu, 1);
+        assert_eq!(cpu.get_full_pc(), 0x01_8002);
+        assert_eq!((cpu.sp, cpu.p), (sp, p));
+    }
+
+    #[test]
+    fn native_cop_uses_its_own_vector() {
+        // CLC; XCE; COP $00
+        let mut cpu = cpu(&[0x18, 0xFB, 0x02, 0x00]);
+        load(&mut cpu, 0xFFE4, &[0x00, 0x90]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.get_full_pc(), 0x00_9000);
+        assert_eq!(peek(&cpu, (cpu.sp + 4) as u32), 0x00);
+    }
+
+    #[test]
+    fn emulation_brk_pushes_no_bank() {
+        // BRK $00
+        let mut cpu = cpu(&[0x00, 0x00]);
+        load(&mut cpu, 0xFFFE, &[0x00, 0x90]);
+        let sp = cpu.sp;
+        run(&mut cpu, 1);
+        assert_eq!(cpu.pc, 0x9000);
+        assert_eq!(cpu.sp, sp - 3);
+        // B is set in the pushed P
+        assert_ne!(peek(&cpu, (cpu.sp + 1) as u32) & FLAG_X, 0);
+        assert_eq!(peek(&cpu, (cpu.sp + 2) as u32), 0x02);
+    }
+
+    #[test]
+    fn native_rti_restores_the_program_bank() {
+        // CLC; XCE; RTI
+        let mut cpu = cpu(&[0x18, 0xFB, 0x40]);
+        run(&mut cpu, 2);
+        let sp = cpu.sp;
+        // P with 16-bit registers, PC $1234, bank $02
+        load(&mut cpu, (sp + 1) as u32, &[0x00, 0x34, 0x12, 0x02]);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.get_full_pc(), 0x02_1234);
+        assert_eq!(cpu.p & (FLAG_M | FLAG_X), 0);
+        assert_eq!(cpu.sp, sp + 4);
+    }
+}
@@ -0,0 +1,77 @@
+//! the EhBASIC profile as the integration test for the ACIA, with a stub ROM echoing
+//! every byte it receives back over the serial line.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, Read, Write},
+    rc::Rc,
+};
+
+use tbo2::{asm, Machine};
+
+/// both ends of a serial line, shared with the test.
+#[derive(Clone, Default)]
+struct Line {
+    to_acia: Rc<RefCell<VecDeque<u8>>>,
+    from_acia: Rc<RefCell<Vec<u8>>>,
+}
+impl Read for Line {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut input = self.to_acia.borrow_mut();
+        if input.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let n = buf.len().min(input.len());
+        for (to, from) in buf.iter_mut().zip(input.drain(..n)) {
+            *to = from;
+        }
+        Ok(n)
+    }
+}
+impl Write for Line {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.from_acia.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// a 256 byte ROM at $FF00 that sets the ACIA to 19200 8N1 without interrupts and echoes.
+fn echo_rom() -> Vec<u8> {
+    let code = asm!(
+        0xFF00,
+        "LDA #$1F; STA $8003
+         LDA #$0B; STA $8002
+         rx: LDA $8001; AND #$08; BEQ rx
+         LDX $8000
+         tx: LDA $8001; AND #$10; BEQ tx
+         STX $8000
+         JMP rx"
+    );
+    let mut rom = vec![0xEA; 0x100];
+    rom[..code.len()].copy_from_slice(&code);
+    // NMI, reset and IRQ all start the echo loop
+    rom[0xFA..].copy_from_slice(&[0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF]);
+    rom
+}
+
+#[test]
+fn acia_echoes_a_line() {
+    let line = Line::default();
+    let mut machine = Machine::ehbasic(&echo_rom(), line.clone(), 1_000_000);
+    machine.set_clock_hz(None);
+
+    let sent = b"PRINT 1+1\r";
+    line.to_acia.borrow_mut().extend(sent);
+    for _ in 0..100_000 {
+        if line.from_acia.borrow().len() == sent.len() {
+            break;
+        }
+        machine.step().unwrap();
+    }
+    assert_eq!(*line.from_acia.borrow(), sent);
+}